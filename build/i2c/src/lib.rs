@@ -50,6 +50,45 @@ struct I2cController {
     ports: BTreeMap<String, I2cPort>,
     #[serde(default)]
     target: bool,
+    /// For a `target` controller, whether it is also used as an initiator.
+    /// Such a dual-role controller is included in the generated configuration
+    /// for both dispositions; the codegen doesn't arbitrate between them.  At
+    /// runtime, the task(s) using it must sequence the role switches
+    /// themselves, and never drive the controller in both roles at once.
+    #[serde(default)]
+    initiator: bool,
+    /// Explicit bus timing; if absent, the driver's defaults are used
+    timing: Option<I2cTiming>,
+}
+
+impl I2cController {
+    /// Returns true if this controller belongs in the configuration generated
+    /// for `disposition`.  Controllers are initiators unless they are marked
+    /// as a `target`, in which case they are only initiators if they are
+    /// also marked as an `initiator`.
+    fn has_disposition(&self, disposition: Disposition) -> bool {
+        match disposition {
+            Disposition::Target => self.target,
+            _ => !self.target || self.initiator,
+        }
+    }
+}
+
+//
+// The fields of the controller's TIMINGR register, which determine the bus
+// speed and setup/hold times; see the "I2C timings" section of the reference
+// manual for how to derive these for a given peripheral clock.  Note that
+// this is per-controller (rather than per-port), as all ports on a controller
+// share its TIMINGR.
+//
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct I2cTiming {
+    presc: u8,
+    sclh: u8,
+    scll: u8,
+    scldel: u8,
+    sdadel: u8,
 }
 
 //
@@ -108,6 +147,11 @@ struct I2cDevice {
     /// device is removable
     #[serde(default)]
     removable: bool,
+
+    /// additional names for this device, each of which gets an accessor
+    /// (but no sensors of its own)
+    #[serde(default)]
+    aliases: Vec<String>,
 }
 
 impl I2cDevice {
@@ -158,6 +202,10 @@ struct I2cGpio {
     pin: u8,
 }
 
+/// Mux drivers, each of which is a module in `drv-stm32xx-i2c`
+const MUX_DRIVERS: &[&str] =
+    &["gpio_mux", "ltc4306", "max7358", "pca9548", "tca9544"];
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct I2cMux {
@@ -165,6 +213,92 @@ struct I2cMux {
     address: u8,
     #[serde(alias = "enable")]
     nreset: Option<I2cGpio>,
+    /// Wiring of a GPIO expander used as a mux; required for (and only
+    /// allowed with) the `gpio_mux` driver
+    gpio_mux: Option<I2cGpioMux>,
+    /// Read back the mux's control register after enabling a segment, for
+    /// the drivers that support it (see `I2cMuxDriver::enable_segment`)
+    #[serde(default)]
+    verify_segment: bool,
+}
+
+//
+// How the pins of a GPIO expander select segments; see the `gpio_mux` module
+// in `drv-stm32xx-i2c`.  `segments` lists the output levels selecting S1, S2,
+// and so on.
+//
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct I2cGpioMux {
+    output_register: u8,
+    config_register: Option<u8>,
+    pins: u8,
+    segments: Vec<u8>,
+    deselect: u8,
+}
+
+impl I2cMux {
+    /// Returns the expression for this mux's driver in generated code.
+    fn driver_expr(&self) -> Result<String> {
+        if !MUX_DRIVERS.contains(&self.driver.as_str()) {
+            bail!(
+                "unknown driver \"{}\" (expected one of {:?})",
+                self.driver,
+                MUX_DRIVERS
+            );
+        }
+
+        let g = match (self.driver.as_str(), &self.gpio_mux) {
+            ("gpio_mux", Some(g)) => g,
+            ("gpio_mux", None) => bail!("gpio_mux driver requires gpio-mux"),
+            (_, Some(_)) => {
+                bail!("gpio-mux is only valid with the gpio_mux driver")
+            }
+            (driver, None) => {
+                return Ok(format!(
+                    "&drv_stm32xx_i2c::{driver}::{}{}",
+                    driver[..1].to_uppercase(),
+                    &driver[1..]
+                ));
+            }
+        };
+
+        if g.segments.is_empty() || g.segments.len() > 8 {
+            bail!("gpio-mux must have between 1 and 8 segments");
+        }
+
+        let mut seen = HashSet::new();
+        for &levels in [&g.deselect].into_iter().chain(&g.segments) {
+            if levels & !g.pins != 0 {
+                bail!(
+                    "gpio-mux levels {levels:#x} drive pins outside {:#x}",
+                    g.pins
+                );
+            }
+            if !seen.insert(levels) {
+                bail!("gpio-mux levels {levels:#x} are used more than once");
+            }
+        }
+
+        let segments = (0..8)
+            .map(|i| match g.segments.get(i) {
+                Some(levels) => format!("Some({levels:#x})"),
+                None => "None".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Ok(format!(
+            "&drv_stm32xx_i2c::gpio_mux::GpioMux {{
+                    output_register: {:#x},
+                    config_register: {:?},
+                    pins: {:#x},
+                    segments: [{segments}],
+                    deselect: {:#x},
+                }}",
+            g.output_register, g.config_register, g.pins, g.deselect,
+        ))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialOrd, PartialEq, Eq, Ord)]
@@ -535,7 +669,15 @@ impl ConfigGenerator {
                 ports.insert((c.controller, p.clone()), index);
             }
 
-            if c.target != (disposition == Disposition::Target) {
+            if c.initiator && !c.target {
+                panic!(
+                    "I2C{} is marked as an initiator but not a target; \
+                    controllers are initiators by default",
+                    c.controller
+                );
+            }
+
+            if !c.has_disposition(disposition) {
                 continue;
             }
 
@@ -647,7 +789,32 @@ impl ConfigGenerator {
         ["##
         )?;
 
+        let mut nentries = 0;
+
         for c in &self.controllers {
+            nentries += 1;
+
+            let timing = match c.timing {
+                Some(t) => {
+                    // PRESC, SCLDEL, and SDADEL are 4-bit fields
+                    if t.presc > 15 || t.scldel > 15 || t.sdadel > 15 {
+                        bail!(
+                            "I2C{} timing {t:?}: presc, scldel, and sdadel \
+                            must be less than 16",
+                            c.controller
+                        );
+                    }
+
+                    format!(
+                        "Some(drv_stm32xx_i2c::I2cTiming {{
+                    presc: {}, sclh: {}, scll: {}, scldel: {}, sdadel: {},
+                }})",
+                        t.presc, t.sclh, t.scll, t.scldel, t.sdadel
+                    )
+                }
+                None => "None".to_string(),
+            };
+
             write!(
                 &mut s,
                 r##"
@@ -656,6 +823,7 @@ impl ConfigGenerator {
                 peripheral: Peripheral::I2c{controller},
                 notification: crate::notifications::I2C{controller}_IRQ_MASK,
                 registers: unsafe {{ &*device::I2C{controller}::ptr() }},
+                timing: {timing},
             }},"##,
                 controller = c.controller,
             )?;
@@ -665,7 +833,10 @@ impl ConfigGenerator {
             &mut s,
             r##"
         ]
-    }}"##
+    }}
+
+    // Generator self-check: one entry was emitted per controller.
+    const _: () = assert!(NCONTROLLERS == {nentries});"##
         )?;
 
         Ok(())
@@ -815,11 +986,12 @@ impl ConfigGenerator {
                         })
                         .unwrap_or_else(|| "None".to_string());
 
-                    let driver_struct = format!(
-                        "{}{}",
-                        mux.driver[..1].to_uppercase(),
-                        &mux.driver[1..]
-                    );
+                    let driver = mux.driver_expr().with_context(|| {
+                        format!(
+                            "mux at address {:#x} on I2C{}",
+                            mux.address, c.controller
+                        )
+                    })?;
 
                     write!(
                         &mut s,
@@ -828,16 +1000,17 @@ impl ConfigGenerator {
                 controller: Controller::I2C{controller},
                 port: PortIndex({i2c_port}),
                 id: Mux::M{mindex},
-                driver: &drv_stm32xx_i2c::{driver}::{driver_struct},
+                driver: {driver},
                 nreset: {nreset},
                 address: {address:#x},
+                verify_segment: {verify_segment},
             }},"##,
                         controller = c.controller,
                         i2c_port = index,
                         mindex = mindex + 1,
-                        driver = mux.driver,
-                        driver_struct = driver_struct,
+                        driver = driver,
                         address = mux.address,
+                        verify_segment = mux.verify_segment,
                     )?;
                 }
             }
@@ -850,6 +1023,53 @@ impl ConfigGenerator {
     }}"##
         )?;
 
+        self.generate_mux_segments()
+    }
+
+    ///
+    /// Emits a `(Mux, Segment)` constant for each named (or reference
+    /// designated) device behind a mux, so that code addressing it needn't
+    /// hard-code mux and segment literals.  Constants are named after the
+    /// device's accessor in `devices` (e.g. `TMP117_INLET`).
+    ///
+    fn generate_mux_segments(&mut self) -> Result<()> {
+        let mut all = BTreeMap::new();
+
+        for d in &self.devices {
+            let Some((mux, segment)) = mux_segment(d) else {
+                continue;
+            };
+
+            for name in [&d.name, &d.refdes].into_iter().flatten() {
+                let constant = format!("{}_{}", d.device, name).to_uppercase();
+
+                if all.insert(constant, (mux, segment)).is_some() {
+                    panic!("duplicate name {} for device {}", name, d.device);
+                }
+            }
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+    pub mod mux_segments {{
+        #[allow(unused_imports)]
+        use drv_i2c_api::{{Mux, Segment}};
+"##
+        )?;
+
+        for (constant, (mux, segment)) in &all {
+            write!(
+                &mut self.output,
+                r##"
+        #[allow(dead_code)]
+        pub const {constant}: (Mux, Segment) = (Mux::M{mux}, Segment::S{segment});
+"##
+            )?;
+        }
+
+        writeln!(&mut self.output, "    }}")?;
+
         Ok(())
     }
 
@@ -898,20 +1118,14 @@ impl ConfigGenerator {
     fn generate_device(&self, d: &I2cDevice, indent: usize) -> String {
         let (controller, port) = self.lookup_controller_port(d);
 
-        let segment = match (d.mux, d.segment) {
-            (Some(mux), Some(segment)) => {
+        let segment = match mux_segment(d) {
+            Some((mux, segment)) => {
                 format!(
                     "Some((drv_i2c_api::Mux::M{}, drv_i2c_api::Segment::S{}))",
                     mux, segment
                 )
             }
-            (None, None) => "None".to_owned(),
-            (Some(_), None) => {
-                panic!("device {} specifies a mux but no segment", d.device)
-            }
-            (None, Some(_)) => {
-                panic!("device {} specifies a segment but no mux", d.device)
-            }
+            None => "None".to_owned(),
         };
 
         let indent = format!("{:indent$}", "", indent = indent);
@@ -945,6 +1159,14 @@ impl ConfigGenerator {
 
         let mut by_port = MultiMap::new();
         let mut by_controller = MultiMap::new();
+        let mut by_controller_port = MultiMap::new();
+
+        check_addresses(
+            self.devices
+                .iter()
+                .map(|d| (self.lookup_controller_port(d), d)),
+        );
+        check_name_lengths(&self.devices);
 
         for (index, d) in self.devices.iter().enumerate() {
             by_device.insert(&d.device, d);
@@ -953,6 +1175,7 @@ impl ConfigGenerator {
 
             by_port.insert(port, index);
             by_controller.insert(controller, index);
+            by_controller_port.insert((controller, port), index);
 
             if let Some(bus) = &d.bus {
                 by_bus.insert((&d.device, bus), d);
@@ -978,6 +1201,8 @@ impl ConfigGenerator {
             }
         }
 
+        let by_alias = check_aliases(&self.devices);
+
         write!(
             &mut self.output,
             r##"
@@ -1037,6 +1262,54 @@ impl ConfigGenerator {
 "##
         )?;
 
+        //
+        // Emit the number of devices on each controller (`NDEVICES_I2C{c}`)
+        // and on each of its ports (`NDEVICES_I2C{c}_PORT_{p}`), allowing
+        // consumers to size arrays from the configuration.  Port indices are
+        // only unique within a controller, so the latter are keyed by both.
+        // Note that these are only emitted for controllers and ports that
+        // have devices.
+        //
+        let mut all: Vec<_> = by_controller.iter_all().collect();
+        all.sort();
+
+        for (controller, indices) in all {
+            write!(
+                &mut self.output,
+                r##"
+        #[allow(dead_code)]
+        pub const NDEVICES_I2C{controller}: usize = {};
+"##,
+                indices.len()
+            )?;
+        }
+
+        let mut all: Vec<_> = by_controller_port.iter_all().collect();
+        all.sort();
+
+        for ((controller, port), indices) in all {
+            write!(
+                &mut self.output,
+                r##"
+        #[allow(dead_code)]
+        pub const NDEVICES_I2C{controller}_PORT_{port}: usize = {};
+"##,
+                indices.len()
+            )?;
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+        /// Returns the number of I2C devices in the configuration
+        #[allow(dead_code)]
+        pub const fn device_count() -> usize {{
+            {}
+        }}
+"##,
+            self.devices.len()
+        )?;
+
         let mut all: Vec<_> = by_device.iter_all().collect();
         all.sort();
 
@@ -1091,7 +1364,12 @@ impl ConfigGenerator {
             )?;
         }
 
-        let mut all: Vec<_> = by_name.iter().collect();
+        //
+        // Aliases get accessors just like names do -- but only accessors:
+        // they are otherwise invisible, and in particular, don't have
+        // sensors of their own.
+        //
+        let mut all: Vec<_> = by_name.iter().chain(by_alias.iter()).collect();
         all.sort();
         for ((device, name), d) in &all {
             write!(
@@ -1171,19 +1449,41 @@ impl ConfigGenerator {
             .parent()
             .context("failed to get i2c device path")?;
 
-        let mut drivers = std::collections::HashSet::new();
+        //
+        // Which modules are drivers (and what their `Validate` types are
+        // called) comes from the explicit lists below; we only look at the
+        // source directory to make sure those lists agree with it.
+        //
+        let mut modules = HashSet::new();
 
         println!("cargo:rerun-if-changed={}", dir.join("src").display());
 
         for entry in std::fs::read_dir(dir.join("src"))? {
-            if let Some(f) = entry?.path().file_name() {
-                if let Some(name) = f.to_str().unwrap().strip_suffix(".rs") {
-                    drivers.insert(name.to_string());
-                }
+            let path = entry?.path();
+
+            if let Some(name) = path
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|f| f.strip_suffix(".rs"))
+            {
+                modules.insert(name.to_string());
             }
         }
 
-        drivers.remove("lib");
+        modules.remove("lib");
+
+        for module in VALIDATE_DRIVERS
+            .iter()
+            .map(|(module, _)| module)
+            .chain(UNVALIDATED_DRIVERS)
+        {
+            if !modules.contains(*module) {
+                bail!(
+                    "{module} is listed as an I2C driver in build/i2c, but \
+                     there is no {module}.rs in drv-i2c-devices"
+                );
+            }
+        }
 
         write!(
             &mut self.output,
@@ -1215,8 +1515,7 @@ impl ConfigGenerator {
         // returned by `device_descriptions()` below: if we change the ordering
         // here, it must be updated there as well.
         for (index, device) in self.devices.iter().enumerate() {
-            if drivers.contains(&device.device) {
-                let driver = device.device.to_case(Case::UpperCamel);
+            if let Some(driver) = validation_driver(&device.device, &modules)? {
                 let out = self.generate_device(device, 24);
 
                 write!(
@@ -1471,11 +1770,16 @@ impl ConfigGenerator {
         name: &str,
         sensors: &[DeviceSensor],
     ) -> Result<()> {
+        let device = d.device.to_uppercase();
+
+        // The alias names this instance's (possibly flavored) struct type, so
+        // that code can be written against it without knowing the flavor.
         write!(
             &mut self.output,
-            "        #[allow(dead_code)]
-        pub const {}_{label}_SENSORS: Sensors_{name} = ",
-            d.device.to_uppercase(),
+            "        #[allow(dead_code, non_camel_case_types)]
+        pub type {device}_{label}_Sensors = Sensors_{name};
+        #[allow(dead_code)]
+        pub const {device}_{label}_SENSORS: Sensors_{name} = ",
         )?;
 
         let mut sensors_by_kind: BTreeMap<Sensor, Vec<usize>> = BTreeMap::new();
@@ -1521,6 +1825,91 @@ impl ConfigGenerator {
         Ok(())
     }
 
+    ///
+    /// Emits a table of `SensorDescription`s indexed by sensor ID, along
+    /// with `sensor_description()` to look up the device that produced a
+    /// given sensor.
+    ///
+    fn emit_sensor_descriptions(
+        &mut self,
+        s: &I2cSensorsDescription,
+    ) -> Result<()> {
+        let mut descriptions = vec![None; s.total_sensors];
+
+        for (d, sensors) in self.devices.iter().zip(s.device_sensors.iter()) {
+            for sensor in sensors {
+                descriptions[sensor.id] = Some((d, sensor.kind));
+            }
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+        #[allow(dead_code)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum SensorKind {{
+            Temperature,
+            Power,
+            Current,
+            Voltage,
+            InputCurrent,
+            InputVoltage,
+            Speed,
+        }}
+
+        #[allow(dead_code)]
+        #[derive(Copy, Clone, Debug)]
+        pub struct SensorDescription {{
+            pub device: &'static str,
+            pub name: Option<&'static str>,
+            pub refdes: Option<&'static str>,
+            pub kind: SensorKind,
+        }}
+
+        #[allow(dead_code)]
+        pub static SENSOR_DESCRIPTIONS: [SensorDescription; NUM_SENSORS] = ["##
+        )?;
+
+        let opt = |s: &Option<String>| match s {
+            Some(s) => format!("Some({s:?})"),
+            None => "None".to_string(),
+        };
+
+        for (id, description) in descriptions.iter().enumerate() {
+            let Some((d, kind)) = description else {
+                panic!("sensor {id} has no device");
+            };
+
+            write!(
+                &mut self.output,
+                r##"
+            SensorDescription {{
+                device: {:?},
+                name: {},
+                refdes: {},
+                kind: SensorKind::{:?},
+            }},"##,
+                d.device,
+                opt(&d.name),
+                opt(&d.refdes),
+                kind,
+            )?;
+        }
+
+        writeln!(
+            &mut self.output,
+            r##"
+        ];
+
+        #[allow(dead_code)]
+        pub fn sensor_description(id: SensorId) -> Option<SensorDescription> {{
+            SENSOR_DESCRIPTIONS.get(usize::from(id)).copied()
+        }}"##
+        )?;
+
+        Ok(())
+    }
+
     fn sensors_description(&self) -> I2cSensorsDescription {
         I2cSensorsDescription::new(&self.devices)
     }
@@ -1614,10 +2003,59 @@ impl ConfigGenerator {
             self.emit_sensor(&k.device, &label, ids)?;
         }
 
+        self.emit_sensor_descriptions(&s)?;
+        self.emit_sensor_checks(&s)?;
+
         writeln!(&mut self.output, "\n    }}")?;
         Ok(())
     }
 
+    ///
+    /// Emits compile-time assertions over the sensors module, turning
+    /// generator bugs into build failures: `NUM_SENSORS` must equal the sum of
+    /// the per-device sensor counts, and every `SensorId` we've emitted must be
+    /// less than it.
+    ///
+    fn emit_sensor_checks(&mut self, s: &I2cSensorsDescription) -> Result<()> {
+        let max_id = s.device_sensors.iter().flatten().map(|d| d.id).max();
+
+        let mut keys: Vec<_> = s.by_device.keys().collect();
+        keys.sort();
+
+        let counts = keys
+            .iter()
+            .map(|k| {
+                format!("NUM_{}_{}_SENSORS", k.device.to_uppercase(), k.kind)
+            })
+            .collect::<Vec<_>>();
+
+        let sum = if counts.is_empty() {
+            "0".to_string()
+        } else {
+            counts.join("\n                + ")
+        };
+
+        write!(
+            &mut self.output,
+            r##"
+        // Generator self-checks
+        const _: () = assert!(
+            NUM_SENSORS
+                == {sum}
+        );"##
+        )?;
+
+        if let Some(max_id) = max_id {
+            write!(
+                &mut self.output,
+                r##"
+        const _: () = assert!({max_id} < NUM_SENSORS);"##
+            )?;
+        }
+
+        Ok(())
+    }
+
     pub fn generate_ports(&mut self) -> Result<()> {
         writeln!(
             &mut self.output,
@@ -1642,6 +2080,222 @@ impl ConfigGenerator {
         writeln!(&mut self.output, "    }}")?;
         Ok(())
     }
+
+    ///
+    /// Generates a `topology` module with a `topology()` function, which
+    /// describes each controller, its ports and, for each port, its muxes and
+    /// devices.  This is static data for a task to report the bus map from,
+    /// without having to cross-reference the app.toml.  Controllers are
+    /// sorted by number and ports by name; muxes are sorted by address, and
+    /// devices by mux, segment and address.
+    ///
+    /// As this costs flash, it's only generated for tasks that enable the
+    /// `i2c-topology` feature.
+    ///
+    pub fn generate_topology(&mut self) -> Result<()> {
+        let mut ports: MultiMap<(u8, usize), &I2cDevice> = MultiMap::new();
+
+        for d in &self.devices {
+            ports.insert(self.lookup_controller_port(d), d);
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+    pub mod topology {{
+        /// A device on an I2C port, as configured
+        #[allow(dead_code)]
+        #[derive(Copy, Clone, Debug)]
+        pub struct DeviceInfo {{
+            pub device: &'static str,
+            pub name: Option<&'static str>,
+            pub refdes: Option<&'static str>,
+            pub address: u8,
+            pub mux: Option<u8>,
+            pub segment: Option<u8>,
+        }}
+
+        /// A mux on an I2C port
+        #[allow(dead_code)]
+        #[derive(Copy, Clone, Debug)]
+        pub struct MuxInfo {{
+            pub driver: &'static str,
+            pub address: u8,
+        }}
+
+        /// An I2C port, with the name of its bus (if any)
+        #[allow(dead_code)]
+        #[derive(Copy, Clone, Debug)]
+        pub struct PortInfo {{
+            pub port: &'static str,
+            pub index: u8,
+            pub bus: Option<&'static str>,
+            pub muxes: &'static [MuxInfo],
+            pub devices: &'static [DeviceInfo],
+        }}
+
+        /// An I2C controller and its ports
+        #[allow(dead_code)]
+        #[derive(Copy, Clone, Debug)]
+        pub struct ControllerInfo {{
+            pub controller: u8,
+            pub ports: &'static [PortInfo],
+        }}
+
+        #[allow(dead_code)]
+        pub fn topology() -> &'static [ControllerInfo] {{
+            &["##
+        )?;
+
+        let mut controllers: Vec<_> = self.controllers.iter().collect();
+        controllers.sort_by_key(|c| c.controller);
+
+        for c in controllers {
+            write!(
+                &mut self.output,
+                r##"
+                ControllerInfo {{
+                    controller: {},
+                    ports: &["##,
+                c.controller
+            )?;
+
+            for (index, (name, port)) in c.ports.iter().enumerate() {
+                let mut muxes: Vec<_> = port.muxes.iter().collect();
+                muxes.sort_by_key(|m| m.address);
+
+                let mut devices = ports
+                    .get_vec(&(c.controller, index))
+                    .cloned()
+                    .unwrap_or_default();
+                devices.sort_by_key(|d| (d.mux, d.segment, d.address));
+
+                write!(
+                    &mut self.output,
+                    r##"
+                        PortInfo {{
+                            port: {name:?},
+                            index: {index},
+                            bus: {:?},
+                            muxes: &["##,
+                    port.name
+                )?;
+
+                for m in muxes {
+                    write!(
+                        &mut self.output,
+                        r##"
+                                MuxInfo {{
+                                    driver: {:?},
+                                    address: {:#x},
+                                }},"##,
+                        m.driver, m.address
+                    )?;
+                }
+
+                write!(
+                    &mut self.output,
+                    r##"
+                            ],
+                            devices: &["##
+                )?;
+
+                for d in devices {
+                    write!(
+                        &mut self.output,
+                        r##"
+                                DeviceInfo {{
+                                    device: {:?},
+                                    name: {:?},
+                                    refdes: {:?},
+                                    address: {:#x},
+                                    mux: {:?},
+                                    segment: {:?},
+                                }},"##,
+                        d.device, d.name, d.refdes, d.address, d.mux, d.segment
+                    )?;
+                }
+
+                write!(
+                    &mut self.output,
+                    r##"
+                            ],
+                        }},"##
+                )?;
+            }
+
+            write!(
+                &mut self.output,
+                r##"
+                    ],
+                }},"##
+            )?;
+        }
+
+        writeln!(
+            &mut self.output,
+            r##"
+            ]
+        }}
+    }}"##
+        )?;
+
+        Ok(())
+    }
+
+    pub fn generate_buses(&mut self) -> Result<()> {
+        //
+        // Sort by name, so that our output doesn't depend on the iteration
+        // order of our HashMap.
+        //
+        let mut buses: Vec<_> = self.buses.iter().collect();
+        buses.sort();
+
+        write!(
+            &mut self.output,
+            r##"
+    pub mod buses {{
+        use drv_i2c_api::{{Controller, PortIndex}};
+
+        /// The names of all I2C buses, in sorted order
+        #[allow(dead_code)]
+        pub const BUS_NAMES: &[&str] = &["##
+        )?;
+
+        for (name, _) in &buses {
+            write!(&mut self.output, "\n            {name:?},")?;
+        }
+
+        write!(
+            &mut self.output,
+            r##"
+        ];
+
+        /// Returns the controller and port of the named I2C bus, if any
+        #[allow(dead_code)]
+        pub fn bus_by_name(name: &str) -> Option<(Controller, PortIndex)> {{
+            match name {{"##
+        )?;
+
+        for (name, (controller, port)) in &buses {
+            write!(
+                &mut self.output,
+                r##"
+                {name:?} => Some((Controller::I2C{controller}, PortIndex({port}))),"##
+            )?;
+        }
+
+        writeln!(
+            &mut self.output,
+            r##"
+                _ => None,
+            }}
+        }}
+    }}"##
+        )?;
+
+        Ok(())
+    }
 }
 
 pub fn codegen(disposition: Disposition) -> Result<()> {
@@ -1662,10 +2316,14 @@ pub fn codegen(disposition: Disposition) -> Result<()> {
             if n != 1 {
                 //
                 // If we have the disposition of a target, we expect exactly one
-                // controller to be configured as a target; if none have been
-                // specified, the task should be deconfigured.
+                // controller to be configured as a target (whether or not it
+                // is also an initiator); if none have been specified, the
+                // task should be deconfigured.
                 //
-                panic!("found {} I2C controller(s); expected exactly one", n);
+                panic!(
+                    "found {} I2C target controller(s); expected exactly one",
+                    n
+                );
             }
 
             g.generate_controllers()?;
@@ -1683,6 +2341,11 @@ pub fn codegen(disposition: Disposition) -> Result<()> {
         Disposition::Devices => {
             g.generate_devices()?;
             g.generate_ports()?;
+            g.generate_buses()?;
+
+            if build_util::has_feature("i2c-topology") {
+                g.generate_topology()?;
+            }
         }
 
         Disposition::Sensors => {
@@ -1732,6 +2395,183 @@ pub fn device_descriptions() -> impl Iterator<Item = I2cDeviceDescription> {
     )
 }
 
+///
+/// Returns the mux and segment of a device, if it's behind a mux, panicking if
+/// only one of the two has been specified.
+///
+fn mux_segment(d: &I2cDevice) -> Option<(u8, u8)> {
+    match (d.mux, d.segment) {
+        (Some(mux), Some(segment)) => Some((mux, segment)),
+        (None, None) => None,
+        (Some(_), None) => {
+            panic!("device {} specifies a mux but no segment", d.device)
+        }
+        (None, Some(_)) => {
+            panic!("device {} specifies a segment but no mux", d.device)
+        }
+    }
+}
+
+///
+/// Checks that no two devices share an address on the same bus -- that is,
+/// the same controller, port, and (if any) mux segment -- panicking with the
+/// identities of both devices if they do.  Each device is paired with its
+/// controller and port index.
+///
+fn check_addresses<'a>(
+    devices: impl IntoIterator<Item = ((u8, usize), &'a I2cDevice)>,
+) {
+    let mut by_address = HashMap::new();
+
+    let ident = |d: &I2cDevice| match (&d.name, &d.refdes) {
+        (Some(name), _) => format!("{} ({})", d.device, name),
+        (None, Some(refdes)) => format!("{} ({})", d.device, refdes),
+        (None, None) => d.device.clone(),
+    };
+
+    for ((controller, port), d) in devices {
+        let key = (controller, port, d.mux, d.segment, d.address);
+
+        if let Some(other) = by_address.insert(key, d) {
+            let segment = match (d.mux, d.segment) {
+                (Some(mux), Some(segment)) => {
+                    format!(", mux {mux}, segment {segment}")
+                }
+                _ => String::new(),
+            };
+
+            panic!(
+                "devices {} and {} are both at address {:#x} on \
+                controller {controller}, port index {port}{segment}",
+                ident(other),
+                ident(d),
+                d.address,
+            );
+        }
+    }
+}
+
+///
+/// Checks that no device alias duplicates a name, refdes, or other alias for
+/// the same kind of device (which would result in duplicate accessors),
+/// returning the devices by alias.
+///
+fn check_aliases(
+    devices: &[I2cDevice],
+) -> HashMap<(&String, &String), &I2cDevice> {
+    let names: HashSet<_> = devices
+        .iter()
+        .flat_map(|d| {
+            d.name.iter().chain(d.refdes.iter()).map(|n| (&d.device, n))
+        })
+        .collect();
+
+    let mut by_alias = HashMap::new();
+
+    for d in devices {
+        for alias in &d.aliases {
+            let key = (&d.device, alias);
+
+            if names.contains(&key) || by_alias.insert(key, d).is_some() {
+                panic!(
+                    "alias {} for device {} duplicates an existing name, \
+                    refdes, or alias",
+                    alias, d.device
+                );
+            }
+        }
+    }
+
+    by_alias
+}
+
+///
+/// Checks that every device name and refdes fits in the name reported to the
+/// host in inventory data ([`drv_i2c_types::MAX_DEVICE_NAME_LEN`] bytes),
+/// which would otherwise be silently truncated.
+///
+fn check_name_lengths<'a>(devices: impl IntoIterator<Item = &'a I2cDevice>) {
+    let max = drv_i2c_types::MAX_DEVICE_NAME_LEN;
+
+    for d in devices {
+        for (what, s) in [("name", &d.name), ("refdes", &d.refdes)] {
+            if let Some(s) = s {
+                if s.len() > max {
+                    panic!(
+                        "{what} \"{s}\" for device {} is {} bytes long, \
+                        but the inventory name is limited to {max} bytes",
+                        d.device,
+                        s.len(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+///
+/// Driver modules in `drv-i2c-devices` that implement `Validate`, and the
+/// type that implements it in each.
+///
+const VALIDATE_DRIVERS: &[(&str, &str)] = &[
+    ("adm1272", "Adm1272"),
+    ("at24csw080", "At24Csw080"),
+    ("bmr491", "Bmr491"),
+    ("emc2305", "Emc2305"),
+    ("isl68224", "Isl68224"),
+    ("ltc4282", "Ltc4282"),
+    ("m24c02", "M24C02"),
+    ("m2_hp_only", "M2HpOnly"),
+    ("max31790", "Max31790"),
+    ("max5970", "Max5970"),
+    ("max6634", "Max6634"),
+    ("mwocp68", "Mwocp68"),
+    ("nvme_bmc", "NvmeBmc"),
+    ("pca9538", "Pca9538"),
+    ("pca9956b", "Pca9956B"),
+    ("pct2075", "Pct2075"),
+    ("raa229618", "Raa229618"),
+    ("sbrmi", "Sbrmi"),
+    ("sbtsi", "Sbtsi"),
+    ("tmp117", "Tmp117"),
+    ("tmp451", "Tmp451"),
+    ("tps546b24a", "Tps546B24A"),
+    ("tse2004av", "Tse2004Av"),
+];
+
+///
+/// Driver modules in `drv-i2c-devices` that don't implement `Validate`;
+/// devices that use them are validated with a raw read.
+///
+const UNVALIDATED_DRIVERS: &[&str] = &["adt7420", "ds2482", "mcp9808"];
+
+///
+/// Returns the type implementing `Validate` for `device`, or `None` if the
+/// device should be validated with a raw read.  It's an error for `device` to
+/// name a module in `drv-i2c-devices` that is in neither of the lists above,
+/// rather than quietly leaving it out of validation.
+///
+fn validation_driver(
+    device: &str,
+    modules: &HashSet<String>,
+) -> Result<Option<&'static str>> {
+    if let Some((_, driver)) = VALIDATE_DRIVERS
+        .iter()
+        .find(|(module, _)| *module == device)
+    {
+        Ok(Some(*driver))
+    } else if UNVALIDATED_DRIVERS.contains(&device) || !modules.contains(device)
+    {
+        Ok(None)
+    } else {
+        bail!(
+            "I2C device {device} has a driver in drv-i2c-devices, but it is \
+             not listed in VALIDATE_DRIVERS or UNVALIDATED_DRIVERS in \
+             build/i2c; add it to one or the other"
+        );
+    }
+}
+
 fn match_arms<'a, C>(
     mut out: impl Write,
     source: impl IntoIterator<Item = (&'a C, &'a Vec<usize>)>,
@@ -1761,3 +2601,474 @@ where
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(
+        device: &str,
+        address: u8,
+        mux: Option<u8>,
+        segment: Option<u8>,
+    ) -> I2cDevice {
+        I2cDevice {
+            device: device.to_string(),
+            name: None,
+            controller: Some(2),
+            bus: None,
+            port: None,
+            flavor: None,
+            address,
+            mux,
+            segment,
+            description: String::new(),
+            refdes: None,
+            power: None,
+            sensors: None,
+            removable: false,
+            aliases: vec![],
+        }
+    }
+
+    fn generator(
+        disposition: Disposition,
+        devices: Vec<I2cDevice>,
+    ) -> ConfigGenerator {
+        ConfigGenerator {
+            output: String::new(),
+            disposition,
+            controllers: vec![],
+            devices,
+            buses: HashMap::new(),
+            ports: IndexMap::new(),
+            singletons: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn validation_drivers() {
+        let modules: HashSet<_> = ["tmp117", "adt7420", "common"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        assert_eq!(
+            validation_driver("tmp117", &modules).unwrap(),
+            Some("Tmp117")
+        );
+        assert_eq!(validation_driver("adt7420", &modules).unwrap(), None);
+
+        // No driver at all is fine: that's a raw read.
+        assert_eq!(validation_driver("lm75", &modules).unwrap(), None);
+
+        // A module that's in neither list is not.
+        let err = validation_driver("common", &modules).unwrap_err();
+        assert!(err.to_string().contains("I2C device common"));
+
+        for (module, _) in VALIDATE_DRIVERS {
+            assert!(!UNVALIDATED_DRIVERS.contains(module), "{module}");
+        }
+    }
+
+    #[test]
+    fn distinct_addresses() {
+        let devices = [
+            device("tmp117", 0x48, None, None),
+            device("tmp117", 0x49, None, None),
+            // Same address as the first, but on a different port...
+            device("tmp117", 0x48, None, None),
+            // ...and on a mux segment off of the first port
+            device("tmp117", 0x48, Some(1), Some(1)),
+            device("tmp117", 0x48, Some(1), Some(2)),
+        ];
+
+        let ports = [0, 0, 1, 0, 0];
+
+        check_addresses(ports.iter().map(|&p| (2, p)).zip(devices.iter()));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "devices tmp117 and tmp451 are both at address 0x48"
+    )]
+    fn duplicate_address() {
+        let devices = [
+            device("tmp117", 0x48, None, None),
+            device("tmp451", 0x48, None, None),
+        ];
+
+        check_addresses(devices.iter().map(|d| ((2, 0), d)));
+    }
+
+    #[test]
+    #[should_panic(expected = "mux 1, segment 3")]
+    fn duplicate_segment_address() {
+        let devices = [
+            device("tmp117", 0x48, Some(1), Some(3)),
+            device("tmp451", 0x48, Some(1), Some(3)),
+        ];
+
+        check_addresses(devices.iter().map(|d| ((2, 0), d)));
+    }
+
+    #[test]
+    fn mux_drivers() {
+        let mux = |driver: &str, gpio_mux| I2cMux {
+            driver: driver.to_string(),
+            address: 0x70,
+            nreset: None,
+            gpio_mux,
+            verify_segment: false,
+        };
+        let wiring = |segments: &[u8], deselect| I2cGpioMux {
+            output_register: 1,
+            config_register: Some(3),
+            pins: 0x03,
+            segments: segments.to_vec(),
+            deselect,
+        };
+
+        assert_eq!(
+            mux("pca9548", None).driver_expr().unwrap(),
+            "&drv_stm32xx_i2c::pca9548::Pca9548"
+        );
+        assert!(mux("pca9999", None).driver_expr().is_err());
+        assert!(mux("gpio_mux", None).driver_expr().is_err());
+        assert!(mux("pca9548", Some(wiring(&[1], 0))).driver_expr().is_err());
+
+        let expr = mux("gpio_mux", Some(wiring(&[1, 2, 3], 0)))
+            .driver_expr()
+            .unwrap();
+        assert!(expr.contains("config_register: Some(3),"));
+        assert!(expr.contains(
+            "segments: [Some(0x1), Some(0x2), Some(0x3), \
+            None, None, None, None, None],"
+        ));
+
+        // Levels must stay within `pins`, and be distinct from each other and
+        // from `deselect`.
+        for (segments, deselect) in [
+            (&[1, 4][..], 0),
+            (&[1, 2], 4),
+            (&[1, 1], 0),
+            (&[1, 0], 0),
+            (&[], 0),
+        ] {
+            assert!(
+                mux("gpio_mux", Some(wiring(segments, deselect)))
+                    .driver_expr()
+                    .is_err(),
+                "{segments:?}, {deselect}"
+            );
+        }
+    }
+
+    #[test]
+    fn controller_dispositions() {
+        let controller = |target, initiator| I2cController {
+            controller: 2,
+            ports: BTreeMap::new(),
+            target,
+            initiator,
+            timing: None,
+        };
+
+        let initiator = controller(false, false);
+        assert!(initiator.has_disposition(Disposition::Initiator));
+        assert!(!initiator.has_disposition(Disposition::Target));
+
+        let target = controller(true, false);
+        assert!(!target.has_disposition(Disposition::Initiator));
+        assert!(target.has_disposition(Disposition::Target));
+
+        let both = controller(true, true);
+        assert!(both.has_disposition(Disposition::Initiator));
+        assert!(both.has_disposition(Disposition::Target));
+    }
+
+    #[test]
+    fn buses() {
+        let mut g = generator(Disposition::Devices, vec![]);
+        g.buses = HashMap::from([
+            ("rear".to_string(), (4, 1)),
+            ("front".to_string(), (2, 0)),
+            ("mid".to_string(), (3, 0)),
+        ]);
+
+        g.generate_buses().unwrap();
+
+        let names = g.output.find("\"front\",").unwrap();
+        assert!(names < g.output.find("\"mid\",").unwrap());
+        assert!(names < g.output.find("\"rear\",").unwrap());
+        assert!(g
+            .output
+            .contains("\"rear\" => Some((Controller::I2C4, PortIndex(1))),"));
+    }
+
+    #[test]
+    fn mux_segments() {
+        let mut a = device("tmp117", 0x48, Some(1), Some(3));
+        a.name = Some("inlet".to_string());
+        a.refdes = Some("U7".to_string());
+
+        // Not behind a mux
+        let mut b = device("tmp117", 0x49, None, None);
+        b.name = Some("outlet".to_string());
+
+        // Behind a mux, but with no name to hang a constant on
+        let c = device("tmp451", 0x4c, Some(2), Some(1));
+
+        let mut d = device("at24csw080", 0x50, Some(2), Some(4));
+        d.refdes = Some("J202".to_string());
+
+        let mut g = generator(Disposition::Initiator, vec![a, b, c, d]);
+
+        g.generate_mux_segments().unwrap();
+
+        let inlet = "pub const TMP117_INLET: (Mux, Segment) = \
+            (Mux::M1, Segment::S3);";
+        let u7 =
+            "pub const TMP117_U7: (Mux, Segment) = (Mux::M1, Segment::S3);";
+        let j202 = "pub const AT24CSW080_J202: (Mux, Segment) = \
+            (Mux::M2, Segment::S4);";
+
+        // Sorted by constant name
+        let j202 = g.output.find(j202).unwrap();
+        let inlet = g.output.find(inlet).unwrap();
+        let u7 = g.output.find(u7).unwrap();
+        assert!(j202 < inlet && inlet < u7);
+
+        assert!(!g.output.contains("OUTLET"));
+        assert!(!g.output.contains("TMP451"));
+    }
+
+    #[test]
+    fn sensor_checks() {
+        let sensors = |temperature, voltage| I2cSensors {
+            temperature,
+            power: 0,
+            current: 0,
+            voltage,
+            input_current: 0,
+            input_voltage: 0,
+            speed: 0,
+            names: None,
+        };
+
+        let mut a = device("tmp117", 0x48, None, None);
+        a.sensors = Some(sensors(2, 0));
+        let b = device("tmp451", 0x4c, None, None);
+        let mut c = device("max5970", 0x3a, None, None);
+        c.sensors = Some(sensors(0, 3));
+
+        let mut g = generator(Disposition::Sensors, vec![a, b, c]);
+        let s = g.sensors_description();
+        g.emit_sensor_checks(&s).unwrap();
+
+        assert!(g.output.contains(
+            "NUM_MAX5970_VOLTAGE_SENSORS\n                \
+            + NUM_TMP117_TEMPERATURE_SENSORS\n"
+        ));
+        assert!(g.output.contains("assert!(4 < NUM_SENSORS);"));
+
+        // Without any sensors, there's no largest ID to check.
+        let mut g = generator(Disposition::Sensors, vec![]);
+        let s = g.sensors_description();
+        g.emit_sensor_checks(&s).unwrap();
+
+        assert!(g.output.contains("NUM_SENSORS\n                == 0\n"));
+        assert!(!g.output.contains("< NUM_SENSORS"));
+    }
+
+    #[test]
+    fn flavored_sensor_struct() {
+        let mut d = device("max5970", 0x3a, None, None);
+        d.flavor = Some("dual".to_string());
+
+        let mut g = generator(Disposition::Sensors, vec![]);
+
+        let sensors = [DeviceSensor {
+            name: None,
+            kind: Sensor::Voltage,
+            id: 4,
+        }];
+        g.emit_sensor_struct(&d, "U7".to_string(), "max5970_dual", &sensors)
+            .unwrap();
+
+        assert!(g
+            .output
+            .contains("pub type MAX5970_U7_Sensors = Sensors_max5970_dual;"));
+        assert!(g.output.contains(
+            "pub const MAX5970_U7_SENSORS: Sensors_max5970_dual = \
+            Sensors_max5970_dual {"
+        ));
+    }
+
+    #[test]
+    fn topology() {
+        let pin = |pin| I2cPin {
+            gpio_port: Some("F".to_string()),
+            pin,
+        };
+        let port = |name: Option<&str>, muxes| I2cPort {
+            name: name.map(str::to_string),
+            description: None,
+            scl: pin(1),
+            sda: pin(0),
+            af: 4,
+            muxes,
+        };
+        let mux = |address| I2cMux {
+            driver: "pca9548".to_string(),
+            address,
+            nreset: None,
+            gpio_mux: None,
+            verify_segment: false,
+        };
+        let controller = |controller, ports| I2cController {
+            controller,
+            ports,
+            target: false,
+            initiator: false,
+            timing: None,
+        };
+
+        let mut behind = device("tmp117", 0x48, Some(1), Some(2));
+        behind.port = Some("H".to_string());
+        let mut direct = device("tmp117", 0x49, None, None);
+        direct.port = Some("H".to_string());
+        let mut eeprom = device("at24csw080", 0x50, None, None);
+        eeprom.port = Some("F".to_string());
+
+        let mut g =
+            generator(Disposition::Devices, vec![behind, direct, eeprom]);
+        g.controllers = vec![
+            controller(4, BTreeMap::new()),
+            controller(
+                2,
+                BTreeMap::from([
+                    ("H".to_string(), port(None, vec![mux(0x71), mux(0x70)])),
+                    ("F".to_string(), port(Some("front"), vec![])),
+                ]),
+            ),
+        ];
+        g.ports = IndexMap::from([
+            ((2, "F".to_string()), 0),
+            ((2, "H".to_string()), 1),
+        ]);
+
+        g.generate_topology().unwrap();
+        let out = &g.output;
+
+        // Controllers by number, and ports by name
+        let i2c2 = out.find("controller: 2,").unwrap();
+        let i2c4 = out.find("controller: 4,").unwrap();
+        let f = out.find("port: \"F\",").unwrap();
+        let h = out.find("port: \"H\",").unwrap();
+        assert!(i2c2 < f && f < h && h < i2c4);
+        assert!(out.contains("bus: Some(\"front\"),"));
+
+        // Muxes by address, and devices by mux, segment and address
+        let m70 = out.find("address: 0x70,").unwrap();
+        let m71 = out.find("address: 0x71,").unwrap();
+        assert!(h < m70 && m70 < m71);
+
+        let eeprom = out.find("address: 0x50,").unwrap();
+        let direct = out.find("address: 0x49,").unwrap();
+        let behind = out.find("address: 0x48,").unwrap();
+        assert!(f < eeprom && eeprom < h);
+        assert!(m71 < direct && direct < behind && behind < i2c4);
+        assert!(out.contains("mux: Some(1),\n"));
+    }
+
+    #[test]
+    fn device_counts() {
+        let on = |controller, address| {
+            let mut d = device("tmp117", address, None, None);
+            d.controller = Some(controller);
+            d
+        };
+
+        let mut g = generator(
+            Disposition::Devices,
+            vec![on(1, 0x48), on(2, 0x48), on(2, 0x49), on(2, 0x4a)],
+        );
+        g.singletons = HashMap::from([(1, 0), (2, 0)]);
+
+        g.generate_devices().unwrap();
+        let out = &g.output;
+
+        // Both controllers have a port 0; their devices aren't lumped together.
+        assert!(out.contains("pub const NDEVICES_I2C1: usize = 1;"));
+        assert!(out.contains("pub const NDEVICES_I2C2: usize = 3;"));
+        assert!(out.contains("pub const NDEVICES_I2C1_PORT_0: usize = 1;"));
+        assert!(out.contains("pub const NDEVICES_I2C2_PORT_0: usize = 3;"));
+        assert!(!out.contains("NDEVICES_PORT_"));
+    }
+
+    #[test]
+    fn aliases() {
+        let mut a = device("tmp117", 0x48, None, None);
+        a.name = Some("inlet".to_string());
+        a.aliases = vec!["front".to_string()];
+
+        let mut b = device("tmp117", 0x49, None, None);
+        b.name = Some("outlet".to_string());
+        b.refdes = Some("U2".to_string());
+
+        // An alias may match a name for a different kind of device.
+        let mut c = device("tmp451", 0x4c, None, None);
+        c.aliases = vec!["inlet".to_string(), "outlet".to_string()];
+
+        let devices = [a, b, c];
+        let by_alias = check_aliases(&devices);
+
+        assert_eq!(by_alias.len(), 3);
+        let tmp117 = "tmp117".to_string();
+        let front = "front".to_string();
+        assert_eq!(by_alias[&(&tmp117, &front)].address, 0x48);
+    }
+
+    #[test]
+    #[should_panic(expected = "alias U2 for device tmp117 duplicates")]
+    fn alias_is_refdes() {
+        let mut a = device("tmp117", 0x48, None, None);
+        a.aliases = vec!["U2".to_string()];
+
+        let mut b = device("tmp117", 0x49, None, None);
+        b.refdes = Some("U2".to_string());
+
+        check_aliases(&[a, b]);
+    }
+
+    #[test]
+    #[should_panic(expected = "alias front for device tmp117 duplicates")]
+    fn duplicate_alias() {
+        let mut a = device("tmp117", 0x48, None, None);
+        a.aliases = vec!["front".to_string()];
+
+        let mut b = device("tmp117", 0x49, None, None);
+        b.aliases = vec!["front".to_string()];
+
+        check_aliases(&[a, b]);
+    }
+
+    #[test]
+    fn name_lengths() {
+        let mut d = device("tmp117", 0x48, None, None);
+        d.name = Some("a".repeat(drv_i2c_types::MAX_DEVICE_NAME_LEN));
+        d.refdes = Some("U1".to_string());
+
+        check_name_lengths([&d]);
+    }
+
+    #[test]
+    #[should_panic(expected = "is 35 bytes long")]
+    fn refdes_too_long() {
+        let mut d = device("tmp117", 0x48, None, None);
+        d.refdes = Some("U1234567890123456789012345678901234".to_string());
+
+        check_name_lengths([&d]);
+    }
+}
@@ -82,6 +82,37 @@ pub fn expose_m_profile() -> Result<()> {
     Ok(())
 }
 
+/// Exposes the git revision being built as the `HUBRIS_GIT_VERSION` env var,
+/// for use with `env!`.
+///
+/// This is the commit hash, with a `-dirty` suffix if there are uncommitted
+/// changes (matching `git-rev` in the build archive), or `unknown` if we're not
+/// building from a git checkout.
+pub fn expose_git_version() {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_owned())
+    };
+
+    let version = match git(&["rev-parse", "HEAD"]) {
+        Some(rev) => {
+            // `diff-index --quiet` fails if there are uncommitted changes
+            let dirty = git(&["diff-index", "--quiet", "HEAD", "--"]).is_none();
+            format!("{rev}{}", if dirty { "-dirty" } else { "" })
+        }
+        None => "unknown".to_owned(),
+    };
+    if let Some(dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={dir}/HEAD");
+        println!("cargo:rerun-if-changed={dir}/index");
+    }
+    println!("cargo:rustc-env=HUBRIS_GIT_VERSION={version}");
+}
+
 /// Returns the `HUBRIS_BOARD` envvar, if set.
 pub fn target_board() -> Option<String> {
     crate::env_var("HUBRIS_BOARD").ok()
@@ -101,6 +101,20 @@ impl core::fmt::Display for I2cDevice {
     }
 }
 
+///
+/// Asks the I2C server `task` to clear its trace ringbuf and counters, e.g.
+/// immediately before reproducing a failure.
+///
+pub fn reset_trace(task: TaskId) -> Result<(), ResponseCode> {
+    let (code, _) = sys_send(task, Op::ResetTrace as u16, &[], &mut [], &[]);
+
+    if code != 0 {
+        Err(ResponseCode::from_u32(code).ok_or(ResponseCode::BadResponse)?)
+    } else {
+        Ok(())
+    }
+}
+
 impl I2cDevice {
     ///
     /// Return a new [`I2cDevice`], given a 5-tuple identifying a device plus
@@ -310,6 +324,62 @@ impl I2cDevice {
         self.response_code(code, ())
     }
 
+    ///
+    /// Probes for the device by writing zero bytes to it, returning `Ok(())`
+    /// if it ACKs its address and [`ResponseCode::NoDevice`] if it doesn't.
+    ///
+    /// The probe is ended with a STOP in either case, so a missing device
+    /// leaves the bus ready for the next transaction.
+    ///
+    pub fn probe(&self) -> Result<(), ResponseCode> {
+        let mut response = 0_usize;
+
+        let (code, _) = sys_send(
+            self.task,
+            Op::WriteRead as u16,
+            &Marshal::marshal(&(
+                self.address,
+                self.controller,
+                self.port,
+                self.segment,
+            )),
+            response.as_bytes_mut(),
+            &[Lease::read_only(&[]), Lease::read_only(&[])],
+        );
+
+        self.response_code(code, ())
+    }
+
+    ///
+    /// Probes for a device that may legitimately be absent (e.g., one marked
+    /// `removable` in the I2C configuration), retrying up to `retries` times
+    /// with a delay of `delay_ms` between attempts if the device does not
+    /// respond.
+    ///
+    /// Returns `Ok(true)` if the device ACK'd any probe, and `Ok(false)` if
+    /// every probe resulted in [`ResponseCode::NoDevice`].  Any other error
+    /// is returned immediately without further retries.
+    ///
+    pub fn probe_with_retries(
+        &self,
+        retries: u32,
+        delay_ms: u64,
+    ) -> Result<bool, ResponseCode> {
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                userlib::hl::sleep_for(delay_ms);
+            }
+
+            match self.probe() {
+                Ok(()) => return Ok(true),
+                Err(ResponseCode::NoDevice) => continue,
+                Err(code) => return Err(code),
+            }
+        }
+
+        Ok(false)
+    }
+
     ///
     /// Writes a buffer, and then performs a subsequent register read.  These
     /// are not performed as a single I2C transaction (that is, it is not a
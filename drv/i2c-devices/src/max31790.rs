@@ -187,6 +187,18 @@ pub struct Max31790 {
 
 pub const MAX_FANS: u8 = 6;
 
+/// Contents of the configuration registers at the start of the register map
+/// (Global Configuration, PWM Frequency, and Fan 1-6 Configuration).
+///
+/// The MAX31790 has no identity or revision registers, so this is the closest
+/// we can get to identifying how a given part has been set up.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Configuration {
+    pub global: u8,
+    pub pwm_frequency: u8,
+    pub fans: [u8; MAX_FANS as usize],
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Fan(u8);
 
@@ -289,6 +301,21 @@ impl Max31790 {
         Ok(())
     }
 
+    /// Reads the configuration registers in a single transaction
+    pub fn configuration(&self) -> Result<Configuration, ResponseCode> {
+        let regs = self.device.read_reg::<u8, [u8; 2 + MAX_FANS as usize]>(
+            Register::GlobalConfiguration as u8,
+        )?;
+        let mut fans = [0; MAX_FANS as usize];
+        fans.copy_from_slice(&regs[2..]);
+
+        Ok(Configuration {
+            global: regs[0],
+            pwm_frequency: regs[1],
+            fans,
+        })
+    }
+
     /// Determines the rotations per minute based on the tach count
     pub fn fan_rpm(&self, fan: Fan) -> Result<Rpm, ResponseCode> {
         let val = read_reg16(&self.device, fan.tach_count())?;
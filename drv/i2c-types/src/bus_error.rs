@@ -0,0 +1,202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Bus error detection for I2C controllers
+//!
+//! The mapping from a controller's ISR bits to [`ResponseCode`]s, and the
+//! order in which those flags are checked and cleared, lives here rather than
+//! in the controller driver so that it can be exercised on the host against a
+//! simulated register block.
+
+use crate::ResponseCode;
+
+///
+/// A bus error condition flagged in the ISR, each of which has its own clear
+/// bit in the ICR.  These are listed in the order in which [`check_errors`]
+/// looks for them.
+///
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ErrorFlag {
+    ArbitrationLost,
+    BusError,
+    Timeout,
+}
+
+impl ErrorFlag {
+    pub const ALL: [ErrorFlag; 3] = [
+        ErrorFlag::ArbitrationLost,
+        ErrorFlag::BusError,
+        ErrorFlag::Timeout,
+    ];
+
+    /// The bit for this flag in the ISR, which is also its clear bit in the
+    /// ICR.  These positions are the same on both the H7 and the G0.
+    pub const fn bit(self) -> u32 {
+        match self {
+            ErrorFlag::ArbitrationLost => 1 << 9,
+            ErrorFlag::BusError => 1 << 8,
+            ErrorFlag::Timeout => 1 << 12,
+        }
+    }
+
+    /// The code returned to the caller when this error is found.
+    pub fn code(self) -> ResponseCode {
+        match self {
+            ErrorFlag::ArbitrationLost => ResponseCode::BusReset,
+            ErrorFlag::BusError => ResponseCode::BusError,
+            ErrorFlag::Timeout => ResponseCode::BusLocked,
+        }
+    }
+}
+
+///
+/// The register access needed to acknowledge bus errors.  The error handling
+/// goes through this trait rather than touching the register block directly
+/// so that it can be driven by a simulated register block; the controller
+/// driver's implementation is a direct passthrough.
+///
+pub trait ErrorRegisters {
+    /// Clears `flag` by writing its bit in the ICR.
+    fn clear(&self, flag: ErrorFlag);
+}
+
+///
+/// Checks the raw ISR value `isr` for bus errors, clearing the first one found
+/// (in the order of [`ErrorFlag`]) and returning its code.  Any other errors
+/// flagged remain set, to be found by the next check.
+///
+#[inline(always)]
+pub fn check_errors<R: ErrorRegisters>(
+    registers: &R,
+    isr: u32,
+) -> Result<(), ResponseCode> {
+    for flag in ErrorFlag::ALL {
+        if isr & flag.bit() != 0 {
+            registers.clear(flag);
+            return Err(flag.code());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    // ISR bit positions, from the H7 and G0 reference manuals.
+    const NACKF: u32 = 1 << 4;
+    const BERR: u32 = 1 << 8;
+    const ARLO: u32 = 1 << 9;
+    const OVR: u32 = 1 << 10;
+    const TIMEOUT: u32 = 1 << 12;
+
+    /// A simulated register block: clearing a flag records the write to the
+    /// ICR and, as the hardware does, drops the flag from the ISR.
+    #[derive(Default)]
+    struct MockRegisters {
+        isr: Cell<u32>,
+        icr: Cell<u32>,
+        writes: Cell<usize>,
+    }
+
+    impl MockRegisters {
+        fn with_isr(isr: u32) -> Self {
+            Self {
+                isr: Cell::new(isr),
+                ..Default::default()
+            }
+        }
+
+        fn check(&self) -> Result<(), ResponseCode> {
+            self.icr.set(0);
+            check_errors(self, self.isr.get())
+        }
+    }
+
+    impl ErrorRegisters for MockRegisters {
+        fn clear(&self, flag: ErrorFlag) {
+            self.icr.set(self.icr.get() | flag.bit());
+            self.isr.set(self.isr.get() & !flag.bit());
+            self.writes.set(self.writes.get() + 1);
+        }
+    }
+
+    #[test]
+    fn isr_bits() {
+        assert_eq!(ErrorFlag::ArbitrationLost.bit(), ARLO);
+        assert_eq!(ErrorFlag::BusError.bit(), BERR);
+        assert_eq!(ErrorFlag::Timeout.bit(), TIMEOUT);
+
+        // NACKF and OVR are handled by the transfer itself, not as bus errors
+        for flag in ErrorFlag::ALL {
+            assert_eq!(flag.bit() & (NACKF | OVR), 0, "{flag:?}");
+        }
+    }
+
+    #[test]
+    fn no_errors() {
+        let regs = MockRegisters::with_isr(0);
+        assert_eq!(regs.check(), Ok(()));
+        assert_eq!(regs.writes.get(), 0);
+    }
+
+    #[test]
+    fn single_error() {
+        for (isr, code) in [
+            (ARLO, ResponseCode::BusReset),
+            (BERR, ResponseCode::BusError),
+            (TIMEOUT, ResponseCode::BusLocked),
+        ] {
+            let regs = MockRegisters::with_isr(isr);
+            assert_eq!(regs.check(), Err(code));
+            assert_eq!(regs.icr.get(), isr);
+            assert_eq!(regs.writes.get(), 1);
+            assert_eq!(regs.isr.get(), 0);
+            assert_eq!(regs.check(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn unrelated_bits_ignored() {
+        // TXE, RXNE, NACKF and OVR are not bus errors
+        let regs = MockRegisters::with_isr(1 << 0 | 1 << 2 | NACKF | OVR);
+        assert_eq!(regs.check(), Ok(()));
+        assert_eq!(regs.writes.get(), 0);
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let regs = MockRegisters::with_isr(ARLO | BERR | TIMEOUT);
+
+        assert_eq!(regs.check(), Err(ResponseCode::BusReset));
+        assert_eq!(regs.icr.get(), ARLO);
+        assert_eq!(regs.isr.get(), BERR | TIMEOUT);
+
+        assert_eq!(regs.check(), Err(ResponseCode::BusError));
+        assert_eq!(regs.icr.get(), BERR);
+        assert_eq!(regs.isr.get(), TIMEOUT);
+
+        assert_eq!(regs.check(), Err(ResponseCode::BusLocked));
+        assert_eq!(regs.icr.get(), TIMEOUT);
+        assert_eq!(regs.isr.get(), 0);
+
+        assert_eq!(regs.check(), Ok(()));
+        assert_eq!(regs.writes.get(), 3);
+    }
+
+    #[test]
+    fn bus_error_and_timeout() {
+        let regs = MockRegisters::with_isr(BERR | TIMEOUT);
+
+        assert_eq!(regs.check(), Err(ResponseCode::BusError));
+        assert_eq!(regs.icr.get(), BERR);
+
+        assert_eq!(regs.check(), Err(ResponseCode::BusLocked));
+        assert_eq!(regs.icr.get(), TIMEOUT);
+
+        assert_eq!(regs.check(), Ok(()));
+    }
+}
@@ -17,6 +17,13 @@ use serde::{Deserialize, Serialize};
 use derive_idol_err::IdolError;
 use enum_kinds::EnumKind;
 
+pub mod bus_error;
+
+/// The longest device name or refdes that can be reported to the host, which
+/// is the size of the (fixed-length) name in the host-SP inventory messages.
+/// The I2C build machinery checks every device against this at build time.
+pub const MAX_DEVICE_NAME_LEN: usize = 32;
+
 #[derive(FromPrimitive, Eq, PartialEq)]
 pub enum Op {
     WriteRead = 1,
@@ -36,6 +43,10 @@ pub enum Op {
     /// without interruption, this logic would not work, but that would be a
     /// very strange device indeed.
     WriteReadBlock = 2,
+
+    /// Clears the driver's trace ringbuf and its counters. This takes no
+    /// arguments or leases and returns nothing.
+    ResetTrace = 3,
 }
 
 /// The response code returned from the I2C server.  These response codes pretty
@@ -109,6 +120,10 @@ pub enum ResponseCode {
     IllegalLeaseCount,
     /// Too much data -- or not enough buffer
     TooMuchData,
+    /// SMBus Packet Error Code did not match the data received
+    BadChecksum,
+    /// Mux control register did not reflect the requested segment
+    SegmentMismatch,
 }
 
 ///
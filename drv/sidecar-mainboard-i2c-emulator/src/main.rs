@@ -26,6 +26,15 @@ fn main() -> ! {
 
     loop {
         hl::recv_without_notification(&mut buffer, |op, msg| match op {
+            Op::ResetTrace => {
+                let (_, caller) = msg
+                    .fixed_with_leases::<(), ()>(0)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                ringbuf_reset!(Trace::None);
+                caller.reply(());
+                Ok(())
+            }
             Op::WriteRead | Op::WriteReadBlock => {
                 let (payload, caller) = msg
                     .fixed_with_leases::<[u8; 4], usize>(2)
@@ -9,7 +9,7 @@
 
 use drv_i2c_api::*;
 use drv_stm32xx_i2c::*;
-use drv_stm32xx_sys_api::{Mode, OutputType, PinSet, Pull, Speed, Sys};
+use drv_stm32xx_sys_api::{Mode, OutputType, Pull, Speed, Sys};
 
 use fixedmap::*;
 use ringbuf::*;
@@ -236,7 +236,6 @@ enum Trace {
     ResetMux(u8),
     SegmentFailed(ResponseCodeU8),
     ConfigureFailed(ResponseCodeU8),
-    Wiggles(u8),
     None,
 }
 
@@ -298,7 +297,7 @@ fn reset_if_needed(
 
 ///
 /// A variant of [`reset_if_needed`] that will also wiggle the SCL lines
-/// via [`wiggle_scl`].
+/// via [`I2cController::recover_bus`].
 ///
 fn reset_and_wiggle_if_needed(
     code: ResponseCode,
@@ -317,21 +316,7 @@ fn reset_and_wiggle_if_needed(
             .filter(|p| p.controller == controller.controller)
             .filter(|p| p.port == port)
         {
-            wiggle_scl(&sys, pin.scl, pin.sda);
-
-            //
-            // [`wiggle_scl`] puts our pins in output (and input) mode; set
-            // them back to be configured for I2C before we reset.
-            //
-            for gpio_pin in &[pin.scl, pin.sda] {
-                sys.gpio_configure_alternate(
-                    *gpio_pin,
-                    OutputType::OpenDrain,
-                    Speed::Low,
-                    Pull::None,
-                    pin.function,
-                );
-            }
+            controller.recover_bus(&sys, pin);
         }
 
         reset(controller, port, muxes, muxmap);
@@ -415,6 +400,15 @@ fn main() -> ! {
 
     loop {
         hl::recv_without_notification(&mut buffer, |op, msg| match op {
+            Op::ResetTrace => {
+                let (_, caller) = msg
+                    .fixed_with_leases::<(), ()>(0)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                drv_stm32xx_i2c::reset_trace();
+                caller.reply(());
+                Ok(())
+            }
             Op::WriteRead | Op::WriteReadBlock => {
                 let lease_count = msg.lease_count();
 
@@ -477,42 +471,44 @@ fn main() -> ! {
                     let rbuf = caller.borrow(i + 1);
                     let rinfo = rbuf.info().ok_or(ResponseCode::BadArg)?;
 
-                    if winfo.len == 0 && rinfo.len == 0 {
-                        // In a given lease pair, we must have either a write
-                        // OR a read -- while perhaps valid to support both
-                        // being zero as a way of testing an address for a
-                        // NACK, it's not a mode that we (currently) support.
-                        return Err(ResponseCode::BadArg);
-                    }
-
-                    if winfo.len > 255 || rinfo.len > 255 {
-                        // For now, we don't support writing or reading more
-                        // than 255 bytes.
-                        return Err(ResponseCode::BadArg);
-                    }
-
                     let mut nread = 0;
 
-                    let controller_result = controller.write_read(
-                        addr,
-                        winfo.len,
-                        |pos| wbuf.read_at(pos),
-                        // Only the final read operation in a WriteReadBlock is
-                        // a block read; everything else is a normal read.
-                        if op == Op::WriteReadBlock && i == lease_count - 2 {
-                            ReadLength::Variable
-                        } else {
-                            ReadLength::Fixed(rinfo.len)
-                        },
-                        |pos, byte| {
-                            if pos + 1 > nread {
-                                nread = pos + 1;
-                            }
+                    let controller_result = if winfo.len == 0 && rinfo.len == 0
+                    {
+                        // A single pair of empty leases in a WriteRead is a
+                        // probe:  a zero-byte write that tests the address
+                        // for an ACK.  Anywhere else, a lease pair must have
+                        // either a write OR a read.
+                        if op != Op::WriteRead || lease_count != 2 {
+                            return Err(ResponseCode::BadArg);
+                        }
 
-                            rbuf.write_at(pos, byte)
-                        },
-                        &ctrl,
-                    );
+                        controller.probe(addr, &ctrl)
+                    } else {
+                        controller.write_read(
+                            addr,
+                            winfo.len,
+                            |pos| wbuf.read_at(pos),
+                            // Only the final read operation in a
+                            // WriteReadBlock is a block read; everything else
+                            // is a normal read.
+                            if op == Op::WriteReadBlock && i == lease_count - 2
+                            {
+                                ReadLength::SmbusBlock { pec: false }
+                            } else {
+                                ReadLength::Fixed(rinfo.len)
+                            },
+                            |pos, byte| {
+                                if pos + 1 > nread {
+                                    nread = pos + 1;
+                                }
+
+                                rbuf.write_at(pos, byte)
+                            },
+                            &ctrl,
+                            None,
+                        )
+                    };
                     match controller_result {
                         Err(code) => {
                             //
@@ -630,87 +626,6 @@ fn configure_port(
     map.insert(controller.controller, port);
 }
 
-///
-/// When the system is either reset without power loss (e.g., due to an SP
-/// upgrade) or I2C is preempted longer than the 25ms I2C timeout (e.g., due
-/// to a large process panicking and being dumped by jefe), I2C can be in an
-/// arbitrary state with respect to the bus -- and we can therefore come to
-/// life with a transaction already in flight.  It is very important that we
-/// abort any such transaction:  failure to do so will result in our first I2C
-/// transaction being corrupted.  (And because our first I2C transaction on SP
-/// boot may well be to disable segments on a mux, this can result in nearly
-/// arbitrary mayhem down the road!)  To do this, we engage in the
-/// time-honored[0] tradition of "clocking through the problem":  wiggling SCL
-/// until we see SDA high, and then pulling SDA low and releasing SCL to
-/// indicate a STOP condition.  (Note that we need to do this up to 9 times to
-/// assure that we have clocked through the entire transaction.)  Our assumption
-/// is that if SCL is being stretched by an errant target, it has been already
-/// stretched beyond our timeout (25ms); if this is the case, us trying to
-/// wiggle SCL here won't actually wiggle SCL -- but unless such a device is
-/// isolated to a segment on a mux that we can reset, nothing will in fact help.
-///
-/// [0] Analog Devices. AN-686: Implementing an I2C Reset. 2003.
-///
-fn wiggle_scl(sys: &Sys, scl: PinSet, sda: PinSet) {
-    let mut wiggles = 0_u8;
-    sys.gpio_set(scl);
-
-    sys.gpio_configure_output(
-        scl,
-        OutputType::OpenDrain,
-        Speed::Low,
-        Pull::None,
-    );
-
-    for _ in 0..9 {
-        sys.gpio_set(sda);
-
-        sys.gpio_configure_output(
-            sda,
-            OutputType::OpenDrain,
-            Speed::Low,
-            Pull::None,
-        );
-
-        sys.gpio_configure_input(sda, Pull::None);
-
-        if sys.gpio_read(sda) == 0 {
-            //
-            // SDA is low -- someone is holding it down: give SCL a wiggle to
-            // try to shake them.  Note that we don't sleep here:  we are
-            // relying on the fact that communicating to the GPIO task is going
-            // to take longer than our minimum SCL pulse.  (Which, on a 400 MHz
-            // H753, is on the order of ~15 usecs -- yielding a cycle time of
-            // ~30 usecs or ~33 KHz.)
-            //
-            sys.gpio_reset(scl);
-            sys.gpio_set(scl);
-            wiggles = wiggles.wrapping_add(1);
-        } else {
-            //
-            // SDA is high. We're going to flip it back to an output, pull the
-            // clock down then, pull SDA down, then release SCL and finally
-            // release SDA.  This will denote a STOP condition.
-            //
-            sys.gpio_set(sda);
-
-            sys.gpio_configure_output(
-                sda,
-                OutputType::OpenDrain,
-                Speed::Low,
-                Pull::None,
-            );
-
-            sys.gpio_reset(scl);
-            sys.gpio_reset(sda);
-            sys.gpio_set(scl);
-            sys.gpio_set(sda);
-        }
-    }
-
-    ringbuf_entry!(Trace::Wiggles(wiggles));
-}
-
 fn configure_pins(
     controllers: &[I2cController<'_>],
     pins: &[I2cPins],
@@ -724,7 +639,7 @@ fn configure_pins(
     // transaction.
     //
     for pin in pins {
-        wiggle_scl(&sys, pin.scl, pin.sda);
+        release_sda(&sys, pin.scl, pin.sda);
     }
 
     for pin in pins {
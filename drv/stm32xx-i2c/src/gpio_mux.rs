@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for I2C segment selection via a general-purpose GPIO expander
+//!
+//! Some boards select I2C segments with discrete analog switches driven by an
+//! 8-bit I2C GPIO expander (e.g. a PCA9538 or TCA6408) rather than with a
+//! dedicated mux.  Unlike the other mux drivers, there's nothing about the
+//! wiring that can be known from the part alone, so this driver carries its
+//! configuration in the [`GpioMux`] itself; the `I2cMux` names the expander
+//! (by address and optional reset line) just as it would a mux.
+//!
+//! The expander is assumed to be dedicated to segment selection: every pin
+//! not in [`GpioMux::pins`] is left as an input.
+
+use crate::*;
+use drv_i2c_api::{ResponseCode, Segment};
+
+pub struct GpioMux {
+    /// Expander register holding the output level of each pin
+    pub output_register: u8,
+
+    /// Expander register selecting each pin's direction, where a 0 bit makes
+    /// that pin an output, if the expander has one
+    pub config_register: Option<u8>,
+
+    /// Pins that drive segment selection
+    pub pins: u8,
+
+    /// Output levels (within `pins`) that select each segment, indexed by
+    /// segment number less one; `None` if that segment isn't wired
+    pub segments: [Option<u8>; 8],
+
+    /// Output levels (within `pins`) that deselect every segment
+    pub deselect: u8,
+}
+
+impl GpioMux {
+    fn write(
+        &self,
+        mux: &I2cMux<'_>,
+        controller: &I2cController<'_>,
+        ctrl: &I2cControl,
+        register: u8,
+        value: u8,
+    ) -> Result<(), ResponseCode> {
+        let buf = [register, value];
+
+        match controller.write_read(
+            mux.address,
+            buf.len(),
+            |pos| buf.get(pos).copied(),
+            ReadLength::Fixed(0),
+            |_, _| Some(()),
+            ctrl,
+            None,
+        ) {
+            Err(code) => Err(mux.error_code(code)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drives `levels` on our pins.  We set the output levels _before_
+    /// making the pins outputs to avoid glitching; we rewrite the direction
+    /// every time (rather than once, in `configure`) because a reset of the
+    /// expander returns every pin to an input.
+    fn drive(
+        &self,
+        mux: &I2cMux<'_>,
+        controller: &I2cController<'_>,
+        ctrl: &I2cControl,
+        levels: u8,
+    ) -> Result<(), ResponseCode> {
+        self.write(mux, controller, ctrl, self.output_register, levels)?;
+
+        if let Some(config) = self.config_register {
+            self.write(mux, controller, ctrl, config, !self.pins)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl I2cMuxDriver for GpioMux {
+    fn configure(
+        &self,
+        mux: &I2cMux<'_>,
+        controller: &I2cController<'_>,
+        gpio: &sys_api::Sys,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        mux.configure(gpio)?;
+        self.drive(mux, controller, ctrl, self.deselect)
+    }
+
+    fn enable_segment(
+        &self,
+        mux: &I2cMux<'_>,
+        controller: &I2cController<'_>,
+        segment: Option<Segment>,
+        ctrl: &I2cControl,
+    ) -> Result<(), ResponseCode> {
+        let levels = match segment {
+            Some(segment) => self.segments[segment as usize - 1]
+                .ok_or(ResponseCode::SegmentNotFound)?,
+            None => self.deselect,
+        };
+
+        self.drive(mux, controller, ctrl, levels)
+    }
+
+    /// Pulses the expander's reset line, if it has one.  This returns all of
+    /// its pins to inputs, so the board must bias the select lines such that
+    /// no segment is selected while they float; the next `enable_segment`
+    /// drives them again.
+    fn reset(
+        &self,
+        mux: &I2cMux<'_>,
+        gpio: &sys_api::Sys,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        mux.reset(gpio)
+    }
+}
@@ -35,15 +35,42 @@ pub type RegisterBlock = device::i2c1::RegisterBlock;
 ))]
 pub type Isr = device::i2c1::isr::R;
 
+pub use drv_i2c_api::bus_error::{check_errors, ErrorFlag, ErrorRegisters};
+
+#[cfg(any(
+    feature = "h743",
+    feature = "h753",
+    feature = "g031",
+    feature = "g030"
+))]
+impl ErrorRegisters for RegisterBlock {
+    #[inline(always)]
+    fn clear(&self, flag: ErrorFlag) {
+        match flag {
+            ErrorFlag::ArbitrationLost => {
+                self.icr.write(|w| w.arlocf().set_bit())
+            }
+            ErrorFlag::BusError => self.icr.write(|w| w.berrcf().set_bit()),
+            ErrorFlag::Timeout => self.icr.write(|w| w.timoutcf().set_bit()),
+        }
+    }
+}
+
+pub mod gpio_mux;
 pub mod ltc4306;
 pub mod max7358;
 pub mod pca9548;
+pub mod tca9544;
 
 use ringbuf::*;
 use userlib::*;
 
 use drv_stm32xx_sys_api as sys_api;
 
+/// The SCL and SDA pins for a single port on a controller.
+///
+/// Beyond configuring the pins for I2C, these are also used to recover a
+/// wedged bus (see [`I2cController::recover_bus`]).
 pub struct I2cPins {
     pub controller: drv_i2c_api::Controller,
     pub port: drv_i2c_api::PortIndex,
@@ -62,6 +89,44 @@ pub struct I2cController<'a> {
     pub peripheral: sys_api::Peripheral,
     pub notification: u32,
     pub registers: &'a RegisterBlock,
+
+    /// Bus timing from the application's I2C configuration; if `None`, we use
+    /// defaults for the CPU (or board) in `configure_timing`.
+    pub timing: Option<I2cTiming>,
+}
+
+/// Values for the fields of the TIMINGR register, which together determine
+/// the SCL frequency and the data setup and hold times.
+///
+/// See the "I2C timings" section of the reference manual; note that `presc`,
+/// `scldel`, and `sdadel` are 4-bit fields.
+#[derive(Copy, Clone, Debug)]
+pub struct I2cTiming {
+    pub presc: u8,
+    pub sclh: u8,
+    pub scll: u8,
+    pub scldel: u8,
+    pub sdadel: u8,
+}
+
+///
+/// A snapshot of a controller's registers, for diagnosing a wedged bus or
+/// task after the fact (see [`I2cController::snapshot`]).  This is plain data
+/// so that it can be cheaply stored in a ring buffer or returned to a caller.
+///
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct I2cRegSnapshot {
+    /// Interrupt and status register: whether the bus is busy, which
+    /// transfer events are pending, and any error flags
+    pub isr: u32,
+    /// Control register 1: whether the peripheral is enabled, and which
+    /// interrupts are enabled
+    pub cr1: u32,
+    /// Control register 2: the address, direction, and byte count of the
+    /// transfer in progress, and whether a START or STOP is pending
+    pub cr2: u32,
+    /// Timeout register: whether the SCL timeout is enabled, and its period
+    pub timeoutr: u32,
 }
 
 ///
@@ -69,6 +134,16 @@ pub struct I2cController<'a> {
 ///
 pub struct I2cTimeout(pub u64);
 
+///
+/// An SCL timeout for a single transaction, in milliseconds, overriding the
+/// default of 25 ms.  This is clamped to [`MAX_SCL_TIMEOUT_MS`], as it must
+/// remain well under the timeout used when waiting for interrupts.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct I2cSclTimeout(pub u32);
+
+pub const MAX_SCL_TIMEOUT_MS: u32 = 80;
+
 pub enum I2cControlResult {
     Interrupted,
     TimedOut,
@@ -119,6 +194,12 @@ pub trait I2cMuxDriver {
 
     /// Enable the specified segment on the specified mux (or disable
     /// all segments if None is explicitly specified as the segment)
+    ///
+    /// If the mux has `verify_segment` set, drivers for muxes whose control
+    /// register can be read back (the PCA9545/PCA9548, TCA9544 and LTC4306)
+    /// then read it, and return [`drv_i2c_api::ResponseCode::SegmentMismatch`]
+    /// if it doesn't reflect the requested segment.  The MAX7358 and GPIO
+    /// expander drivers don't verify.
     fn enable_segment(
         &self,
         mux: &I2cMux<'_>,
@@ -141,6 +222,45 @@ pub struct I2cMux<'a> {
     /// it's an active-low RESET.
     pub nreset: Option<I2cGpio>,
     pub address: u8,
+
+    /// Read back the control register after enabling a segment, to check
+    /// that the mux actually switched.  This costs a transaction per segment
+    /// change, so it's opt-in; see [`I2cMuxDriver::enable_segment`].
+    pub verify_segment: bool,
+}
+
+///
+/// A target address.  Unless otherwise specified (by constructing a
+/// [`I2cAddress::TenBit`]), addresses are 7-bit, and a `u8` converts into a
+/// 7-bit address.  Note that muxes (and therefore segments) are always
+/// addressed with 7 bits.
+///
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum I2cAddress {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+impl From<u8> for I2cAddress {
+    fn from(addr: u8) -> Self {
+        I2cAddress::SevenBit(addr)
+    }
+}
+
+impl I2cAddress {
+    /// Returns the value for the ADD10 bit in CR2
+    fn add10(self) -> bool {
+        matches!(self, I2cAddress::TenBit(_))
+    }
+
+    /// Returns the value for the SADD field in CR2: for a 7-bit address, this
+    /// goes in SADD[7:1]; for a 10-bit address, it is all of SADD[9:0].
+    fn sadd(self) -> u16 {
+        match self {
+            I2cAddress::SevenBit(addr) => u16::from(addr) << 1,
+            I2cAddress::TenBit(addr) => addr & 0x3ff,
+        }
+    }
 }
 
 ///
@@ -152,6 +272,17 @@ pub enum ReadLength {
     Fixed(usize),
     /// Read size is variable: first byte contains length
     Variable,
+    /// Like `Variable`, but the length is trusted only up to the given cap:
+    /// if the device claims more, we read just that many bytes (NACKing the
+    /// last), end the transfer early, and fail with `TooMuchData`.  (A cap
+    /// above 255 is no cap at all, since the length is a single byte; a cap
+    /// of 0 is rejected with `BadArg`.)
+    AtMost(usize),
+    /// SMBus block read: the first byte contains the length, which is followed
+    /// by exactly that many data bytes and, if `pec` is set, a Packet Error
+    /// Code that is checked against the entire transaction (but not passed to
+    /// the caller)
+    SmbusBlock { pec: bool },
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -176,6 +307,7 @@ enum Trace {
     ReadWait(Register, u32),
     KonamiOperation(I2cKonamiCode),
     Konami(Register, u32),
+    KonamiAttempt(u8),
     Reset(Register, u32),
     Addr(Register, u32),
     AddrMatch,
@@ -192,7 +324,72 @@ enum Trace {
     WaitTx,
     BusySleep,
     Stop,
+    SclTimeout {
+        ms: u32,
+        timeouta: u16,
+    },
     RepeatedStart(#[count(children)] bool),
+    TenBitAddress(u16),
+    ScanAck(u8),
+    ScanDone {
+        found: u8,
+    },
+    ProbeAck(u8),
+    ProbeNack(u8),
+    /// The device NACK'd its address: it's absent (or not answering).
+    AddressNack(I2cAddress),
+    /// The device ACK'd its address but NACK'd a write byte.  `pos` is the
+    /// number of bytes loaded into TXDR when the NACK was seen, not the index
+    /// of the byte that was NACK'd.
+    WriteNack {
+        pos: usize,
+    },
+    ArbitrationRetry(u8),
+    SegmentVerified {
+        address: u8,
+        control: u8,
+    },
+    SegmentMismatch {
+        address: u8,
+        expected: u8,
+        found: u8,
+    },
+    WriteWrite {
+        first: usize,
+        second: usize,
+    },
+    BlockCount(u8),
+    LengthCapped {
+        reported: u8,
+        cap: u8,
+    },
+    ReloadWait(Register, u32),
+    Overrun {
+        accepted: usize,
+        total: usize,
+    },
+    Reload {
+        pos: usize,
+        remaining: usize,
+    },
+    ReleaseSda {
+        pulses: u8,
+        released: bool,
+    },
+    RecoverBus(drv_i2c_api::Controller, drv_i2c_api::PortIndex),
+    PowerDown(drv_i2c_api::Controller),
+    PowerUp(drv_i2c_api::Controller),
+    PecMismatch {
+        expected: u8,
+        actual: u8,
+    },
+    RxPecMatch(u8),
+    RxPecMismatch {
+        addr: u8,
+        expected: u8,
+        actual: u8,
+    },
+    TxPec(u8, u8),
     LostInterrupt,
     #[count(skip)]
     Panic(Register, u32),
@@ -207,9 +404,218 @@ enum Trace {
     None,
 }
 
-counted_ringbuf!(Trace, 48, Trace::None);
+/// The number of times [`I2cController::write_read`] retries a transaction
+/// after losing arbitration.
+pub const ARBITRATION_RETRIES: u8 = 3;
+
+/// The most times [`I2cController::send_konami_code_with_retry`] will retry
+/// a NACK'd Konami Code, however many retries it is asked for.
+pub const MAX_KONAMI_RETRIES: u8 = 3;
+
+// Timestamped, so that we can see where the time goes in a slow transaction.
+counted_ringbuf!(Trace, 48, Trace::None, timestamped);
+
+/// Clears the driver's ringbuf and zeroes its counters, so that a failure can
+/// be reproduced without earlier, unrelated traffic in the trace.
+///
+/// If the ringbuf is disabled, this only resets the counters (or does nothing
+/// at all, if they are disabled too).
+pub fn reset_trace() {
+    ringbuf_reset!(Trace::None);
+}
+
+// Counts of each error returned by `write_read` and `send_konami_code`, across
+// all controllers, so that they can be seen without decoding the ringbuf.
+// This is opt-in to spare the RAM on builds that don't want it.
+#[cfg(feature = "error-counters")]
+counters::counters!(ERROR_COUNTERS, drv_i2c_api::ResponseCode);
+
+/// Records `result` in `ERROR_COUNTERS` if it is an error, returning it
+/// unchanged.
+fn count_error<T>(
+    result: Result<T, drv_i2c_api::ResponseCode>,
+) -> Result<T, drv_i2c_api::ResponseCode> {
+    #[cfg(feature = "error-counters")]
+    if let Err(code) = result {
+        counters::count!(ERROR_COUNTERS, code);
+    }
+    result
+}
+
+cfg_if::cfg_if! {
+    //
+    // The timeout value is defined to be:
+    //
+    //   t_timeout = (TIMEOUTA + 1) x 2048 x t_i2cclk
+    //
+    // We want our default t_timeout to be at least 25 ms: on h743 with a 10 ns
+    // t_i2cclk this yields 1219.7 (1220); on g031, this is 195.88 (196). Note
+    // that these numbers make assumptions about the system's clocking and
+    // clock tree configuration; TODO.
+    //
+    if #[cfg(any(feature = "h743", feature = "h753"))] {
+        const I2CCLK_KHZ: u32 = 100_000;
+        const DEFAULT_TIMEOUTA: u16 = 1220;
+    } else if #[cfg(any(feature = "g030", feature = "g031"))] {
+        const I2CCLK_KHZ: u32 = 16_000;
+        const DEFAULT_TIMEOUTA: u16 = 196;
+    } else {
+        compile_error!("unknown STM32xx variant");
+    }
+}
+
+/// Returns the TIMEOUTA value for an SCL timeout of at least `ms`
+/// milliseconds (clamped to [`MAX_SCL_TIMEOUT_MS`]).
+fn timeouta(ms: u32) -> u16 {
+    let ms = ms.clamp(1, MAX_SCL_TIMEOUT_MS);
+    let timeouta = (ms * I2CCLK_KHZ).div_ceil(2048) - 1;
+
+    // TIMEOUTA is a 12-bit field
+    timeouta.min(0xfff) as u16
+}
+
+/// Folds `byte` into a running SMBus Packet Error Code.
+///
+/// The PEC is a CRC-8 with no initial value or final XOR, so folding in one
+/// byte is equivalent to computing the PEC of the running value XOR'd with it.
+fn pec_update(pec: u8, byte: u8) -> u8 {
+    smbus_pec::pec(&[pec ^ byte])
+}
+
+///
+/// When the system is either reset without power loss (e.g., due to an SP
+/// upgrade) or I2C is preempted longer than the 25ms I2C timeout (e.g., due
+/// to a large process panicking and being dumped by jefe), I2C can be in an
+/// arbitrary state with respect to the bus -- and we can therefore come to
+/// life with a transaction already in flight.  It is very important that we
+/// abort any such transaction:  failure to do so will result in our first I2C
+/// transaction being corrupted.  (And because our first I2C transaction on SP
+/// boot may well be to disable segments on a mux, this can result in nearly
+/// arbitrary mayhem down the road!)  To do this, we engage in the
+/// time-honored[0] tradition of "clocking through the problem":  wiggling SCL
+/// until we see SDA high, and then pulling SDA low and releasing SCL to
+/// indicate a STOP condition.  (Note that we need to do this up to 9 times to
+/// assure that we have clocked through the entire transaction.)  Our assumption
+/// is that if SCL is being stretched by an errant target, it has been already
+/// stretched beyond our timeout (25ms); if this is the case, us trying to
+/// wiggle SCL here won't actually wiggle SCL -- but unless such a device is
+/// isolated to a segment on a mux that we can reset, nothing will in fact help.
+///
+/// [0] Analog Devices. AN-686: Implementing an I2C Reset. 2003.
+///
+/// This leaves both pins configured as open-drain GPIO outputs; it is up to
+/// the caller to return them to their I2C alternate function.  Returns `true`
+/// if SDA was released.
+pub fn release_sda(
+    sys: &sys_api::Sys,
+    scl: sys_api::PinSet,
+    sda: sys_api::PinSet,
+) -> bool {
+    sys.gpio_set(scl);
+    sys.gpio_configure_output(
+        scl,
+        sys_api::OutputType::OpenDrain,
+        sys_api::Speed::Low,
+        sys_api::Pull::None,
+    );
+    sys.gpio_configure_input(sda, sys_api::Pull::None);
+
+    let mut pulses = 0;
+
+    while sys.gpio_read(sda) == 0 && pulses < 9 {
+        //
+        // SDA is low -- someone is holding it down: give SCL a wiggle to try
+        // to shake them.  Note that we don't sleep here:  we are relying on
+        // the fact that communicating to the GPIO task is going to take
+        // longer than our minimum SCL pulse.  (Which, on a 400 MHz H753, is
+        // on the order of ~15 usecs -- yielding a cycle time of ~30 usecs or
+        // ~33 KHz.)
+        //
+        sys.gpio_reset(scl);
+        sys.gpio_set(scl);
+        pulses += 1;
+    }
+
+    let released = sys.gpio_read(sda) != 0;
+    ringbuf_entry!(Trace::ReleaseSda { pulses, released });
+
+    //
+    // Now denote a STOP condition: pull the clock down, then pull SDA down,
+    // then release SCL and finally release SDA.
+    //
+    sys.gpio_reset(scl);
+    sys.gpio_reset(sda);
+    sys.gpio_configure_output(
+        sda,
+        sys_api::OutputType::OpenDrain,
+        sys_api::Speed::Low,
+        sys_api::Pull::None,
+    );
+    sys.gpio_set(scl);
+    sys.gpio_set(sda);
+
+    released
+}
 
 impl I2cMux<'_> {
+    /// If this mux has `verify_segment` set, reads back its control register
+    /// with `read` and checks that the bits in `mask` match `expected`,
+    /// returning [`drv_i2c_api::ResponseCode::SegmentMismatch`] if they don't.
+    fn verify_control(
+        &self,
+        expected: u8,
+        mask: u8,
+        read: impl FnOnce() -> Result<u8, drv_i2c_api::ResponseCode>,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        if !self.verify_segment {
+            return Ok(());
+        }
+
+        let found = read()?;
+        let address = self.address;
+
+        if found & mask == expected & mask {
+            ringbuf_entry!(Trace::SegmentVerified {
+                address,
+                control: found
+            });
+            Ok(())
+        } else {
+            ringbuf_entry!(Trace::SegmentMismatch {
+                address,
+                expected,
+                found
+            });
+            Err(drv_i2c_api::ResponseCode::SegmentMismatch)
+        }
+    }
+
+    /// Reads the control register of a mux that has no other register, such
+    /// that any read (without a register address) is of it.
+    fn read_control(
+        &self,
+        controller: &I2cController<'_>,
+        ctrl: &I2cControl,
+    ) -> Result<u8, drv_i2c_api::ResponseCode> {
+        let mut control = 0;
+
+        match controller.write_read(
+            self.address,
+            0,
+            |_| None,
+            ReadLength::Fixed(1),
+            |_, byte| {
+                control = byte;
+                Some(())
+            },
+            ctrl,
+            None,
+        ) {
+            Err(code) => Err(self.error_code(code)),
+            _ => Ok(control),
+        }
+    }
+
     /// A convenience routine to translate an error induced by in-band
     /// management into one that can be returned to a caller
     fn error_code(
@@ -266,12 +672,52 @@ impl I2cController<'_> {
         sys.leave_reset(self.peripheral);
     }
 
+    /// Powers down the controller between uses by gating its clock, to save
+    /// power while idle.  Bring it back with [`Self::reenable`].
+    ///
+    /// Any transaction must have completed (i.e., `write_read` must have
+    /// returned) before calling this; the controller is stopped wherever it
+    /// happens to be.
+    pub fn disable(&self, sys: &sys_api::Sys) {
+        ringbuf_entry!(Trace::PowerDown(self.controller));
+
+        // Stop the peripheral before pulling its clock out from under it.
+        self.registers.cr1.modify(|_, w| w.pe().clear_bit());
+        sys.disable_clock(self.peripheral);
+    }
+
+    /// Powers the controller back up after [`Self::disable`].
+    ///
+    /// We don't rely on the controller retaining any state while its clock
+    /// was gated: this reconfigures it from scratch, just as [`Self::enable`]
+    /// followed by [`Self::configure`] does at startup.  This is for use as an
+    /// initiator; a target must instead be reconfigured by its next
+    /// `operate_as_target`.
+    pub fn reenable(&self, sys: &sys_api::Sys) {
+        ringbuf_entry!(Trace::PowerUp(self.controller));
+
+        self.enable(sys);
+        self.configure();
+    }
+
     fn configure_timing(&self, i2c: &RegisterBlock) {
-        // TODO: this configuration mechanism is getting increasingly hairy. It
-        // generally assumes that a given processor runs at a given speed on all
-        // boards, which is not at all true. As of recently it's now doing a
-        // hybrid of "CPU model" and "board name" sensing. Should move to
-        // configuration!
+        if let Some(t) = self.timing {
+            #[rustfmt::skip]
+            i2c.timingr.write(|w| { w
+                .presc().bits(t.presc)
+                .sclh().bits(t.sclh)
+                .scll().bits(t.scll)
+                .scldel().bits(t.scldel)
+                .sdadel().bits(t.sdadel)
+            });
+            return;
+        }
+
+        // Absent explicit configuration, we fall back on defaults that
+        // generally assume that a given processor runs at a given speed on all
+        // boards, which is not at all true; they've since grown a hybrid of
+        // "CPU model" and "board name" sensing.  Boards that differ should
+        // specify `timing` for their controllers in the I2C configuration.
         cfg_if::cfg_if! {
             if #[cfg(any(feature = "h743", feature = "h753"))] {
                 cfg_if::cfg_if! {
@@ -360,33 +806,24 @@ impl I2cController<'_> {
     }
 
     fn configure_timeouts(&self, i2c: &RegisterBlock) {
-        cfg_if::cfg_if! {
-            //
-            // The timeout value is defined to be:
-            //
-            //   t_timeout = (TIMEOUTA + 1) x 2048 x t_i2cclk
-            //
-            // We want our t_timeout to be at least 25 ms: on h743 with a 10 ns
-            // t_i2cclk this yields 1219.7 (1220); on g031, this is 195.88
-            // (196). Note that these numbers make assumptions about the
-            // system's clocking and clock tree configuration; TODO.
-            //
-            if #[cfg(any(feature = "h743", feature = "h753"))] {
-                i2c.timeoutr.write(|w| { w
-                    .timouten().set_bit()           // Enable SCL timeout
-                    .timeouta().bits(1220)          // Timeout value
-                    .tidle().clear_bit()            // Want SCL, not IDLE
-                });
-            } else if #[cfg(any(feature = "g030", feature = "g031"))] {
-                i2c.timeoutr.write(|w| { w
-                    .timouten().set_bit()           // Enable SCL timeout
-                    .timeouta().bits(196)           // Timeout value
-                    .tidle().clear_bit()            // Want SCL, not IDLE
-                });
-            } else {
-                compile_error!("unknown STM32xx variant");
-            }
-        }
+        self.set_timeouta(i2c, DEFAULT_TIMEOUTA);
+    }
+
+    ///
+    /// Sets the SCL timeout.  TIMEOUTA can only be written when the timeout
+    /// is disabled, so we disable it, write the new value, and then enable
+    /// it again.
+    ///
+    fn set_timeouta(&self, i2c: &RegisterBlock, timeouta: u16) {
+        i2c.timeoutr.modify(|_, w| w.timouten().clear_bit());
+
+        #[rustfmt::skip]
+        i2c.timeoutr.modify(|_, w| { w
+            .timeouta().bits(timeouta)      // Timeout value
+            .tidle().clear_bit()            // Want SCL, not IDLE
+        });
+
+        i2c.timeoutr.modify(|_, w| w.timouten().set_bit());
     }
 
     pub fn configure(&self) {
@@ -414,6 +851,32 @@ impl I2cController<'_> {
         i2c.cr1.modify(|_, w| w.pe().set_bit());
     }
 
+    /// Recover a bus that has been left wedged by a target holding SDA low,
+    /// as indicated by a transaction failing with `ControllerBusy` or
+    /// `BusLocked`.
+    ///
+    /// This requires the SCL and SDA pins for the port in use, as found in
+    /// [`I2cPins`]: we temporarily take them over as GPIOs to clock out the
+    /// target (see [`release_sda`]), return them to their I2C alternate
+    /// function, and then reset the controller.
+    pub fn recover_bus(&self, sys: &sys_api::Sys, pins: &I2cPins) {
+        ringbuf_entry!(Trace::RecoverBus(pins.controller, pins.port));
+
+        release_sda(sys, pins.scl, pins.sda);
+
+        for gpio_pin in [pins.scl, pins.sda] {
+            sys.gpio_configure_alternate(
+                gpio_pin,
+                sys_api::OutputType::OpenDrain,
+                sys_api::Speed::Low,
+                sys_api::Pull::None,
+                pins.function,
+            );
+        }
+
+        self.reset();
+    }
+
     /// Reset the controller, as per the datasheet: clear PE, wait for it
     /// to become 0, and set it.
     pub fn reset(&self) {
@@ -446,24 +909,23 @@ impl I2cController<'_> {
     /// conditions should generally result in the controller being reset.
     ///
     fn check_errors(&self, isr: &Isr) -> Result<(), drv_i2c_api::ResponseCode> {
-        let i2c = self.registers;
-
-        if isr.arlo().is_lost() {
-            i2c.icr.write(|w| w.arlocf().set_bit());
-            return Err(drv_i2c_api::ResponseCode::BusReset);
-        }
+        check_errors(self.registers, isr.bits())
+    }
 
-        if isr.berr().is_error() {
-            i2c.icr.write(|w| w.berrcf().set_bit());
-            return Err(drv_i2c_api::ResponseCode::BusError);
-        }
+    ///
+    /// Captures the registers that describe the controller's state (see
+    /// [`I2cRegSnapshot`]).  This only reads registers, none of which are
+    /// cleared on read, and so is safe to call at any time.
+    ///
+    pub fn snapshot(&self) -> I2cRegSnapshot {
+        let i2c = self.registers;
 
-        if isr.timeout().is_timeout() {
-            i2c.icr.write(|w| w.timoutcf().set_bit());
-            return Err(drv_i2c_api::ResponseCode::BusLocked);
+        I2cRegSnapshot {
+            isr: i2c.isr.read().bits(),
+            cr1: i2c.cr1.read().bits(),
+            cr2: i2c.cr2.read().bits(),
+            timeoutr: i2c.timeoutr.read().bits(),
         }
-
-        Ok(())
     }
 
     ///
@@ -595,12 +1057,149 @@ impl I2cController<'_> {
 
     /// Perform a write to and then a read from the specified device.  Either
     /// the write length or the read length can be zero, but one of these must
-    /// be non-zero.  Additionally, both lengths must be less than 256 bytes:
-    /// the device can support longer buffers, and the implementation could
-    /// be extended in the future to allow them.
+    /// be non-zero.  Either length may exceed the 255 bytes that the
+    /// controller can transfer at once (NBYTES), in which case the transfer
+    /// is broken up into chunks of at most 255 bytes using the controller's
+    /// RELOAD mechanism; this is invisible on the bus.
+    ///
+    /// For a [`ReadLength::AtMost`] read, a length byte above the cap results
+    /// in [`drv_i2c_api::ResponseCode::TooMuchData`] after only the capped
+    /// number of bytes have been read (and passed to `putbyte`), with a
+    /// `LengthCapped` entry in the ring buffer recording the length the
+    /// device claimed.
+    ///
+    /// For a [`ReadLength::SmbusBlock`] read, a count of zero (with no PEC to
+    /// follow) ends the transfer after one more byte, which is NACK'd and
+    /// discarded.  A count that exceeds what `putbyte` will accept results
+    /// in [`drv_i2c_api::ResponseCode::TooMuchData`] once the remaining
+    /// bytes have been clocked out.  (The same is true of any read
+    /// that `putbyte` can't keep up with; in either case, the number of bytes
+    /// accepted and the number the device sent are recorded in the ring
+    /// buffer.)  A PEC mismatch results in
+    /// [`drv_i2c_api::ResponseCode::BadChecksum`].  (SMBus does not define a
+    /// PEC for 10-bit addresses, so asking for one with such an address
+    /// results in [`drv_i2c_api::ResponseCode::BadArg`].)
+    ///
+    /// A [`ReadLength::AtMost`] cap of zero results in
+    /// [`drv_i2c_api::ResponseCode::BadArg`].
+    ///
+    /// If `timeout` is specified, the SCL timeout is set to it for the
+    /// duration of this transaction, and restored to the default afterwards.
+    ///
+    /// If we lose arbitration, the transaction is retried from the start (up
+    /// to [`ARBITRATION_RETRIES`] times) before we give up and return
+    /// [`drv_i2c_api::ResponseCode::BusReset`].  Arbitration can only be lost
+    /// to another controller, so this assumes a multi-master bus -- on the
+    /// single-master buses that are our norm, arbitration loss instead
+    /// indicates a glitch or a misbehaving device, for which retrying is
+    /// harmless (and if it persists, the caller's reset still follows).  Note
+    /// that a retry repeats any write that was already (partially) sent, and
+    /// that `putbyte` sees a retried read start from position 0 again.
+    #[allow(clippy::too_many_arguments)]
     pub fn write_read(
         &self,
-        addr: u8,
+        addr: impl Into<I2cAddress>,
+        wlen: usize,
+        getbyte: impl Fn(usize) -> Option<u8>,
+        rlen: ReadLength,
+        mut putbyte: impl FnMut(usize, u8) -> Option<()>,
+        ctrl: &I2cControl,
+        timeout: Option<I2cSclTimeout>,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        let i2c = self.registers;
+        let addr = addr.into();
+
+        if let Some(I2cSclTimeout(ms)) = timeout {
+            //
+            // Wait for any previous transaction to be done before we change
+            // the timeout out from under it.
+            //
+            count_error(self.wait_until_notbusy())?;
+
+            let timeouta = timeouta(ms);
+            ringbuf_entry!(Trace::SclTimeout { ms, timeouta });
+            self.set_timeouta(i2c, timeouta);
+        }
+
+        let mut attempt = 0;
+        let rval = loop {
+            let rval = self.write_read_inner(
+                addr,
+                wlen,
+                &getbyte,
+                rlen,
+                &mut putbyte,
+                ctrl,
+            );
+
+            //
+            // Only arbitration loss results in BusReset.  When we lose
+            // arbitration, the controller has already let go of the bus
+            // (and `check_errors` has cleared ARLO); `write_read_inner`
+            // waits for the winner's STOP before starting again.
+            //
+            match rval {
+                Err(drv_i2c_api::ResponseCode::BusReset)
+                    if attempt < ARBITRATION_RETRIES =>
+                {
+                    attempt += 1;
+                    ringbuf_entry!(Trace::ArbitrationRetry(attempt));
+                }
+                _ => break rval,
+            }
+        };
+
+        if timeout.is_some() {
+            self.set_timeouta(i2c, DEFAULT_TIMEOUTA);
+        }
+
+        count_error(rval)
+    }
+
+    /// Perform a single write to the specified device of `first` bytes from
+    /// `getfirst` followed by `second` bytes from `getsecond`, with one START
+    /// and one STOP: on the bus this is indistinguishable from a write of the
+    /// two buffers concatenated.  This is for devices that take (say) a
+    /// command block followed by a data block in one transaction, when the
+    /// two come from different places.
+    ///
+    /// Either length may be zero, but not both.  The combined length may
+    /// exceed 255 bytes, in which case RELOAD is used as for
+    /// [`Self::write_read`] (including across the boundary between the two
+    /// buffers).  A NACK is reported as it would be by `write_read`, at its
+    /// position in the combined write.
+    pub fn write_write(
+        &self,
+        addr: impl Into<I2cAddress>,
+        first: usize,
+        getfirst: impl Fn(usize) -> Option<u8>,
+        second: usize,
+        getsecond: impl Fn(usize) -> Option<u8>,
+        ctrl: &I2cControl,
+        timeout: Option<I2cSclTimeout>,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        ringbuf_entry!(Trace::WriteWrite { first, second });
+
+        self.write_read(
+            addr,
+            first + second,
+            |pos| {
+                if pos < first {
+                    getfirst(pos)
+                } else {
+                    getsecond(pos - first)
+                }
+            },
+            ReadLength::Fixed(0),
+            |_, _| Some(()),
+            ctrl,
+            timeout,
+        )
+    }
+
+    fn write_read_inner(
+        &self,
+        addr: I2cAddress,
         wlen: usize,
         getbyte: impl Fn(usize) -> Option<u8>,
         mut rlen: ReadLength,
@@ -609,40 +1208,79 @@ impl I2cController<'_> {
     ) -> Result<(), drv_i2c_api::ResponseCode> {
         // Assert our preconditions as described above
         assert!(wlen > 0 || rlen != ReadLength::Fixed(0));
-        assert!(wlen <= 255);
-
-        if let ReadLength::Fixed(rlen) = rlen {
-            assert!(rlen <= 255);
-        }
 
         let i2c = self.registers;
         let notification = self.notification;
+        let (add10, sadd) = (addr.add10(), addr.sadd());
+
+        if let I2cAddress::TenBit(addr) = addr {
+            ringbuf_entry!(Trace::TenBitAddress(addr));
+        }
+
+        // For SMBus block reads with a PEC, we accumulate the PEC over every
+        // byte on the wire (including address bytes) as we go.
+        let check_pec = rlen == ReadLength::SmbusBlock { pec: true };
+        let mut pec = 0;
+
+        if check_pec && add10 {
+            return Err(drv_i2c_api::ResponseCode::BadArg);
+        }
+
+        // A cap of 0 would leave us nothing to NACK after the length byte.
+        if rlen == ReadLength::AtMost(0) {
+            return Err(drv_i2c_api::ResponseCode::BadArg);
+        }
 
         self.wait_until_notbusy()?;
 
         if wlen > 0 {
+            pec = pec_update(pec, sadd as u8);
+
+            let nbytes = wlen.min(255);
+
             #[rustfmt::skip]
             i2c.cr2.modify(|_, w| { w
-                .nbytes().bits(wlen as u8)
+                .nbytes().bits(nbytes as u8)
                 .autoend().clear_bit()
-                .reload().clear_bit()
-                .add10().clear_bit()
-                .sadd().bits((addr << 1).into())
+                .reload().bit(wlen > nbytes)
+                .add10().bit(add10)
+                .sadd().bits(sadd)
                 .rd_wrn().clear_bit()
                 .start().set_bit()
             });
 
             let mut pos = 0;
+            let mut chunk_end = nbytes;
 
             while pos < wlen {
+                if pos == chunk_end {
+                    // We've sent a full chunk, but have more to go.
+                    self.wait_for_reload(ctrl)?;
+                    chunk_end += self.reload(pos, wlen - pos);
+                }
+
                 loop {
                     let isr = i2c.isr.read();
                     ringbuf_entry!(Trace::Write(Register::ISR, isr.bits()));
 
                     self.check_errors(&isr)?;
 
+                    // Check NACKF before TXIS: an absent device NACKs its
+                    // address, after which TXIS will never be set, so this
+                    // is what lets a probe of missing hardware fail as soon
+                    // as the NACK interrupt arrives. A present device ACKs
+                    // its address and never sets NACKF here before TXIS, so
+                    // this ordering doesn't change anything for it.
                     if isr.nackf().is_nack() {
+                        // Nothing has been sent before the first TXIS, so a
+                        // NACK at `pos == 0` can only be for the address.
+                        if pos == 0 {
+                            ringbuf_entry!(Trace::AddressNack(addr));
+                        } else {
+                            ringbuf_entry!(Trace::WriteNack { pos });
+                        }
                         i2c.icr.write(|w| w.nackcf().set_bit());
+                        self.flush_tx();
                         return Err(drv_i2c_api::ResponseCode::NoDevice);
                     }
 
@@ -660,6 +1298,7 @@ impl I2cController<'_> {
 
                 // And send it!
                 i2c.txdr.write(|w| w.txdata().bits(byte));
+                pec = pec_update(pec, byte);
                 pos += 1;
             }
 
@@ -672,7 +1311,9 @@ impl I2cController<'_> {
                 self.check_errors(&isr)?;
 
                 if isr.nackf().is_nack() {
+                    ringbuf_entry!(Trace::WriteNack { pos: wlen });
                     i2c.icr.write(|w| w.nackcf().set_bit());
+                    self.flush_tx();
                     return Err(drv_i2c_api::ResponseCode::NoRegister);
                 }
 
@@ -685,23 +1326,37 @@ impl I2cController<'_> {
             }
         }
 
-        let mut overrun = false;
+        // If `putbyte` refused a byte, the number of bytes it had accepted
+        let mut overrun = None;
+        // Set if the device's length byte exceeded our `AtMost` cap
+        let mut capped = false;
+        let mut pec_error = false;
 
         if rlen != ReadLength::Fixed(0) {
+            pec = pec_update(pec, sadd as u8 | 1);
+
+            // For variable-length reads, the length is at most 255 (it's a
+            // byte on the wire), so we never need to reload once we know it.
+            let mut chunk_end = usize::MAX;
+
             //
             // If we have both a write and a read, we deliberately do not send
             // a STOP between them to force the RESTART (many devices do not
             // permit a STOP between a register address write and a subsequent
             // read).
             //
+
             if let ReadLength::Fixed(rlen) = rlen {
+                let nbytes = rlen.min(255);
+                chunk_end = nbytes;
+
                 #[rustfmt::skip]
                 i2c.cr2.modify(|_, w| { w
-                    .nbytes().bits(rlen as u8)
+                    .nbytes().bits(nbytes as u8)
                     .autoend().clear_bit()
-                    .reload().clear_bit()
-                    .add10().clear_bit()
-                    .sadd().bits((addr << 1).into())
+                    .reload().bit(rlen > nbytes)
+                    .add10().bit(add10)
+                    .sadd().bits(sadd)
                     .rd_wrn().set_bit()
                     .start().set_bit()
                 });
@@ -711,8 +1366,8 @@ impl I2cController<'_> {
                     .nbytes().bits(1)
                     .autoend().clear_bit()
                     .reload().set_bit()
-                    .add10().clear_bit()
-                    .sadd().bits((addr << 1).into())
+                    .add10().bit(add10)
+                    .sadd().bits(sadd)
                     .rd_wrn().set_bit()
                     .start().set_bit()
                 });
@@ -736,7 +1391,10 @@ impl I2cController<'_> {
 
                     self.check_errors(&isr)?;
 
+                    // As the receiver, the only thing we can be NACK'd on
+                    // is the (read) address.
                     if isr.nackf().is_nack() {
+                        ringbuf_entry!(Trace::AddressNack(addr));
                         i2c.icr.write(|w| w.nackcf().set_bit());
                         return Err(drv_i2c_api::ResponseCode::NoDevice);
                     }
@@ -749,6 +1407,36 @@ impl I2cController<'_> {
                 // Read it!
                 let byte: u8 = i2c.rxdr.read().rxdata().bits();
 
+                if let ReadLength::AtMost(cap) = rlen {
+                    let cap = u8::try_from(cap).unwrap_or(u8::MAX);
+
+                    if byte > cap {
+                        ringbuf_entry!(Trace::LengthCapped {
+                            reported: byte,
+                            cap
+                        });
+                        capped = true;
+
+                        //
+                        // Read only as much as we're willing to.  Clearing
+                        // RELOAD makes the controller NACK the last byte of
+                        // the (shortened) transfer, telling the device to
+                        // stop sending, after which we send our STOP as
+                        // usual.  (We've rejected a cap of 0 above.)
+                        //
+                        #[rustfmt::skip]
+                        i2c.cr2.modify(|_, w| { w
+                            .nbytes().bits(cap)
+                            .reload().clear_bit()
+                        });
+
+                        rlen = ReadLength::Fixed(cap.into());
+                        continue;
+                    }
+
+                    rlen = ReadLength::Variable;
+                }
+
                 if rlen == ReadLength::Variable {
                     #[rustfmt::skip]
                     i2c.cr2.modify(|_, w| { w
@@ -760,16 +1448,84 @@ impl I2cController<'_> {
                     continue;
                 }
 
-                if !overrun && putbyte(pos, byte).is_none() {
+                if let ReadLength::SmbusBlock { pec: has_pec } = rlen {
+                    ringbuf_entry!(Trace::BlockCount(byte));
+                    pec = pec_update(pec, byte);
+
+                    let remaining = usize::from(byte) + usize::from(has_pec);
+
+                    if remaining == 0 {
+                        // There's nothing more to read; NACK and STOP.
+                        self.end_read(ctrl)?;
+                        break;
+                    }
+
+                    //
+                    // A count of 255 plus a PEC is one more byte than we can
+                    // program into NBYTES, which requires a reload.
+                    //
+                    let nbytes = remaining.min(255);
+                    chunk_end = nbytes;
+
+                    #[rustfmt::skip]
+                    i2c.cr2.modify(|_, w| { w
+                        .nbytes().bits(nbytes as u8)
+                        .reload().bit(remaining > nbytes)
+                    });
+
+                    rlen = ReadLength::Fixed(remaining);
+                    continue;
+                }
+
+                if check_pec && rlen == ReadLength::Fixed(pos + 1) {
+                    //
+                    // This is the trailing PEC byte, which is for us rather
+                    // than our caller.
+                    //
+                    if byte != pec {
+                        ringbuf_entry!(Trace::PecMismatch {
+                            expected: pec,
+                            actual: byte
+                        });
+                        pec_error = true;
+                    }
+
+                    pos += 1;
+                    continue;
+                }
+
+                pec = pec_update(pec, byte);
+
+                if overrun.is_none() && putbyte(pos, byte).is_none() {
                     //
                     // If we're unable to accept what we just read, we need to
                     // keep reading to complete the transfer -- but we will
                     // not call putbyte again and we will return failure.
                     //
-                    overrun = true;
+                    overrun = Some(pos);
                 }
 
                 pos += 1;
+
+                if let ReadLength::Fixed(rlen) = rlen {
+                    if pos == chunk_end && pos < rlen {
+                        // That was the last byte of a full chunk, but there's
+                        // more to come.
+                        self.wait_for_reload(ctrl)?;
+                        chunk_end += self.reload(pos, rlen - pos);
+                    }
+                }
+            }
+
+            if let Some(accepted) = overrun {
+                //
+                // Record how much the device actually sent (not counting
+                // any PEC), to help make sense of a bogus length byte.
+                //
+                ringbuf_entry!(Trace::Overrun {
+                    accepted,
+                    total: pos - usize::from(check_pec),
+                });
             }
 
             // All done; now block until our transfer is complete...
@@ -794,13 +1550,123 @@ impl I2cController<'_> {
         //
         i2c.cr2.modify(|_, w| w.stop().set_bit());
 
-        if overrun {
+        if overrun.is_some() || capped {
             Err(drv_i2c_api::ResponseCode::TooMuchData)
+        } else if pec_error {
+            Err(drv_i2c_api::ResponseCode::BadChecksum)
         } else {
             Ok(())
         }
     }
 
+    ///
+    /// Flushes TXDR, discarding any byte that we have loaded into it but that
+    /// hasn't made it into the shift register.  Because we load TXDR as soon
+    /// as it's empty, a NACK in the middle of a write leaves the next byte
+    /// behind, and the controller would otherwise send that stale byte as the
+    /// first byte of the next write.  This should therefore be called
+    /// whenever a NACK cuts a write short.  (Setting TXE is harmless if TXDR
+    /// is already empty.)
+    ///
+    fn flush_tx(&self) {
+        self.registers.isr.modify(|_, w| w.txe().set_bit());
+    }
+
+    ///
+    /// Waits for a chunk of a transfer to complete with RELOAD set (that is,
+    /// for TCR), at which point the controller is stretching the clock until
+    /// we program the next chunk with [`Self::reload`].
+    ///
+    fn wait_for_reload(
+        &self,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        let i2c = self.registers;
+
+        loop {
+            let isr = i2c.isr.read();
+            ringbuf_entry!(Trace::ReloadWait(Register::ISR, isr.bits()));
+
+            self.check_errors(&isr)?;
+
+            if isr.nackf().is_nack() {
+                i2c.icr.write(|w| w.nackcf().set_bit());
+                self.flush_tx();
+                return Err(drv_i2c_api::ResponseCode::NoDevice);
+            }
+
+            if isr.tcr().bit_is_set() {
+                return Ok(());
+            }
+
+            self.wfi(ctrl)?;
+            (ctrl.enable)(self.notification);
+        }
+    }
+
+    ///
+    /// Ends a read whose length byte leaves nothing more to read.  The
+    /// controller doesn't NACK a byte that it receives with RELOAD set, and
+    /// it stretches the clock after the length byte until NBYTES is set to a
+    /// non-zero value, so we can neither NACK the length byte itself nor
+    /// simply stop.  Instead, we program one more byte with RELOAD clear:
+    /// the controller NACKs it, as it does the last byte of any read, which
+    /// tells the device to stop sending.  We discard that byte, and the
+    /// caller then waits for TC and sends the STOP as usual.  On the wire,
+    /// this is the length byte (ACK), one byte (NACK), and STOP.
+    ///
+    fn end_read(
+        &self,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        let i2c = self.registers;
+
+        #[rustfmt::skip]
+        i2c.cr2.modify(|_, w| { w
+            .nbytes().bits(1)
+            .reload().clear_bit()
+        });
+
+        loop {
+            self.wfi(ctrl)?;
+            (ctrl.enable)(self.notification);
+
+            let isr = i2c.isr.read();
+            ringbuf_entry!(Trace::Read(Register::ISR, isr.bits()));
+
+            self.check_errors(&isr)?;
+
+            if !isr.rxne().is_empty() {
+                break;
+            }
+        }
+
+        let _ = i2c.rxdr.read();
+
+        Ok(())
+    }
+
+    ///
+    /// Programs the next chunk of a transfer that has `remaining` bytes left
+    /// (starting at `pos`), returning the size of the chunk.  RELOAD is left
+    /// set only if this chunk isn't the last: in particular, if `remaining`
+    /// is exactly 255, it must be cleared, or we would wait for a TCR rather
+    /// than a TC at the end of the transfer.
+    ///
+    fn reload(&self, pos: usize, remaining: usize) -> usize {
+        let nbytes = remaining.min(255);
+        ringbuf_entry!(Trace::Reload { pos, remaining });
+
+        // Writing a non-zero NBYTES clears TCR and releases the clock.
+        #[rustfmt::skip]
+        self.registers.cr2.modify(|_, w| { w
+            .nbytes().bits(nbytes as u8)
+            .reload().bit(remaining > nbytes)
+        });
+
+        nbytes
+    }
+
     ///
     /// Regrettably, some devices insist on special sequences to be sent to
     /// unlock functionality -- effectively a Konami Code for an I2C device.
@@ -819,6 +1685,56 @@ impl I2cController<'_> {
         addr: u8,
         ops: &[I2cKonamiCode],
         ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        count_error(self.send_konami_code_inner(addr, ops, ctrl))
+    }
+
+    ///
+    /// Like [`Self::send_konami_code`], but if the device NACKs the sequence
+    /// it is sent again, after sleeping for `delay_ms`, up to `retries` times
+    /// (capped at [`MAX_KONAMI_RETRIES`]).  Each attempt is recorded in the
+    /// ringbuf.  Any other error is returned without a retry, as is the
+    /// NACK from the last attempt.
+    ///
+    /// This exists for the unlock path of quirky devices -- the MAX7358
+    /// will occasionally NACK the first unlock after power-up, but not the
+    /// second -- and should not be used to paper over a device that NACKs
+    /// persistently:  the cap keeps such a failure from being hidden for
+    /// long, and a NACK that survives the retries is still an error.
+    ///
+    pub fn send_konami_code_with_retry(
+        &self,
+        addr: u8,
+        ops: &[I2cKonamiCode],
+        ctrl: &I2cControl,
+        retries: u8,
+        delay_ms: u64,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        let retries = retries.min(MAX_KONAMI_RETRIES);
+        let mut attempt = 0;
+
+        let rval = loop {
+            ringbuf_entry!(Trace::KonamiAttempt(attempt));
+
+            match self.send_konami_code_inner(addr, ops, ctrl) {
+                Err(drv_i2c_api::ResponseCode::NoRegister)
+                    if attempt < retries =>
+                {
+                    attempt += 1;
+                    hl::sleep_for(delay_ms);
+                }
+                rval => break rval,
+            }
+        };
+
+        count_error(rval)
+    }
+
+    fn send_konami_code_inner(
+        &self,
+        addr: u8,
+        ops: &[I2cKonamiCode],
+        ctrl: &I2cControl,
     ) -> Result<(), drv_i2c_api::ResponseCode> {
         let i2c = self.registers;
         let notification = self.notification;
@@ -855,6 +1771,7 @@ impl I2cController<'_> {
 
                 if isr.nackf().is_nack() {
                     i2c.icr.write(|w| w.nackcf().set_bit());
+                    self.flush_tx();
                     return Err(drv_i2c_api::ResponseCode::NoRegister);
                 }
 
@@ -875,6 +1792,129 @@ impl I2cController<'_> {
         Ok(())
     }
 
+    ///
+    /// Probes a single 7-bit address by issuing a zero-byte write, returning
+    /// `Ok(())` if the device ACK'd and [`ResponseCode::NoDevice`] if it did
+    /// not.
+    ///
+    /// As with [`Self::scan`], the probe is ended with a STOP that we wait
+    /// to see on the bus, so a NACK leaves the bus idle and the controller
+    /// ready for another transaction (e.g. a retry of this probe).  The
+    /// caller is likewise responsible for any mux configuration.
+    ///
+    /// [`ResponseCode::NoDevice`]: drv_i2c_api::ResponseCode::NoDevice
+    ///
+    pub fn probe(
+        &self,
+        addr: u8,
+        ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        self.wait_until_notbusy()?;
+
+        if self.probe_address(addr, ctrl)? {
+            ringbuf_entry!(Trace::ProbeAck(addr));
+            Ok(())
+        } else {
+            ringbuf_entry!(Trace::ProbeNack(addr));
+            Err(drv_i2c_api::ResponseCode::NoDevice)
+        }
+    }
+
+    ///
+    /// Scans the bus by issuing a zero-byte write to each 7-bit address,
+    /// returning a bitmap in which bit `n` is set if address `n` ACK'd.  The
+    /// reserved addresses (0x00-0x07 and 0x78-0x7f) are not probed, and are
+    /// therefore never reported as present.
+    ///
+    /// Each probe is ended with a STOP -- which the controller generates
+    /// itself on a NACK -- and we wait for that STOP to be seen on the bus
+    /// before moving on, so a missing device does not disturb the next probe.
+    ///
+    /// Note that this scans whatever is currently visible on the bus:  it is
+    /// up to the caller to have selected the port and, if the bus has muxes,
+    /// to have enabled the desired segment (or disabled all of them).  Any
+    /// muxes that are themselves visible will be reported as present.
+    ///
+    pub fn scan(
+        &self,
+        ctrl: &I2cControl,
+    ) -> Result<u128, drv_i2c_api::ResponseCode> {
+        let mut present = 0u128;
+
+        self.wait_until_notbusy()?;
+
+        for addr in 0x08u8..0x78 {
+            if self.probe_address(addr, ctrl)? {
+                ringbuf_entry!(Trace::ScanAck(addr));
+                present |= 1 << addr;
+            }
+        }
+
+        ringbuf_entry!(Trace::ScanDone {
+            found: present.count_ones() as u8
+        });
+
+        Ok(present)
+    }
+
+    ///
+    /// Issues a zero-byte write to `addr`, returning whether it was ACK'd.
+    /// The bus must be idle on entry; it is idle again on return.
+    ///
+    fn probe_address(
+        &self,
+        addr: u8,
+        ctrl: &I2cControl,
+    ) -> Result<bool, drv_i2c_api::ResponseCode> {
+        let i2c = self.registers;
+        let notification = self.notification;
+
+        #[rustfmt::skip]
+        i2c.cr2.modify(|_, w| { w
+            .nbytes().bits(0u8)
+            .autoend().clear_bit()
+            .reload().clear_bit()
+            .add10().clear_bit()
+            .sadd().bits((addr << 1).into())
+            .rd_wrn().clear_bit()
+            .start().set_bit()
+        });
+
+        let ack = loop {
+            let isr = i2c.isr.read();
+
+            self.check_errors(&isr)?;
+
+            if isr.nackf().is_nack() {
+                i2c.icr.write(|w| w.nackcf().set_bit());
+                self.flush_tx();
+                break false;
+            }
+
+            if isr.tc().is_complete() {
+                break true;
+            }
+
+            self.wfi(ctrl)?;
+            (ctrl.enable)(notification);
+        };
+
+        if ack {
+            i2c.cr2.modify(|_, w| w.stop().set_bit());
+        }
+
+        //
+        // Whether we sent the STOP or the controller sent it in response to
+        // the NACK, wait for it to complete before the next START.  (We
+        // don't enable the STOP interrupt, so we poll for the bus to go idle
+        // rather than waiting for STOPF.)
+        //
+        self.wait_until_notbusy()?;
+        i2c.icr.write(|w| w.stopcf().set_bit());
+
+        Ok(ack)
+    }
+
     fn configure_as_target(&self) {
         let i2c = self.registers;
 
@@ -914,9 +1954,69 @@ impl I2cController<'_> {
     pub fn operate_as_target(
         &self,
         ctrl: &I2cTargetControl,
+        initiate: impl FnMut(u8) -> bool,
+        rxbyte: impl FnMut(u8, u8),
+        txbyte: impl FnMut(u8) -> Option<u8>,
+    ) -> ! {
+        self.operate_as_target_inner(
+            ctrl,
+            false,
+            initiate,
+            rxbyte,
+            txbyte,
+            |_, _| {},
+        )
+    }
+
+    /// Operate as a target that uses SMBus Packet Error Checking.
+    ///
+    /// This is [`I2cController::operate_as_target`], except that a PEC is
+    /// accumulated over every byte of each transaction we respond to
+    /// (including the address bytes):
+    ///
+    /// - When the host writes to us, the last byte before the STOP is taken
+    ///   to be its PEC, and is checked rather than passed to `rxbyte`; the
+    ///   outcome is reported via `rxpec` (with the address and whether it
+    ///   matched).  To be able to do this, each byte is held back until the
+    ///   next one arrives -- and a byte that is followed by a repeated START
+    ///   is always data, as SMBus doesn't put a PEC on the write half of a
+    ///   combined transaction.  Note that this means every write to us must
+    ///   carry a PEC: a host that doesn't send one will see its last byte
+    ///   dropped (and reported as a mismatch).
+    ///
+    /// - When the host reads from us, the PEC is sent after the last byte
+    ///   returned by `txbyte` (that is, in place of the first `None`).
+    ///
+    /// The controller can compute and check the PEC in hardware, but only
+    /// with slave byte control (SBC) enabled and NBYTES programmed with the
+    /// length of the transfer (including the PEC) -- which, as a target, we
+    /// don't know ahead of time.  So we leave SBC off (as
+    /// `configure_as_target` does) and compute the PEC in software, as
+    /// [`I2cController::write_read`] does.  The cost of this is that a PEC
+    /// mismatch can't be NACK'd: by the time we know the byte was the PEC,
+    /// it has been ACK'd, and so it is up to `rxpec` to discard the
+    /// transaction.
+    pub fn operate_as_smbus_target(
+        &self,
+        ctrl: &I2cTargetControl,
+        initiate: impl FnMut(u8) -> bool,
+        rxbyte: impl FnMut(u8, u8),
+        txbyte: impl FnMut(u8) -> Option<u8>,
+        rxpec: impl FnMut(u8, bool),
+    ) -> ! {
+        self.operate_as_target_inner(
+            ctrl, true, initiate, rxbyte, txbyte, rxpec,
+        )
+    }
+
+    fn operate_as_target_inner(
+        &self,
+        ctrl: &I2cTargetControl,
+        smbus_pec: bool,
         mut initiate: impl FnMut(u8) -> bool,
         mut rxbyte: impl FnMut(u8, u8),
         mut txbyte: impl FnMut(u8) -> Option<u8>,
+        mut rxpec: impl FnMut(u8, bool),
     ) -> ! {
         // Note: configure_as_target toggles the CR1.PE bit, which has the side
         // effect of clearing all flags.
@@ -926,9 +2026,10 @@ impl I2cController<'_> {
         let notification = self.notification;
 
         'addrloop: loop {
-            // Flush our TXDR. TODO: does this ever matter in practice? Are we
-            // making it to this point with TXE clear?
-            i2c.isr.modify(|_, w| w.txe().set_bit());
+            // Flush our TXDR: if the host NACK'd us (or just stopped reading)
+            // in the middle of a read, the byte we had loaded next is still
+            // there.
+            self.flush_tx();
 
             // Wait to be addressed.
             let (is_write, addr) = loop {
@@ -984,6 +2085,13 @@ impl I2cController<'_> {
                 ringbuf_entry!(Trace::AddrNack(addr));
             }
 
+            // If we're checking PECs, `held` is the most recently received
+            // byte, which we don't yet know to be data, and `pec` is the PEC
+            // over everything on the wire before it.
+            let mut held: Option<u8> = None;
+            let mut pec = pec_update(0, addr << 1 | u8::from(!is_write));
+            let mut pec_sent = false;
+
             if is_write {
                 // During the write phase, the host sends bytes our way, and we
                 // have the opportunity to ACK/NACK each one. This phase
@@ -1016,7 +2124,13 @@ impl I2cController<'_> {
 
                         if initiated {
                             ringbuf_entry!(Trace::Rx(addr, rx));
-                            rxbyte(addr, rx);
+
+                            if !smbus_pec {
+                                rxbyte(addr, rx);
+                            } else if let Some(prev) = held.replace(rx) {
+                                pec = pec_update(pec, prev);
+                                rxbyte(addr, prev);
+                            }
                         } else {
                             // We're ignoring this byte. It has already been
                             // NACK'd, and the NACK flag is self-clearing. Ask
@@ -1037,6 +2151,22 @@ impl I2cController<'_> {
                     if isr.stopf().is_stop() {
                         ringbuf_entry!(Trace::Stop);
                         i2c.icr.write(|w| w.stopcf().set_bit());
+
+                        // If we're checking PECs, the last byte was the PEC.
+                        if let Some(actual) = held.take() {
+                            if actual == pec {
+                                ringbuf_entry!(Trace::RxPecMatch(addr));
+                            } else {
+                                ringbuf_entry!(Trace::RxPecMismatch {
+                                    addr,
+                                    expected: pec,
+                                    actual,
+                                });
+                            }
+
+                            rxpec(addr, actual == pec);
+                        }
+
                         continue 'addrloop;
                     }
 
@@ -1051,6 +2181,17 @@ impl I2cController<'_> {
                     if isr.addr().is_match() {
                         i2c.icr.write(|w| w.addrcf().set_bit());
 
+                        // A byte followed by a repeated start isn't a PEC.
+                        if let Some(prev) = held.take() {
+                            pec = pec_update(pec, prev);
+                            rxbyte(addr, prev);
+                        }
+
+                        pec = pec_update(
+                            pec,
+                            addr << 1 | u8::from(!isr.dir().is_write()),
+                        );
+
                         //
                         // If we have an address match, check to see if this is
                         // change in direction; if it is, break out of our receive
@@ -1138,6 +2279,14 @@ impl I2cController<'_> {
                             Some(byte) => {
                                 ringbuf_entry!(Trace::Tx(addr, byte));
                                 i2c.txdr.write(|w| w.txdata().bits(byte));
+                                pec = pec_update(pec, byte);
+                            }
+                            None if smbus_pec && !pec_sent => {
+                                // That was our last byte; follow it with the
+                                // PEC.
+                                ringbuf_entry!(Trace::TxPec(addr, pec));
+                                i2c.txdr.write(|w| w.txdata().bits(pec));
+                                pec_sent = true;
                             }
                             None => {
                                 //
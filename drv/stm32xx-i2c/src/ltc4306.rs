@@ -103,6 +103,7 @@ fn read_reg_u8(
             Some(())
         },
         ctrl,
+        None,
     );
     match controller_result {
         Err(code) => Err(mux.error_code(code)),
@@ -124,6 +125,7 @@ fn write_reg_u8(
         ReadLength::Fixed(0),
         |_, _| Some(()),
         ctrl,
+        None,
     ) {
         Err(code) => Err(mux.error_code(code)),
         _ => Ok(()),
@@ -174,12 +176,15 @@ impl I2cMuxDriver for Ltc4306 {
         let reg0 = Register0(read_reg_u8(mux, controller, 0, ctrl)?);
 
         if !reg0.not_failed() {
-            Err(ResponseCode::SegmentDisconnected)
+            return Err(ResponseCode::SegmentDisconnected);
         } else if !reg0.connected() {
-            Err(ResponseCode::MuxDisconnected)
-        } else {
-            Ok(())
+            return Err(ResponseCode::MuxDisconnected);
         }
+
+        // The lower four bits of register 3 read back as bus activity.
+        mux.verify_control(reg3.0, 0xf0, || {
+            read_reg_u8(mux, controller, 3, ctrl)
+        })
     }
 
     fn reset(
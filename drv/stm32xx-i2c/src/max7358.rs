@@ -70,6 +70,10 @@ enum Trace {
 
 ringbuf!(Trace, 32, Trace::None);
 
+/// Retries allowed for a NACK'd unlock sequence, and the delay between them.
+const KONAMI_RETRIES: u8 = 1;
+const KONAMI_RETRY_DELAY_MS: u64 = 1;
+
 fn read_regs(
     mux: &I2cMux<'_>,
     controller: &I2cController<'_>,
@@ -86,6 +90,7 @@ fn read_regs(
             Some(())
         },
         ctrl,
+        None,
     );
     match controller_result {
         Err(code) => Err(mux.error_code(code)),
@@ -131,6 +136,7 @@ fn write_reg(
         ReadLength::Fixed(0),
         |_, _| Some(()),
         ctrl,
+        None,
     ) {
         Err(code) => Err(mux.error_code(code)),
         _ => Ok(()),
@@ -177,7 +183,9 @@ impl I2cMuxDriver for Max7358 {
         let mut scratch = [0u8; 1];
         read_regs(mux, controller, &mut scratch[0..1], ctrl)?;
 
-        controller.send_konami_code(
+        // Even so, the first unlock after power-up is occasionally NACK'd;
+        // the next one has always been accepted, so allow for one retry.
+        controller.send_konami_code_with_retry(
             mux.address,
             &[
                 I2cKonamiCode::Write,
@@ -186,6 +194,8 @@ impl I2cMuxDriver for Max7358 {
                 I2cKonamiCode::Read,
             ],
             ctrl,
+            KONAMI_RETRIES,
+            KONAMI_RETRY_DELAY_MS,
         )?;
 
         let reg = SwitchControl(0);
@@ -3,6 +3,9 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Driver for the PCA9548 I2C mux
+//!
+//! This also drives the PCA9545, which has the same control register for its
+//! four channels, but reports interrupt status in the upper four bits.
 
 use crate::*;
 use bitfield::bitfield;
@@ -76,17 +79,25 @@ impl I2cMuxDriver for Pca9548 {
         // This part has but one register -- any write is to the control
         // register.
         //
-        match controller.write_read(
+        if let Err(code) = controller.write_read(
             mux.address,
             1,
             |_| Some(reg.0),
             ReadLength::Fixed(0),
             |_, _| Some(()),
             ctrl,
+            None,
         ) {
-            Err(code) => Err(mux.error_code(code)),
-            _ => Ok(()),
+            return Err(mux.error_code(code));
         }
+
+        //
+        // On a PCA9545, the upper four bits read back as interrupt status,
+        // so we only check them if we asked for a segment that only exists
+        // on a PCA9548.
+        //
+        let mask = if reg.0 & 0xf0 != 0 { 0xff } else { 0x0f };
+        mux.verify_control(reg.0, mask, || mux.read_control(controller, ctrl))
     }
 
     fn reset(
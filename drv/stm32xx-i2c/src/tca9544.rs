@@ -0,0 +1,124 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the TCA9544A I2C mux
+
+use crate::*;
+use bitfield::bitfield;
+use drv_i2c_api::{ResponseCode, Segment};
+
+pub struct Tca9544;
+
+bitfield! {
+    #[derive(Copy, Clone, Eq, PartialEq)]
+    pub struct ControlRegister(u8);
+    pub int3, _: 7;
+    pub int2, _: 6;
+    pub int1, _: 5;
+    pub int0, _: 4;
+    enabled, set_enabled: 2;
+    channel, set_channel: 1, 0;
+}
+
+impl Tca9544 {
+    ///
+    /// Reads the interrupt status of the mux, returning a mask in which bit
+    /// `n` is set if the interrupt input for channel `n` (that is, segment
+    /// `S{n + 1}`) is asserted.  Note that the interrupt inputs are reported
+    /// regardless of which channel (if any) is enabled.
+    ///
+    pub fn interrupt_status(
+        &self,
+        mux: &I2cMux<'_>,
+        controller: &I2cController<'_>,
+        ctrl: &I2cControl,
+    ) -> Result<u8, ResponseCode> {
+        let mut reg = ControlRegister(0);
+
+        //
+        // Like the PCA9544A, this part has but one register -- any read is
+        // from the control register.
+        //
+        match controller.write_read(
+            mux.address,
+            0,
+            |_| None,
+            ReadLength::Fixed(1),
+            |_, byte| {
+                reg = ControlRegister(byte);
+                Some(())
+            },
+            ctrl,
+            None,
+        ) {
+            Err(code) => Err(mux.error_code(code)),
+            _ => Ok(reg.0 >> 4),
+        }
+    }
+}
+
+impl I2cMuxDriver for Tca9544 {
+    fn configure(
+        &self,
+        mux: &I2cMux<'_>,
+        _controller: &I2cController<'_>,
+        gpio: &sys_api::Sys,
+        _ctrl: &I2cControl,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        mux.configure(gpio)
+    }
+
+    fn enable_segment(
+        &self,
+        mux: &I2cMux<'_>,
+        controller: &I2cController<'_>,
+        segment: Option<Segment>,
+        ctrl: &I2cControl,
+    ) -> Result<(), ResponseCode> {
+        //
+        // Unlike the PCA9548, only one channel can be selected at a time:
+        // the channel is selected by the low two bits, and enabled by the
+        // enable bit.  With the enable bit clear, all channels are disabled.
+        //
+        let mut reg = ControlRegister(0);
+
+        if let Some(segment) = segment {
+            let channel = match segment {
+                Segment::S1 => 0,
+                Segment::S2 => 1,
+                Segment::S3 => 2,
+                Segment::S4 => 3,
+                _ => {
+                    return Err(ResponseCode::SegmentNotFound);
+                }
+            };
+
+            reg.set_channel(channel);
+            reg.set_enabled(true);
+        }
+
+        if let Err(code) = controller.write_read(
+            mux.address,
+            1,
+            |_| Some(reg.0),
+            ReadLength::Fixed(0),
+            |_, _| Some(()),
+            ctrl,
+            None,
+        ) {
+            return Err(mux.error_code(code));
+        }
+
+        // The upper four bits read back as interrupt status.
+        mux.verify_control(reg.0, 0x07, || mux.read_control(controller, ctrl))
+    }
+
+    fn reset(
+        &self,
+        mux: &I2cMux<'_>,
+        gpio: &sys_api::Sys,
+    ) -> Result<(), drv_i2c_api::ResponseCode> {
+        mux.reset(gpio)
+    }
+}
@@ -66,6 +66,7 @@ struct CountGenerator<'input> {
     enum_name: &'input syn::Ident,
     field_defs: Vec<proc_macro2::TokenStream>,
     field_inits: Vec<proc_macro2::TokenStream>,
+    field_resets: Vec<proc_macro2::TokenStream>,
     variant_patterns: Vec<proc_macro2::TokenStream>,
     needed_generics: HashSet<syn::Ident>,
     all_generics: HashSet<syn::Ident>,
@@ -80,6 +81,7 @@ impl<'input> CountGenerator<'input> {
             input,
             field_defs: Vec::with_capacity(variants),
             field_inits: Vec::with_capacity(variants),
+            field_resets: Vec::with_capacity(variants),
             variant_patterns: Vec::with_capacity(variants),
             all_generics: input
                 .generics
@@ -98,6 +100,7 @@ impl<'input> CountGenerator<'input> {
             enum_name,
             field_defs,
             field_inits,
+            field_resets,
             mut variant_patterns,
             any_skipped,
             needed_generics,
@@ -156,6 +159,12 @@ impl<'input> CountGenerator<'input> {
                         #(#variant_patterns),*
                     };
                 }
+
+                // If every variant is skipped, there's nothing to reset.
+                #[allow(unused_variables)]
+                fn reset(counters: &Self::Counters) {
+                    #(#field_resets;)*
+                }
             }
         }
     }
@@ -238,6 +247,7 @@ impl<'input> CountGenerator<'input> {
         let Self {
             field_defs,
             field_inits,
+            field_resets,
             enum_name,
             ..
         } = self;
@@ -253,6 +263,9 @@ impl<'input> CountGenerator<'input> {
         field_inits.push(
             quote! { #variant_name: core::sync::atomic::AtomicU32::new(0) },
         );
+        field_resets.push(quote! {
+            counters.#variant_name.store(0, core::sync::atomic::Ordering::Relaxed)
+        });
     }
 
     /// Generate a field def and field initializer for a variant *with*
@@ -265,6 +278,7 @@ impl<'input> CountGenerator<'input> {
         let Self {
             field_defs,
             field_inits,
+            field_resets,
             enum_name,
             needed_generics,
             all_generics,
@@ -283,6 +297,9 @@ impl<'input> CountGenerator<'input> {
         field_inits.push(quote! {
             #variant_name: <#variant_type as counters::Count>::NEW_COUNTERS
         });
+        field_resets.push(quote! {
+            <#variant_type as counters::Count>::reset(&counters.#variant_name)
+        });
         where_clause_types.insert(variant_type.clone());
         if let syn::Type::Path(ty_path) = variant_type {
             if let Some(ident) = ty_path.path.get_ident() {
@@ -34,6 +34,9 @@ pub trait Count {
 
     /// Increment the counter for this event.
     fn count(&self, counters: &Self::Counters);
+
+    /// Reset every counter in `counters` back to 0.
+    fn reset(counters: &Self::Counters);
 }
 
 /// Declares a set of event counters.
@@ -110,6 +113,11 @@ impl<T: Count, E: Count> Count for Result<T, E> {
             Err(e) => e.count(&counters.Err),
         }
     }
+
+    fn reset(counters: &Self::Counters) {
+        T::reset(&counters.Ok);
+        E::reset(&counters.Err);
+    }
 }
 
 impl<T: Count> Count for Option<T> {
@@ -133,6 +141,11 @@ impl<T: Count> Count for Option<T> {
             }
         }
     }
+
+    fn reset(counters: &Self::Counters) {
+        T::reset(&counters.Some);
+        counters.None.store(0, Ordering::Relaxed);
+    }
 }
 
 impl<T: Count, E: Count> Count for &'_ Result<T, E> {
@@ -148,6 +161,11 @@ impl<T: Count, E: Count> Count for &'_ Result<T, E> {
             Err(e) => e.count(&counters.Err),
         }
     }
+
+    fn reset(counters: &Self::Counters) {
+        T::reset(&counters.Ok);
+        E::reset(&counters.Err);
+    }
 }
 
 impl<T: Count> Count for &'_ Option<T> {
@@ -171,6 +189,11 @@ impl<T: Count> Count for &'_ Option<T> {
             }
         }
     }
+
+    fn reset(counters: &Self::Counters) {
+        T::reset(&counters.Some);
+        counters.None.store(0, Ordering::Relaxed);
+    }
 }
 
 impl Count for core::convert::Infallible {
@@ -182,6 +205,8 @@ impl Count for core::convert::Infallible {
         // will not count them. We will not help count them.
         match *self {}
     }
+
+    fn reset(_: &Self::Counters) {}
 }
 
 impl Count for () {
@@ -196,6 +221,10 @@ impl Count for () {
             Ordering::Relaxed,
         );
     }
+
+    fn reset(counters: &Self::Counters) {
+        counters.store(0, Ordering::Relaxed);
+    }
 }
 
 /// Counters for [`bool`]s.
@@ -227,4 +256,9 @@ impl Count for bool {
 
         armv6m_atomic_hack::AtomicU32Ext::fetch_add(ctr, 1, Ordering::Relaxed);
     }
+
+    fn reset(counters: &Self::Counters) {
+        counters.r#true.store(0, Ordering::Relaxed);
+        counters.r#false.store(0, Ordering::Relaxed);
+    }
 }
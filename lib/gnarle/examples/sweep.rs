@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Sweeps codec parameters over sample files, reporting the compressed size
+//! and compression time for each combination.
+//!
+//! ```text
+//! cargo run --release -p gnarle --features std --example sweep [FILE...]
+//! ```
+//!
+//! With no arguments, this uses the FPGA bitstreams checked in elsewhere in
+//! the tree. For each file it first finds the escape byte that gives the
+//! smallest output with the default threshold and width, then tries every
+//! width and a range of thresholds with that escape byte (and with
+//! [`gnarle::DEFAULT_ESC`], for comparison).
+
+use gnarle::{Codec, RunWidth, DEFAULT_ESC};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Bitstreams checked in elsewhere in the tree, relative to this crate.
+const SAMPLES: &[&str] = &[
+    "../../drv/gimlet-seq-server/fpga-b.bin",
+    "../../drv/sidecar-mainboard-controller/sidecar_mainboard_controller_rev_c_d.bit",
+    "../../drv/sidecar-front-io/sidecar_qsfp_x32_controller_rev_b_c.bit",
+];
+
+const THRESHOLDS: std::ops::RangeInclusive<usize> = 3..=8;
+
+/// Number of times each compression is timed; the fastest is reported.
+const ITERATIONS: usize = 5;
+
+fn time_compress(codec: &Codec, input: &[u8]) -> Duration {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            let mut n = 0;
+            codec
+                .compress(input, |chunk| {
+                    n += chunk.len();
+                    Ok::<_, std::convert::Infallible>(())
+                })
+                .ok();
+            std::hint::black_box(n);
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn sweep(path: &Path, input: &[u8]) {
+    println!("{} ({} bytes)", path.display(), input.len());
+
+    let (best_esc, _) = (1..=u8::MAX)
+        .map(|esc| (esc, Codec::new(esc).unwrap().compressed_len(input)))
+        .min_by_key(|&(_, len)| len)
+        .unwrap();
+
+    println!(
+        "  {:>4} {:>5} {:>9} {:>10} {:>7} {:>10}",
+        "esc", "width", "threshold", "size", "ratio", "time"
+    );
+
+    let mut escs = vec![DEFAULT_ESC];
+    if best_esc != DEFAULT_ESC {
+        escs.push(best_esc);
+    }
+
+    for esc in escs {
+        for width in [RunWidth::U8, RunWidth::U16] {
+            for threshold in THRESHOLDS {
+                let codec = Codec::new(esc)
+                    .unwrap()
+                    .with_run_width(width)
+                    .with_run_threshold(threshold);
+
+                let len = codec.compressed_len(input);
+                let compressed = codec.compress_to_vec(input);
+                assert_eq!(compressed.len(), len);
+                assert_eq!(
+                    codec.decompress_to_vec(&compressed).as_deref(),
+                    Ok(input),
+                    "{codec:?} failed to round-trip"
+                );
+
+                println!(
+                    "  {esc:#04x} {:>5} {threshold:>9} {len:>10} {:>6.1}% \
+                     {:>10.2?}",
+                    format!("{width:?}"),
+                    100.0 * len as f64 / input.len() as f64,
+                    time_compress(&codec, input),
+                );
+            }
+        }
+    }
+    println!();
+}
+
+fn main() -> std::io::Result<()> {
+    let mut paths: Vec<PathBuf> =
+        std::env::args_os().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        paths = SAMPLES.iter().map(|s| dir.join(s)).collect();
+    }
+
+    for path in &paths {
+        let input = std::fs::read(path)?;
+        sweep(path, &input);
+    }
+
+    Ok(())
+}
@@ -9,17 +9,423 @@
 //! there don't appear to be any `no_std` lz4 crates out there, no matter what
 //! their READMEs claim.
 
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
-/// Internal definition of how long the run count is. Tuning this might improve
-/// performance, though its current value seems optimal in practice.
-type RunType = u8;
+/// How many bytes are used to encode the length of a run.
+///
+/// The count is stored little-endian, as one less than the run length, so a
+/// single escape sequence can describe up to 256 repeats with `U8` or up to
+/// 65536 with `U16`. Wider counts pay one extra byte per escape sequence, so
+/// they only help for data with very long runs.
+///
+/// Streams encoded with different widths are not compatible. Decoding a `U16`
+/// stream with a `U8` codec (or vice versa) misreads count bytes as data, and
+/// will produce output of the wrong length.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RunWidth {
+    #[default]
+    U8,
+    U16,
+}
+
+impl RunWidth {
+    /// Number of bytes used to encode a run count.
+    pub const fn bytes(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+        }
+    }
+
+    /// Longest run that can be encoded in a single escape sequence.
+    pub const fn max_run(self) -> usize {
+        match self {
+            Self::U8 => u8::MAX as usize + 1,
+            Self::U16 => u16::MAX as usize + 1,
+        }
+    }
+}
+
+/// The default byte used to signal that data is being interrupted for a run.
+/// This value was chosen as a relatively infrequent byte in iCE40 bitstreams.
+/// In practice, for the sorts of files we deal in, its value doesn't really
+/// matter as long as it isn't `0x00`.
+pub const DEFAULT_ESC: u8 = 0xBA;
+
+/// The default length at which a run of identical bytes is encoded as an
+/// escape sequence rather than as literal bytes.
+pub const DEFAULT_RUN_THRESHOLD: usize = 4;
+
+/// Size of the trailer appended by [`Codec::compress_checked`].
+pub const CHECKSUM_SIZE: usize = 2;
+
+/// Header byte written by [`Codec::compress_or_store`] before RLE-compressed
+/// data.
+pub const FRAME_COMPRESSED: u8 = 0x00;
+
+/// Header byte written by [`Codec::compress_or_store`] before data that is
+/// stored as-is, because compressing it would not have made it smaller.
+pub const FRAME_STORED: u8 = 0x01;
+
+/// Errors that can occur while decompressing a checked stream.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DecompressError {
+    /// The input ended in the middle of a run sequence, or was too short to
+    /// contain a checksum.
+    Truncated,
+    /// The output buffer was too small to hold the decompressed data.
+    OutputTooSmall,
+    /// The decompressed data did not match the checksum in the stream.
+    ChecksumMismatch,
+}
+
+/// Parameters shared between compression and decompression.
+///
+/// Data compressed with one `Codec` must be decompressed with an identical
+/// one. The compressed stream carries no record of the parameters used to
+/// produce it, so decompressing with a mismatched codec will not reproduce the
+/// original data; it's up to the user to keep the two in sync.
+///
+/// `Codec::default()` uses [`DEFAULT_ESC`], [`RunWidth::U8`], and
+/// [`DEFAULT_RUN_THRESHOLD`], and is what the free functions in this crate
+/// use.
+///
+/// The run threshold only affects compression: the decompressor reacts to
+/// escape bytes wherever they appear, so streams compressed with different
+/// thresholds can be decoded by the same decompressor.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Codec {
+    esc: u8,
+    run_width: RunWidth,
+    run_threshold: usize,
+}
+
+impl Codec {
+    /// Creates a codec that uses `esc` to signal runs.
+    ///
+    /// Returns `None` if `esc` is `0x00`, since zeroes tend to be the most
+    /// common byte in the data we compress, and using it as the escape would
+    /// make every isolated zero cost three bytes.
+    pub const fn new(esc: u8) -> Option<Self> {
+        if esc == 0 {
+            None
+        } else {
+            Some(Self {
+                esc,
+                run_width: RunWidth::U8,
+                run_threshold: DEFAULT_RUN_THRESHOLD,
+            })
+        }
+    }
+
+    /// Returns a copy of this codec that encodes run counts with `run_width`.
+    pub const fn with_run_width(self, run_width: RunWidth) -> Self {
+        Self { run_width, ..self }
+    }
+
+    /// Returns a copy of this codec that encodes runs of at least
+    /// `run_threshold` identical bytes as escape sequences, and shorter runs
+    /// as literal bytes. (Runs of the escape byte are always escaped,
+    /// whatever their length.)
+    ///
+    /// An escape sequence costs `2 + run_width.bytes()` bytes, and a run of
+    /// that length costs the same either way. Thresholds below that make the
+    /// output larger; thresholds above it make the output larger too, but
+    /// produce fewer escape sequences.
+    pub const fn with_run_threshold(self, run_threshold: usize) -> Self {
+        Self {
+            run_threshold,
+            ..self
+        }
+    }
+
+    /// Returns the escape byte used by this codec.
+    pub const fn esc(&self) -> u8 {
+        self.esc
+    }
+
+    /// Returns the width of run counts used by this codec.
+    pub const fn run_width(&self) -> RunWidth {
+        self.run_width
+    }
+
+    /// Returns the run length at which this codec starts using escape
+    /// sequences.
+    pub const fn run_threshold(&self) -> usize {
+        self.run_threshold
+    }
+
+    /// Compresses data from `input` using this codec's parameters. See the
+    /// free function [`compress`] for details.
+    pub fn compress<E>(
+        &self,
+        input: &[u8],
+        mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.for_each_run(input, |byte, count| {
+            self.generate_run(byte, count, &mut out)
+        })
+    }
+
+    /// Compresses `input` like [`Codec::compress`], then appends a Fletcher-16
+    /// checksum of the *uncompressed* input as two final little-endian bytes,
+    /// handed to `out` like any other output.
+    ///
+    /// Unlike `compress`, this must be given the entire input in one call,
+    /// and the result must be decoded with [`Codec::decompress_checked`].
+    pub fn compress_checked<E>(
+        &self,
+        input: &[u8],
+        mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.compress(input, &mut out)?;
+        let mut checksum = fletcher::Fletcher16::new();
+        checksum.update(input);
+        out(&checksum.value().to_le_bytes())
+    }
+
+    /// Decompresses a complete stream produced by [`Codec::compress_checked`]
+    /// into `output`, returning the prefix of `output` that was written.
+    ///
+    /// Unlike [`decompress`], this is not incremental: `input` must hold the
+    /// whole stream, and `output` must be large enough for the whole
+    /// decompressed result.
+    pub fn decompress_checked<'a>(
+        &self,
+        input: &[u8],
+        output: &'a mut [u8],
+    ) -> Result<&'a [u8], DecompressError> {
+        let Some(split) = input.len().checked_sub(CHECKSUM_SIZE) else {
+            return Err(DecompressError::Truncated);
+        };
+        let (mut data, trailer) = input.split_at(split);
+        let expected = u16::from_le_bytes([trailer[0], trailer[1]]);
+
+        let mut state = self.decompressor();
+        let n = decompress(&mut state, &mut data, output).len();
+        if !data.is_empty() {
+            return Err(DecompressError::OutputTooSmall);
+        }
+        if !state.is_idle() {
+            // Either `output` filled up during the final run, or the stream
+            // ends partway through a run sequence.
+            return Err(match state.state {
+                DState::Repeating(..) => DecompressError::OutputTooSmall,
+                _ => DecompressError::Truncated,
+            });
+        }
+
+        let output = &output[..n];
+        let mut checksum = fletcher::Fletcher16::new();
+        checksum.update(output);
+        if checksum.value() != expected {
+            return Err(DecompressError::ChecksumMismatch);
+        }
+        Ok(output)
+    }
+
+    /// Compresses `input` like [`Codec::compress`] if that would make it
+    /// smaller, and otherwise hands it to `out` unchanged. Either way, the
+    /// output starts with a one-byte header ([`FRAME_COMPRESSED`] or
+    /// [`FRAME_STORED`]) saying which was done, so the worst case is one byte
+    /// larger than `input`, rather than the up-to-2x expansion RLE can cause
+    /// on near-random data (each lone escape byte in the input costs three
+    /// bytes).
+    ///
+    /// Like `compress_checked`, this must be given the entire input in one
+    /// call. The result must be decoded with a decompressor from
+    /// [`Codec::framed_decompressor`].
+    pub fn compress_or_store<E>(
+        &self,
+        input: &[u8],
+        mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.compressed_len(input) < input.len() {
+            out(&[FRAME_COMPRESSED])?;
+            self.compress(input, out)
+        } else {
+            out(&[FRAME_STORED])?;
+            out(input)
+        }
+    }
+
+    /// Returns the number of bytes `compress` would produce for `input`,
+    /// without producing them. See the free function [`compressed_len`].
+    pub fn compressed_len(&self, input: &[u8]) -> usize {
+        let mut len = 0;
+        self.for_each_run(input, |byte, count| {
+            len += self.run_len(byte, count);
+            Ok::<_, core::convert::Infallible>(())
+        })
+        .ok();
+        len
+    }
+
+    /// Compresses `input` with this codec, returning a `Vec`.
+    #[cfg(feature = "std")]
+    pub fn compress_to_vec(&self, input: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+
+        self.compress(input, |chunk| {
+            output.extend_from_slice(chunk);
+            Ok::<_, std::convert::Infallible>(())
+        })
+        .ok();
 
-/// The byte used to signal that data is being interrupted for a run. This value
-/// was chosen as a relatively infrequent byte in iCE40 bitstreams. In practice,
-/// for the sorts of files we deal in, its value doesn't really matter as long
-/// as it isn't `0x00`.
-const ESC: u8 = 0xBA;
+        output
+    }
+
+    /// Decompresses `input` with this codec, returning a `Vec`.
+    ///
+    /// Returns [`DecompressError::Truncated`] if `input` ends partway through
+    /// a run sequence.
+    #[cfg(feature = "std")]
+    pub fn decompress_to_vec(
+        &self,
+        input: &[u8],
+    ) -> Result<Vec<u8>, DecompressError> {
+        let mut output = vec![];
+        let mut state = self.decompressor();
+        let mut input = input;
+        let mut buf = [0; 256];
+
+        loop {
+            let chunk = decompress(&mut state, &mut input, &mut buf);
+            if chunk.is_empty() {
+                break;
+            }
+            output.extend_from_slice(chunk);
+        }
+
+        if !state.is_idle() {
+            return Err(DecompressError::Truncated);
+        }
+        Ok(output)
+    }
+
+    /// Returns a new `StreamingCompressor` that compresses with this codec.
+    pub fn streaming_compressor(&self) -> StreamingCompressor {
+        StreamingCompressor {
+            codec: *self,
+            current_run: None,
+        }
+    }
+
+    /// Returns a new `Decompressor` that will decode data compressed with this
+    /// codec.
+    pub fn decompressor(&self) -> Decompressor {
+        Decompressor {
+            codec: *self,
+            framed: false,
+            state: DState::Copying,
+        }
+    }
+
+    /// Returns a new `Decompressor` that will decode data produced by
+    /// [`Codec::compress_or_store`] with this codec, starting with its header
+    /// byte.
+    pub fn framed_decompressor(&self) -> Decompressor {
+        Decompressor {
+            codec: *self,
+            framed: true,
+            state: DState::AwaitingHeader,
+        }
+    }
+
+    /// Splits `input` into runs of identical bytes, each no longer than the
+    /// longest run we can encode, and hands them to `f` in order. This is the
+    /// state machine shared by `compress` and `compressed_len`.
+    fn for_each_run<E>(
+        &self,
+        input: &[u8],
+        mut f: impl FnMut(u8, usize) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut current_run = None;
+        self.extend_run(&mut current_run, input, &mut f)?;
+        if let Some((current_byte, current_len)) = current_run {
+            f(current_byte, current_len)?;
+        }
+
+        Ok(())
+    }
+
+    /// Feeds `input` through the run-splitting state machine, starting from
+    /// (and leaving behind) the run in progress in `current_run`. Runs that
+    /// are complete are handed to `f`; the last one is not, since more input
+    /// may extend it.
+    fn extend_run<E>(
+        &self,
+        current_run: &mut Option<(u8, usize)>,
+        input: &[u8],
+        mut f: impl FnMut(u8, usize) -> Result<(), E>,
+    ) -> Result<(), E> {
+        for &byte in input {
+            if let Some((current_byte, current_len)) = current_run {
+                if byte == *current_byte
+                    && *current_len < self.run_width.max_run()
+                {
+                    *current_len += 1;
+                    continue;
+                }
+                f(*current_byte, *current_len)?;
+            }
+
+            *current_run = Some((byte, 1));
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a run should be encoded as an escape sequence rather
+    /// than as literal bytes.
+    fn is_escaped_run(&self, byte: u8, count: usize) -> bool {
+        count >= self.run_threshold || byte == self.esc
+    }
+
+    fn generate_run<E>(
+        &self,
+        byte: u8,
+        count: usize,
+        out: &mut impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.is_escaped_run(byte, count) {
+            out(&[self.esc, byte])?;
+            let count = count - 1;
+            match self.run_width {
+                RunWidth::U8 => {
+                    out(&u8::try_from(count).unwrap().to_le_bytes())?
+                }
+                RunWidth::U16 => {
+                    out(&u16::try_from(count).unwrap().to_le_bytes())?
+                }
+            }
+        } else {
+            for _ in 0..count {
+                out(&[byte])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes `generate_run` would emit.
+    fn run_len(&self, byte: u8, count: usize) -> usize {
+        if self.is_escaped_run(byte, count) {
+            2 + self.run_width.bytes()
+        } else {
+            count
+        }
+    }
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self {
+            esc: DEFAULT_ESC,
+            run_width: RunWidth::U8,
+            run_threshold: DEFAULT_RUN_THRESHOLD,
+        }
+    }
+}
 
 /// Compresses data from `input`, handing the results to `out` as small slices.
 /// `out` has the opportunity to abort compression by returning `Err`. `out` is
@@ -32,91 +438,184 @@ const ESC: u8 = 0xBA;
 /// You can call `compress` more than once to process input in chunks. A
 /// sequence of data chopped into arbitrary chunks, compressed, and then
 /// concatenated is still a valid RLE sequence, though runs that cross chunk
-/// boundaries will be compressed less efficiently.
+/// boundaries will be compressed less efficiently. Use a
+/// [`StreamingCompressor`] to avoid that.
+///
+/// This uses the default [`Codec`].
 pub fn compress<E>(
     input: &[u8],
-    mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    out: impl FnMut(&[u8]) -> Result<(), E>,
 ) -> Result<(), E> {
-    let mut current_run: Option<(u8, usize)> = None;
-    for &byte in input {
-        if let Some((current_byte, current_len)) = &mut current_run {
-            if byte == *current_byte
-                && *current_len < usize::from(RunType::MAX) + 1
-            {
-                *current_len += 1;
-                continue;
+    Codec::default().compress(input, out)
+}
+
+/// State for compressing data that arrives in chunks.
+///
+/// Unlike calling [`compress`] once per chunk, a `StreamingCompressor` holds on
+/// to the run in progress at the end of each chunk, so a run that crosses a
+/// chunk boundary is encoded exactly as it would be if the input had been
+/// compressed in one piece. The price is that the final run isn't emitted
+/// until [`finish`](StreamingCompressor::finish) is called.
+///
+/// If `out` returns `Err`, the data handed to the compressor so far can't be
+/// recovered, and the compressor should be discarded (or `finish`ed, with its
+/// output ignored) before being reused.
+pub struct StreamingCompressor {
+    codec: Codec,
+    current_run: Option<(u8, usize)>,
+}
+
+impl StreamingCompressor {
+    /// Compresses `input`, handing complete runs to `out`, and keeping the last
+    /// run back in case the next chunk continues it.
+    pub fn push<E>(
+        &mut self,
+        input: &[u8],
+        mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let codec = &self.codec;
+        codec.extend_run(&mut self.current_run, input, |byte, count| {
+            codec.generate_run(byte, count, &mut out)
+        })
+    }
+
+    /// Emits the run in progress, if any. Afterwards the compressor may be
+    /// used to compress a new, independent stream.
+    pub fn finish<E>(
+        &mut self,
+        mut out: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        match self.current_run.take() {
+            Some((byte, count)) => {
+                self.codec.generate_run(byte, count, &mut out)
             }
-            generate_run(*current_byte, *current_len, &mut out)?;
+            None => Ok(()),
         }
-
-        current_run = Some((byte, 1));
     }
-    if let Some((current_byte, current_len)) = current_run {
-        generate_run(current_byte, current_len, &mut out)?;
+}
+
+impl Default for StreamingCompressor {
+    fn default() -> Self {
+        Codec::default().streaming_compressor()
     }
+}
 
-    Ok(())
+/// Compresses `input` if that would make it smaller, and otherwise stores it
+/// as-is, behind a one-byte header saying which was done. See
+/// [`Codec::compress_or_store`] for details.
+///
+/// This uses the default [`Codec`].
+pub fn compress_or_store<E>(
+    input: &[u8],
+    out: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    Codec::default().compress_or_store(input, out)
 }
 
-/// Compresses the given data, returning a `Vec`
+/// Returns the number of bytes that [`compress`] would hand to its `out`
+/// function for `input`, without actually compressing anything.
+///
+/// This is useful for deciding whether compression is worthwhile before
+/// committing to it. The result is exact, not an estimate.
+///
+/// This uses the default [`Codec`].
+pub fn compressed_len(input: &[u8]) -> usize {
+    Codec::default().compressed_len(input)
+}
+
+/// Compresses the given data, returning a `Vec`.
+///
+/// This uses the default [`Codec`].
 #[cfg(feature = "std")]
 pub fn compress_to_vec(input: &[u8]) -> Vec<u8> {
-    let mut output = vec![];
-
-    compress(input, |chunk| {
-        output.extend_from_slice(chunk);
-        Ok::<_, std::convert::Infallible>(())
-    })
-    .ok();
-
-    output
+    Codec::default().compress_to_vec(input)
 }
 
-fn generate_run<E>(
-    byte: u8,
-    count: usize,
-    out: &mut impl FnMut(&[u8]) -> Result<(), E>,
-) -> Result<(), E> {
-    if count < 4 && byte != ESC {
-        for _ in 0..count {
-            out(&[byte])?;
-        }
-    } else {
-        out(&[ESC, byte])?;
-        out(&RunType::try_from(count - 1).unwrap().to_le_bytes())?;
-    }
-    Ok(())
+/// Decompresses the given data, returning a `Vec`.
+///
+/// Returns [`DecompressError::Truncated`] if `input` ends partway through a
+/// run sequence.
+///
+/// This uses the default [`Codec`].
+#[cfg(feature = "std")]
+pub fn decompress_to_vec(input: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    Codec::default().decompress_to_vec(input)
 }
 
 /// State that you're expected to hang on to while decompressing something.
-pub struct Decompressor(DState);
+///
+/// A `Decompressor` remembers the [`Codec`] it was created from; use
+/// [`Codec::decompressor`] to decode data compressed with a non-default codec.
+///
+/// A `Decompressor` may be reused to decode more than one stream, but only if
+/// it is idle between them, or has been [`reset`](Decompressor::reset): a
+/// stream that ends (or is abandoned) partway through a run sequence leaves
+/// the decompressor expecting the rest of that sequence, and it will take the
+/// start of the next stream to be it, corrupting the output.
+///
+/// A decompressor from [`Codec::framed_decompressor`] expects each stream to
+/// start with the header byte written by [`Codec::compress_or_store`], and is
+/// not idle until it has seen one.
+pub struct Decompressor {
+    codec: Codec,
+    framed: bool,
+    state: DState,
+}
 
 impl Decompressor {
     pub fn is_idle(&self) -> bool {
-        matches!(self.0, DState::Copying)
+        matches!(self.state, DState::Copying | DState::Stored)
+    }
+
+    /// Returns `true` if this decompressor was given a header byte that isn't
+    /// one written by [`Codec::compress_or_store`]. It will make no further
+    /// progress until it is [`reset`](Decompressor::reset).
+    pub fn is_invalid(&self) -> bool {
+        matches!(self.state, DState::Invalid)
+    }
+
+    /// Returns the decompressor to its initial state, discarding any partial
+    /// run sequence (or pending run output), so that it can be used to decode
+    /// a new, independent stream with the same [`Codec`] (and, for a framed
+    /// decompressor, starting with a new header byte).
+    pub fn reset(&mut self) {
+        self.state = if self.framed {
+            DState::AwaitingHeader
+        } else {
+            DState::Copying
+        };
     }
 }
 
 impl Default for Decompressor {
     fn default() -> Self {
-        Self(DState::Copying)
+        Codec::default().decompressor()
     }
 }
 
 enum DState {
+    /// We're decoding the output of `compress_or_store`, and haven't yet seen
+    /// the header byte that says how the rest of it was encoded.
+    AwaitingHeader,
+    /// The header byte said the data was stored uncompressed, so we copy it
+    /// to the output without looking for escapes.
+    Stored,
+    /// The header byte wasn't one we recognize; we won't touch the input.
+    Invalid,
     /// We're not in a run, we're just copying bytes to the output and watching
     /// for the escape byte.
     Copying,
     /// We're in a run, we are going to produce the given byte N times, where
     /// the count on the right is `N-1`.
-    Repeating(u8, RunType),
+    Repeating(u8, u16),
     /// We encountered an escape byte, keep track of this fact in the event that
     /// the input is exhausted before the full run sequence was read.
     AwaitingByte,
     /// An escape byte and the byte to be produced was read, keep track of this
     /// fact in the event that the input is exhausted before the full run
-    /// sequence was read.
-    AwaitingCount(u8),
+    /// sequence was read. With multi-byte run counts, we also track the count
+    /// accumulated so far and how many of its bytes we've seen.
+    AwaitingCount(u8, u16, u8),
 }
 
 /// Decompresses a chunk of data `input`, writing results to the start of
@@ -139,27 +638,73 @@ pub fn decompress<'a>(
     input: &mut &[u8],
     output: &'a mut [u8],
 ) -> &'a [u8] {
-    fn take_byte(input: &mut &[u8]) -> Option<u8> {
-        let (first, rest) = input.split_first()?;
-        *input = rest;
-        Some(*first)
-    }
+    let progress = decompress_with_progress(state, input, output);
+    &output[..progress.produced]
+}
+
+/// Record of what a single call to [`decompress_with_progress`] did.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// Number of bytes taken from the front of `input`.
+    pub consumed: usize,
+    /// Number of bytes written to the start of `output`.
+    pub produced: usize,
+    /// `true` if `input` is exhausted and no run is pending, i.e. everything
+    /// handed to the decompressor so far has been written out. If this is
+    /// `false` and `input` is empty, the output buffer filled up partway
+    /// through a run, and the rest of the run will be produced on the next
+    /// call.
+    pub fully_drained: bool,
+}
 
+/// Decompresses a chunk of data `input` like [`decompress`], but instead of
+/// returning the written prefix of `output`, returns a [`Progress`] describing
+/// how much input was consumed and output produced.
+///
+/// This is useful when resuming decompression across fixed-size output
+/// buffers (e.g. DMA buffers), where the caller needs to distinguish "output
+/// full, run pending" from "input exhausted". `input` is updated exactly as it
+/// is by `decompress`.
+pub fn decompress_with_progress(
+    state: &mut Decompressor,
+    input: &mut &[u8],
+    output: &mut [u8],
+) -> Progress {
+    let before = input.len();
+    let esc = state.codec.esc;
+    let count_bytes = state.codec.run_width.bytes();
     let mut n = 0;
     while n < output.len() {
-        match &mut state.0 {
+        match &mut state.state {
+            DState::AwaitingHeader => match take_byte(input) {
+                Some(FRAME_COMPRESSED) => state.state = DState::Copying,
+                Some(FRAME_STORED) => state.state = DState::Stored,
+                Some(_) => state.state = DState::Invalid,
+                None => break,
+            },
+            DState::Stored => {
+                let len = input.len().min(output.len() - n);
+                if len == 0 {
+                    break;
+                }
+                let (data, rest) = input.split_at(len);
+                output[n..][..len].copy_from_slice(data);
+                *input = rest;
+                n += len;
+            }
+            DState::Invalid => break,
             DState::Repeating(byte, count) => {
                 output[n] = *byte;
                 n += 1;
                 if let Some(new_count) = count.checked_sub(1) {
                     *count = new_count;
                 } else {
-                    state.0 = DState::Copying;
+                    state.state = DState::Copying;
                 }
             }
             DState::Copying => match take_byte(input) {
-                Some(ESC) => {
-                    state.0 = DState::AwaitingByte;
+                Some(byte) if byte == esc => {
+                    state.state = DState::AwaitingByte;
                 }
                 Some(byte) => {
                     output[n] = byte;
@@ -169,18 +714,753 @@ pub fn decompress<'a>(
             },
             DState::AwaitingByte => match take_byte(input) {
                 Some(byte) => {
-                    state.0 = DState::AwaitingCount(byte);
+                    state.state = DState::AwaitingCount(byte, 0, 0);
                 }
                 None => break,
             },
-            DState::AwaitingCount(byte) => match take_byte(input) {
-                Some(count) => {
-                    state.0 = DState::Repeating(*byte, count);
+            DState::AwaitingCount(byte, count, received) => {
+                match take_byte(input) {
+                    Some(b) => {
+                        // Counts are little-endian.
+                        *count |= u16::from(b) << (8 * *received);
+                        *received += 1;
+                        if usize::from(*received) == count_bytes {
+                            state.state = DState::Repeating(*byte, *count);
+                        }
+                    }
+                    None => break,
                 }
+            }
+        }
+    }
+
+    Progress {
+        consumed: before - input.len(),
+        produced: n,
+        fully_drained: input.is_empty() && state.is_idle(),
+    }
+}
+
+/// A destination for [`decompress_into`] that may have room for only some of
+/// the bytes it's offered, such as a ring buffer being drained by DMA.
+pub trait RingSink {
+    /// Appends as many bytes from the front of `bytes` as there is room for,
+    /// returning how many that was (which must not exceed `bytes.len()`).
+    /// Returning 0 means the sink is full; `bytes` is never empty.
+    fn push_bytes(&mut self, bytes: &[u8]) -> usize;
+}
+
+/// Decompresses a chunk of data `input` like [`decompress_with_progress`], but
+/// writes directly to `ring` rather than to an output slice, saving a copy
+/// when the output is headed for a ring buffer anyway.
+///
+/// Decompression stops when `input` is exhausted or `ring` is full (i.e. its
+/// [`RingSink::push_bytes`] accepts nothing), and resumes where it left off
+/// on the next call. `input` is updated exactly as it is by `decompress`; note
+/// that a run sequence may be consumed from it while `ring` is full, in which
+/// case the run is pending (and `fully_drained` is `false`) until there's
+/// room for it.
+pub fn decompress_into(
+    state: &mut Decompressor,
+    input: &mut &[u8],
+    ring: &mut impl RingSink,
+) -> Progress {
+    // Runs are offered to `ring` in pieces of at most this many bytes.
+    const RUN_CHUNK: usize = 64;
+
+    let before = input.len();
+    let esc = state.codec.esc;
+    let count_bytes = state.codec.run_width.bytes();
+    let mut n = 0;
+    loop {
+        match &mut state.state {
+            DState::AwaitingHeader => match take_byte(input) {
+                Some(FRAME_COMPRESSED) => state.state = DState::Copying,
+                Some(FRAME_STORED) => state.state = DState::Stored,
+                Some(_) => state.state = DState::Invalid,
                 None => break,
             },
+            DState::Stored => {
+                if input.is_empty() {
+                    break;
+                }
+                let len = ring.push_bytes(input);
+                if len == 0 {
+                    break;
+                }
+                *input = &input[len..];
+                n += len;
+            }
+            DState::Invalid => break,
+            DState::Repeating(byte, count) => {
+                let remaining = usize::from(*count) + 1;
+                let chunk = [*byte; RUN_CHUNK];
+                let len = ring.push_bytes(&chunk[..remaining.min(RUN_CHUNK)]);
+                if len == 0 {
+                    break;
+                }
+                n += len;
+                if len == remaining {
+                    state.state = DState::Copying;
+                } else {
+                    *count -= len as u16;
+                }
+            }
+            DState::Copying => {
+                // Hand over everything up to the next escape byte at once.
+                let literal =
+                    input.iter().position(|&b| b == esc).unwrap_or(input.len());
+                if literal == 0 {
+                    match take_byte(input) {
+                        Some(_) => state.state = DState::AwaitingByte,
+                        None => break,
+                    }
+                } else {
+                    let len = ring.push_bytes(&input[..literal]);
+                    if len == 0 {
+                        break;
+                    }
+                    *input = &input[len..];
+                    n += len;
+                }
+            }
+            DState::AwaitingByte => match take_byte(input) {
+                Some(byte) => {
+                    state.state = DState::AwaitingCount(byte, 0, 0);
+                }
+                None => break,
+            },
+            DState::AwaitingCount(byte, count, received) => {
+                match take_byte(input) {
+                    Some(b) => {
+                        // Counts are little-endian.
+                        *count |= u16::from(b) << (8 * *received);
+                        *received += 1;
+                        if usize::from(*received) == count_bytes {
+                            state.state = DState::Repeating(*byte, *count);
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    Progress {
+        consumed: before - input.len(),
+        produced: n,
+        fully_drained: input.is_empty() && state.is_idle(),
+    }
+}
+
+fn take_byte(input: &mut &[u8]) -> Option<u8> {
+    let (first, rest) = input.split_first()?;
+    *input = rest;
+    Some(*first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress_with(codec: &Codec, input: &[u8]) -> Vec<u8> {
+        let mut v = vec![];
+        codec
+            .compress(input, |c| {
+                v.extend_from_slice(c);
+                Ok::<_, std::convert::Infallible>(())
+            })
+            .unwrap();
+        v
+    }
+
+    #[test]
+    fn compressed_len_matches_compress() {
+        let long_run = [0x42; 600];
+        let mut mixed = vec![];
+        for i in 0..1024_usize {
+            mixed.push(if i % 97 < 40 { 0 } else { (i % 7) as u8 });
+        }
+        let inputs: [&[u8]; 9] = [
+            &[],
+            &[1],
+            &[1, 1, 1],
+            &[1, 1, 1, 1],
+            &[DEFAULT_ESC],
+            &[DEFAULT_ESC, 1, DEFAULT_ESC, DEFAULT_ESC, 2, 2, 2, 2, 2],
+            &long_run,
+            &[0; 257],
+            &mixed,
+        ];
+        for input in inputs {
+            for codec in [Codec::default(), Codec::new(0x01).unwrap()] {
+                assert_eq!(
+                    codec.compressed_len(input),
+                    compress_with(&codec, input).len(),
+                    "input: {input:x?}, codec: {codec:?}",
+                );
+            }
+        }
+        assert_eq!(
+            compressed_len(&mixed),
+            compress_with(&Codec::default(), &mixed).len()
+        );
+    }
+
+    #[test]
+    fn u16_runs_round_trip() {
+        let codec = Codec::default().with_run_width(RunWidth::U16);
+        let mut input = vec![0x42; 40_000];
+        input.extend_from_slice(&[1, 2, DEFAULT_ESC, 3]);
+        input.resize(input.len() + 70_000, 0);
+
+        let compressed = compress_with(&codec, &input);
+        // 40k of 0x42 fits in one escape, 70k of zeroes needs two, and the
+        // lone escape byte needs one more.
+        assert_eq!(compressed.len(), 4 * 4 + 3);
+        assert_eq!(codec.compressed_len(&input), compressed.len());
+
+        // Feed the input through in small pieces to exercise a count being
+        // split across calls.
+        let mut state = codec.decompressor();
+        let mut output = vec![];
+        for chunk in compressed.chunks(3) {
+            let mut chunk = chunk;
+            let mut buf = [0; 1000];
+            while !chunk.is_empty() || !state.is_idle() {
+                let out = decompress(&mut state, &mut chunk, &mut buf);
+                if out.is_empty() {
+                    break;
+                }
+                output.extend_from_slice(out);
+            }
+        }
+        assert!(state.is_idle());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn u16_stream_in_u8_decompressor_is_detected() {
+        let input = [0x42; 1000];
+        let compressed = compress_with(
+            &Codec::default().with_run_width(RunWidth::U16),
+            &input,
+        );
+
+        let mut state = Decompressor::default();
+        let mut output = [0; 2000];
+        let out = decompress(&mut state, &mut &compressed[..], &mut output);
+        assert!(state.is_idle());
+        assert_ne!(out.len(), input.len());
+    }
+
+    #[test]
+    fn checked_round_trip() {
+        let codec = Codec::default();
+        for input in [&[][..], &[1, 2, 3], &[5; 300], &[DEFAULT_ESC; 2]] {
+            let mut compressed = vec![];
+            codec
+                .compress_checked(input, |c| {
+                    compressed.extend_from_slice(c);
+                    Ok::<_, std::convert::Infallible>(())
+                })
+                .unwrap();
+            assert_eq!(
+                compressed.len(),
+                codec.compressed_len(input) + CHECKSUM_SIZE
+            );
+
+            let mut output = [0; 300];
+            assert_eq!(
+                codec.decompress_checked(&compressed, &mut output),
+                Ok(input)
+            );
+        }
+    }
+
+    #[test]
+    fn checked_detects_corruption() {
+        let codec = Codec::default();
+        let input = [1, 2, 3, 4, 4, 4, 4, 4, 4, 4, 4, 5, 6, DEFAULT_ESC, 7];
+        let mut compressed = vec![];
+        codec
+            .compress_checked(&input, |c| {
+                compressed.extend_from_slice(c);
+                Ok::<_, std::convert::Infallible>(())
+            })
+            .unwrap();
+
+        let mut output = [0; 64];
+        // Corrupting a literal byte only shows up in the checksum.
+        let mut corrupt = compressed.clone();
+        corrupt[1] ^= 0x01;
+        assert_eq!(
+            codec.decompress_checked(&corrupt, &mut output),
+            Err(DecompressError::ChecksumMismatch)
+        );
+
+        // Corrupting anything else should be caught one way or another.
+        for i in 0..compressed.len() {
+            let mut corrupt = compressed.clone();
+            corrupt[i] ^= 0x01;
+            assert!(
+                codec.decompress_checked(&corrupt, &mut output).is_err(),
+                "corruption at {i} not detected"
+            );
+        }
+
+        assert_eq!(
+            codec.decompress_checked(&compressed[..1], &mut output),
+            Err(DecompressError::Truncated)
+        );
+        assert_eq!(
+            codec.decompress_checked(&compressed, &mut output[..4]),
+            Err(DecompressError::OutputTooSmall)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn to_vec_round_trip() {
+        assert_eq!(compress_to_vec(&[]), []);
+        assert_eq!(decompress_to_vec(&[]), Ok(vec![]));
+
+        let mut input = vec![1, 2, DEFAULT_ESC, 3];
+        input.resize(2000, 0xff);
+        let compressed = compress_to_vec(&input);
+        assert!(compressed.len() < input.len());
+        assert_eq!(decompress_to_vec(&compressed), Ok(input));
+
+        assert_eq!(
+            decompress_to_vec(&compressed[..compressed.len() - 1]),
+            Err(DecompressError::Truncated)
+        );
+    }
+
+    #[test]
+    fn progress_across_tiny_outputs() {
+        // A 10-byte run followed by a literal, decoded four bytes at a time.
+        let input = [9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 1];
+        let compressed = compress_with(&Codec::default(), &input);
+        assert_eq!(compressed, [DEFAULT_ESC, 9, 9, 1]);
+
+        let mut state = Decompressor::default();
+        let mut input = &compressed[..];
+        let mut buf = [0; 4];
+
+        let p = decompress_with_progress(&mut state, &mut input, &mut buf);
+        assert_eq!(
+            p,
+            Progress {
+                consumed: 3,
+                produced: 4,
+                fully_drained: false,
+            }
+        );
+        assert_eq!(buf, [9; 4]);
+
+        let p = decompress_with_progress(&mut state, &mut input, &mut buf);
+        assert_eq!(
+            p,
+            Progress {
+                consumed: 0,
+                produced: 4,
+                fully_drained: false,
+            }
+        );
+        assert_eq!(buf, [9; 4]);
+
+        let p = decompress_with_progress(&mut state, &mut input, &mut buf);
+        assert_eq!(
+            p,
+            Progress {
+                consumed: 1,
+                produced: 3,
+                fully_drained: true,
+            }
+        );
+        assert_eq!(buf[..3], [9, 9, 1]);
+        assert!(input.is_empty());
+    }
+
+    /// A ring buffer that accepts at most a few bytes per push, like one
+    /// being drained by DMA while we fill it.
+    struct MockRing {
+        buf: [u8; 16],
+        head: usize,
+        len: usize,
+        per_push: usize,
+    }
+
+    impl MockRing {
+        fn drain(&mut self, out: &mut Vec<u8>) {
+            while self.len > 0 {
+                out.push(self.buf[self.head]);
+                self.head = (self.head + 1) % self.buf.len();
+                self.len -= 1;
+            }
+        }
+    }
+
+    impl RingSink for MockRing {
+        fn push_bytes(&mut self, bytes: &[u8]) -> usize {
+            assert!(!bytes.is_empty());
+            let room = self.buf.len() - self.len;
+            let n = bytes.len().min(room).min(self.per_push);
+            for &b in &bytes[..n] {
+                let tail = (self.head + self.len) % self.buf.len();
+                self.buf[tail] = b;
+                self.len += 1;
+            }
+            n
+        }
+    }
+
+    #[test]
+    fn decompress_into_small_ring() {
+        let mut input = vec![1, 2, DEFAULT_ESC, 3];
+        input.resize(input.len() + 300, 0x42);
+        input.extend((0..200).map(|i| (i % 13) as u8));
+        input.resize(input.len() + 40, 0);
+
+        for codec in [
+            Codec::default(),
+            Codec::default().with_run_width(RunWidth::U16),
+        ] {
+            let compressed = compress_with(&codec, &input);
+            for per_push in [1, 3, 16] {
+                let mut ring = MockRing {
+                    buf: [0; 16],
+                    head: 0,
+                    len: 0,
+                    per_push,
+                };
+                let mut state = codec.decompressor();
+                let mut output = vec![];
+
+                // Feed the input in small pieces, and only drain the ring
+                // when the decompressor stops for want of room.
+                for mut chunk in compressed.chunks(5) {
+                    loop {
+                        let p =
+                            decompress_into(&mut state, &mut chunk, &mut ring);
+                        if chunk.is_empty() && p.produced == 0 {
+                            break;
+                        }
+                        ring.drain(&mut output);
+                    }
+                }
+                while !state.is_idle() {
+                    let p =
+                        decompress_into(&mut state, &mut &[][..], &mut ring);
+                    assert!(p.produced > 0);
+                    ring.drain(&mut output);
+                }
+                ring.drain(&mut output);
+
+                assert_eq!(output, input, "{codec:?}, {per_push} per push");
+            }
+        }
+
+        // A stored stream is copied straight through.
+        let codec = Codec::default();
+        let random: Vec<u8> = (0..100_u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        let stored = compress_or_store_with(&codec, &random);
+        assert_eq!(stored[0], FRAME_STORED);
+        let mut ring = MockRing {
+            buf: [0; 16],
+            head: 0,
+            len: 0,
+            per_push: 7,
+        };
+        let mut state = codec.framed_decompressor();
+        let mut output = vec![];
+        let mut rest = &stored[..];
+        while !rest.is_empty() {
+            decompress_into(&mut state, &mut rest, &mut ring);
+            ring.drain(&mut output);
         }
+        assert!(state.is_idle());
+        assert_eq!(output, random);
     }
 
-    &output[..n]
+    #[test]
+    fn reset_between_streams() {
+        let first = compress_with(&Codec::default(), &[1, 2, 3, 3, 3, 3, 3]);
+        let second = compress_with(&Codec::default(), &[4, 5, 6, 6, 6, 6]);
+
+        let mut state = Decompressor::default();
+        let mut output = [0; 32];
+
+        // Abandon the first stream partway through its run sequence...
+        let truncated = &first[..first.len() - 1];
+        decompress(&mut state, &mut &truncated[..], &mut output);
+        assert!(!state.is_idle());
+
+        // ...without which, the second stream would be misinterpreted.
+        state.reset();
+        assert!(state.is_idle());
+        let out = decompress(&mut state, &mut &second[..], &mut output);
+        assert_eq!(out, [4, 5, 6, 6, 6, 6]);
+        assert!(state.is_idle());
+
+        // And a complete stream can be followed by another one.
+        state.reset();
+        let out = decompress(&mut state, &mut &first[..], &mut output);
+        assert_eq!(out, [1, 2, 3, 3, 3, 3, 3]);
+        let out = decompress(&mut state, &mut &second[..], &mut output);
+        assert_eq!(out, [4, 5, 6, 6, 6, 6]);
+    }
+
+    fn compress_or_store_with(codec: &Codec, input: &[u8]) -> Vec<u8> {
+        let mut v = vec![];
+        codec
+            .compress_or_store(input, |c| {
+                v.extend_from_slice(c);
+                Ok::<_, std::convert::Infallible>(())
+            })
+            .unwrap();
+        v
+    }
+
+    fn decompress_framed(codec: &Codec, mut input: &[u8]) -> Vec<u8> {
+        let mut state = codec.framed_decompressor();
+        let mut output = vec![];
+        let mut buf = [0; 100];
+        loop {
+            let out = decompress(&mut state, &mut input, &mut buf);
+            if out.is_empty() {
+                break;
+            }
+            output.extend_from_slice(out);
+        }
+        assert!(input.is_empty());
+        assert!(state.is_idle());
+        output
+    }
+
+    #[test]
+    fn random_data_is_stored() {
+        // xorshift32, so we don't need a dependency for a few KiB of noise.
+        let mut x = 0x1234_5678_u32;
+        let input: Vec<u8> = (0..4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+
+        let codec = Codec::default();
+        assert!(codec.compressed_len(&input) > input.len());
+
+        let framed = compress_or_store_with(&codec, &input);
+        assert_eq!(framed[0], FRAME_STORED);
+        assert_eq!(&framed[1..], input);
+        assert_eq!(decompress_framed(&codec, &framed), input);
+    }
+
+    #[test]
+    fn compressible_data_is_compressed() {
+        let codec = Codec::new(0x55).unwrap();
+        let mut input = vec![1, 2, 0x55, 3];
+        input.resize(1000, 0);
+
+        let framed = compress_or_store_with(&codec, &input);
+        assert_eq!(framed[0], FRAME_COMPRESSED);
+        assert_eq!(&framed[1..], compress_with(&codec, &input));
+        assert_eq!(decompress_framed(&codec, &framed), input);
+
+        // Empty input gains nothing from compression, so it's stored.
+        assert_eq!(compress_or_store_with(&codec, &[]), [FRAME_STORED]);
+        assert_eq!(decompress_framed(&codec, &[FRAME_STORED]), []);
+    }
+
+    #[test]
+    fn framed_decompressor_rejects_bad_header() {
+        let mut state = Codec::default().framed_decompressor();
+        assert!(!state.is_idle());
+
+        let mut input = &[0x02, 1, 2, 3][..];
+        let mut output = [0; 8];
+        assert!(decompress(&mut state, &mut input, &mut output).is_empty());
+        assert!(state.is_invalid());
+        assert!(!state.is_idle());
+        assert_eq!(input, [1, 2, 3]);
+
+        state.reset();
+        assert!(!state.is_invalid());
+        let out =
+            decompress(&mut state, &mut &[FRAME_STORED, 7][..], &mut output);
+        assert_eq!(out, [7]);
+    }
+
+    #[test]
+    fn run_threshold_sizes() {
+        // Runs of every length from 1 to 8, plus a few lone escape bytes.
+        let mut input = vec![];
+        for len in 1..=8 {
+            for _ in 0..len {
+                input.push(len as u8);
+            }
+            input.push(DEFAULT_ESC);
+        }
+
+        let mut sizes = vec![];
+        for threshold in [3, 4, 5] {
+            let codec = Codec::default().with_run_threshold(threshold);
+            let compressed = compress_with(&codec, &input);
+            assert_eq!(codec.compressed_len(&input), compressed.len());
+
+            // Thresholds don't affect decompression.
+            let mut state = Decompressor::default();
+            let mut output = [0; 64];
+            let out = decompress(&mut state, &mut &compressed[..], &mut output);
+            assert_eq!(out, input);
+
+            sizes.push(compressed.len());
+        }
+
+        // A run of 3 costs three bytes either way, but a run of 4 is a byte
+        // shorter escaped.
+        assert_eq!(sizes[0], sizes[1]);
+        assert_eq!(sizes[2], sizes[1] + 1);
+        // 1+2+3 literal bytes, five escaped runs, and eight escaped escapes
+        assert_eq!(sizes[1], 6 + 5 * 3 + 8 * 3);
+    }
+
+    #[test]
+    fn codec_rejects_zero_esc() {
+        assert!(Codec::new(0x00).is_none());
+        assert_eq!(Codec::new(0x55).unwrap().esc(), 0x55);
+        assert_eq!(Codec::default().esc(), DEFAULT_ESC);
+    }
+
+    #[test]
+    fn custom_esc_round_trip() {
+        let codec = Codec::new(0x55).unwrap();
+        let input = [1, 2, 0x55, 7, 7, 7, 7, 7, 7, DEFAULT_ESC, 0x55, 0x55];
+        let compressed = compress_with(&codec, &input);
+
+        let mut state = codec.decompressor();
+        let mut output = [0; 32];
+        let out = decompress(&mut state, &mut &compressed[..], &mut output);
+        assert_eq!(out, input);
+        assert!(state.is_idle());
+    }
+
+    #[test]
+    fn mismatched_codec_does_not_round_trip() {
+        let input = [1, 2, 3, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 0x55, 4];
+        let compressed = compress_with(&Codec::default(), &input);
+
+        // The stream has no idea which escape byte was used, so the best we can
+        // hope for is that decoding with the wrong one produces something that
+        // visibly isn't the input, without panicking. Here, the literal `0x55`
+        // is taken as the start of a run that is never completed.
+        let mut state = Codec::new(0x55).unwrap().decompressor();
+        let mut output = [0; 32];
+        let out = decompress(&mut state, &mut &compressed[..], &mut output);
+        assert_ne!(out, input);
+        assert!(!state.is_idle());
+    }
+
+    #[test]
+    fn streaming_matches_whole_buffer() {
+        // Runs of every interesting length, including ones longer than a
+        // single escape sequence can hold, and lone escape bytes.
+        let mut input = vec![];
+        for (i, len) in [1, 3, 4, 5, 17, 256, 257, 600, 2, 1].iter().enumerate()
+        {
+            input.resize(input.len() + len, i as u8 * 3);
+            input.push(DEFAULT_ESC);
+        }
+
+        for codec in [
+            Codec::default(),
+            Codec::default().with_run_width(RunWidth::U16),
+        ] {
+            let expected = compress_with(&codec, &input);
+
+            for chunk_size in [1, 2, 3, 7, 64, 256, input.len()] {
+                let mut out = vec![];
+                let mut push = |c: &[u8]| {
+                    out.extend_from_slice(c);
+                    Ok::<_, std::convert::Infallible>(())
+                };
+                let mut compressor = codec.streaming_compressor();
+                for chunk in input.chunks(chunk_size) {
+                    compressor.push(chunk, &mut push).unwrap();
+                }
+                compressor.finish(&mut push).unwrap();
+
+                assert_eq!(
+                    out, expected,
+                    "chunk size {chunk_size}, codec {codec:?}"
+                );
+            }
+        }
+
+        // Finishing with nothing pending produces nothing.
+        let mut compressor = StreamingCompressor::default();
+        compressor
+            .finish(|_| Err::<(), _>("unexpected output"))
+            .unwrap();
+    }
+
+    #[test]
+    fn escape_bytes_round_trip() {
+        const E: u8 = DEFAULT_ESC;
+
+        // Any run of escape bytes -- even one that is too short to be worth
+        // escaping -- must be escaped, since a literal escape byte would be
+        // taken as the start of a run.
+        for len in [1, 2, 3, 5] {
+            let input = vec![E; len];
+            for threshold in [3, 4, 5] {
+                let codec = Codec::default().with_run_threshold(threshold);
+                let compressed = compress_with(&codec, &input);
+                assert_eq!(compressed, [E, E, len as u8 - 1], "len {len}");
+
+                let mut state = codec.decompressor();
+                let mut output = [0; 8];
+                let out =
+                    decompress(&mut state, &mut &compressed[..], &mut output);
+                assert_eq!(out, input);
+                assert!(state.is_idle());
+            }
+
+            let codec = Codec::default().with_run_width(RunWidth::U16);
+            let compressed = compress_with(&codec, &input);
+            assert_eq!(compressed, [E, E, len as u8 - 1, 0], "len {len}");
+        }
+
+        let interleaved: [&[u8]; 5] = [
+            &[E, 1],
+            &[1, E],
+            &[1, E, 1],
+            &[E, 1, E, E, 2, E, E, E],
+            &[0, E, 0, 0, E, E, 0, 0, 0, 0, E, E, E, E, E, 0],
+        ];
+        for input in interleaved {
+            for codec in [
+                Codec::default(),
+                Codec::default().with_run_threshold(5),
+                Codec::default().with_run_width(RunWidth::U16),
+            ] {
+                let compressed = compress_with(&codec, input);
+                assert_eq!(codec.compressed_len(input), compressed.len());
+
+                let mut state = codec.decompressor();
+                let mut output = [0; 32];
+                let out =
+                    decompress(&mut state, &mut &compressed[..], &mut output);
+                assert_eq!(out, input, "input {input:?}, codec {codec:?}");
+                assert!(state.is_idle());
+            }
+        }
+    }
 }
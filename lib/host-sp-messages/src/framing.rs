@@ -0,0 +1,261 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! COBS framing for messages sent over the control uart.
+//!
+//! Messages are COBS-encoded (via `corncobs`) and terminated by a single 0x00
+//! byte, so that either side can find the start of the next message after
+//! receiving noise or a partial packet. The helpers here combine that framing
+//! with [`serialize`](crate::serialize) and
+//! [`deserialize`](crate::deserialize).
+
+use crate::{
+    DataBlob, DecodeFailureReason, Header, HostToSp, HubpackError,
+    MAX_MESSAGE_SIZE, SEQ_REPLY,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Maximum length of a framed message, including the 0x00 terminator.
+pub const MAX_FRAME_SIZE: usize = corncobs::max_encoded_len(MAX_MESSAGE_SIZE);
+
+/// Serializes a message like [`try_serialize`](crate::try_serialize) into
+/// `msg`, then COBS-encodes it into `out`, returning the length of the frame
+/// (including its terminator).
+///
+/// # Errors
+///
+/// Fails under the same conditions as `try_serialize`.
+///
+/// # Panics
+///
+/// Panics if `fill_data` returns a size greater than the length of the slice it
+/// was given.
+pub fn try_encode_frame<F, S>(
+    msg: &mut [u8; MAX_MESSAGE_SIZE],
+    out: &mut [u8; MAX_FRAME_SIZE],
+    header: &Header,
+    command: &S,
+    fill_data: F,
+) -> Result<usize, HubpackError>
+where
+    F: FnOnce(&mut [u8]) -> Result<usize, S>,
+    S: Serialize,
+{
+    let n = crate::try_serialize(msg, header, command, fill_data)?;
+    Ok(corncobs::encode_buf(&msg[..n], out))
+}
+
+/// Infallible-`fill_data` version of [`try_encode_frame`], analogous to
+/// [`serialize`](crate::serialize).
+pub fn encode_frame<F>(
+    msg: &mut [u8; MAX_MESSAGE_SIZE],
+    out: &mut [u8; MAX_FRAME_SIZE],
+    header: &Header,
+    command: &impl Serialize,
+    fill_data: F,
+) -> Result<usize, HubpackError>
+where
+    F: FnOnce(&mut [u8]) -> usize,
+{
+    try_encode_frame(msg, out, header, command, |buf| Ok(fill_data(buf)))
+}
+
+/// Decodes a COBS frame in place and deserializes the message it contains
+/// like [`deserialize`](crate::deserialize).
+///
+/// `frame` must include the 0x00 terminator. Decoding happens in place (the
+/// decoded message is never longer than its encoding), so `frame` is
+/// overwritten and the returned data blob borrows from it.
+///
+/// # Errors
+///
+/// Returns [`DecodeFailureReason::Cobs`] if `frame` is not a valid COBS
+/// frame, and otherwise fails under the same conditions as `deserialize`.
+pub fn decode_frame<T: DeserializeOwned>(
+    frame: &mut [u8],
+) -> Result<(Header, T, &[u8]), DecodeFailureReason> {
+    let n = corncobs::decode_in_place(frame)
+        .map_err(|_| DecodeFailureReason::Cobs)?;
+    crate::deserialize(&frame[..n])
+}
+
+/// Decodes a COBS frame like [`decode_frame`], but deserializes the message
+/// with [`deserialize_strict`](crate::deserialize_strict).
+pub fn decode_frame_strict<T: DeserializeOwned + DataBlob>(
+    frame: &mut [u8],
+) -> Result<(Header, T, &[u8]), DecodeFailureReason> {
+    let n = corncobs::decode_in_place(frame)
+        .map_err(|_| DecodeFailureReason::Cobs)?;
+    crate::deserialize_strict(&frame[..n])
+}
+
+/// Decodes a request from the host like [`decode_frame_strict`], additionally
+/// rejecting a sequence number with [`SEQ_REPLY`] set, since only SP replies
+/// carry that bit.
+pub fn decode_host_request(
+    frame: &mut [u8],
+) -> Result<(Header, HostToSp, &[u8]), DecodeFailureReason> {
+    let (header, request, data) = decode_frame_strict::<HostToSp>(frame)?;
+
+    if header.sequence & SEQ_REPLY != 0 {
+        return Err(DecodeFailureReason::SequenceInvalid);
+    }
+
+    Ok((header, request, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{version, SpToHost, MAGIC};
+
+    fn header() -> Header {
+        Header {
+            magic: MAGIC,
+            version: version::V2,
+            sequence: 0x1234_5678_9abc_def0,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut msg = [0; MAX_MESSAGE_SIZE];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode_frame(
+            &mut msg,
+            &mut frame,
+            &header(),
+            &HostToSp::GetStatus,
+            |_| 0,
+        )
+        .unwrap();
+
+        // The only zero byte is the terminator.
+        assert_eq!(frame[n - 1], 0);
+        assert!(!frame[..n - 1].contains(&0));
+
+        let (h, command, data) =
+            decode_frame::<HostToSp>(&mut frame[..n]).unwrap();
+        assert_eq!(h, header());
+        assert_eq!(command, HostToSp::GetStatus);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn roundtrip_embedded_zeros() {
+        let blob = [0x00, 0x01, 0x00, 0x00, 0xff, 0x00];
+        let mut msg = [0; MAX_MESSAGE_SIZE];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode_frame(
+            &mut msg,
+            &mut frame,
+            &header(),
+            &SpToHost::Phase2Data,
+            |buf| {
+                buf[..blob.len()].copy_from_slice(&blob);
+                blob.len()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(frame[n - 1], 0);
+        assert!(!frame[..n - 1].contains(&0));
+
+        let (h, command, data) =
+            decode_frame_strict::<SpToHost>(&mut frame[..n]).unwrap();
+        assert_eq!(h, header());
+        assert_eq!(command, SpToHost::Phase2Data);
+        assert_eq!(data, blob);
+    }
+
+    #[test]
+    fn roundtrip_boot_failure() {
+        let message = b"no bootable device";
+        let mut msg = [0; MAX_MESSAGE_SIZE];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode_frame(
+            &mut msg,
+            &mut frame,
+            &header(),
+            &HostToSp::HostBootFailure { reason: 3 },
+            |buf| {
+                buf[..message.len()].copy_from_slice(message);
+                message.len()
+            },
+        )
+        .unwrap();
+
+        let (_, command, data) =
+            decode_frame_strict::<HostToSp>(&mut frame[..n]).unwrap();
+        assert_eq!(command, HostToSp::HostBootFailure { reason: 3 });
+        assert_eq!(data, message);
+    }
+
+    #[test]
+    fn host_request() {
+        let message = b"no bootable device";
+        let mut msg = [0; MAX_MESSAGE_SIZE];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let mut encode = |frame: &mut _, header: &Header| {
+            encode_frame(
+                &mut msg,
+                frame,
+                header,
+                &HostToSp::HostBootFailure { reason: 3 },
+                |buf| {
+                    buf[..message.len()].copy_from_slice(message);
+                    message.len()
+                },
+            )
+            .unwrap()
+        };
+
+        // A boot failure keeps its message...
+        let n = encode(&mut frame, &header());
+        let (h, command, data) = decode_host_request(&mut frame[..n]).unwrap();
+        assert_eq!(h, header());
+        assert_eq!(command, HostToSp::HostBootFailure { reason: 3 });
+        assert_eq!(data, message);
+
+        // ...but a request can't look like one of our replies.
+        let mut reply = header();
+        reply.sequence |= SEQ_REPLY;
+        let n = encode(&mut frame, &reply);
+        assert_eq!(
+            decode_host_request(&mut frame[..n]),
+            Err(DecodeFailureReason::SequenceInvalid)
+        );
+    }
+
+    #[test]
+    fn roundtrip_max_size() {
+        let mut msg = [0; MAX_MESSAGE_SIZE];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode_frame(
+            &mut msg,
+            &mut frame,
+            &header(),
+            &SpToHost::Phase2Data,
+            |buf| {
+                buf.fill(0);
+                buf.len()
+            },
+        )
+        .unwrap();
+        assert!(n <= MAX_FRAME_SIZE);
+
+        let (_, _, data) = decode_frame::<SpToHost>(&mut frame[..n]).unwrap();
+        assert!(data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn bad_frame() {
+        // An unterminated frame
+        let mut frame = [0x03, 0x01, 0x02];
+        assert_eq!(
+            decode_frame::<HostToSp>(&mut frame),
+            Err(DecodeFailureReason::Cobs)
+        );
+    }
+}
@@ -7,6 +7,7 @@
 
 #![cfg_attr(not(test), no_std)]
 
+use enum_kinds::EnumKind;
 use hubpack::SerializedSize;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_big_array::BigArray;
@@ -15,14 +16,23 @@ use static_assertions::{const_assert, const_assert_eq};
 use unwrap_lite::UnwrapLite;
 use zerocopy::{AsBytes, FromBytes};
 
+#[cfg(feature = "framing")]
+pub mod framing;
+
+pub use drv_i2c_types::MAX_DEVICE_NAME_LEN;
 pub use hubpack::error::Error as HubpackError;
 
 /// Magic value for [`Header::magic`].
 pub const MAGIC: u32 = 0x01de_19cc;
 
+/// Bit set in the sequence number of every SP reply, and never in a host
+/// request.
+pub const SEQ_REPLY: u64 = 0x8000_0000_0000_0000;
+
 /// Maximum message length.
 ///
-/// Does not include framing overhead for packetization (e.g., cobs).
+/// Does not include framing overhead for packetization (e.g., cobs); see
+/// `framing::MAX_FRAME_SIZE` for that.
 // Value from RFD316:
 //   4KiB + header size + crc size + sizeof(u64)
 // In RFD316, "header size" includes one byte more than our `Header` struct: the
@@ -37,17 +47,106 @@ pub const MAX_MESSAGE_SIZE: usize = 4123;
 /// to be _at least_ this long, regardless of the particular `SpToHost` response
 /// being sent. It will be longer than this for any `SpToHost` variants that
 /// serialize to a sequence shorter than `SpToHost::MAX_SIZE`.
-pub const MIN_SP_TO_HOST_FILL_DATA_LEN: usize =
-    MAX_MESSAGE_SIZE - Header::MAX_SIZE - CHECKSUM_SIZE - SpToHost::MAX_SIZE;
+pub const MIN_SP_TO_HOST_FILL_DATA_LEN: usize = MAX_MESSAGE_SIZE
+    - Header::MAX_SIZE
+    - MAX_CHECKSUM_SIZE
+    - SpToHost::MAX_SIZE;
+
+/// Size of the largest checksum trailer used by any protocol version.
+const MAX_CHECKSUM_SIZE: usize = core::mem::size_of::<u32>();
+
+/// Size of the largest message carrying a `T` (typically [`HostToSp`] or
+/// [`SpToHost`]) with no trailing data, under any protocol version.
+///
+/// A buffer of this size may be given to [`try_serialize_slice`] in place of
+/// a full `[u8; MAX_MESSAGE_SIZE]` by a task that only sends commands without
+/// a data blob: there's always room for the header, the command (or, if
+/// `fill_data` fails, its error, which is also a `T`) and the checksum.
+/// `fill_data` is then given an empty (or nearly so) slice, and any data it
+/// tries to write is rejected rather than overflowing the buffer.
+pub const fn max_size_for<T: SerializedSize>() -> usize {
+    Header::MAX_SIZE + T::MAX_SIZE + MAX_CHECKSUM_SIZE
+}
+
+/// Size of the checksum trailer for messages with the given protocol version.
+///
+/// Versions we don't know about are assumed to use the original Fletcher-16
+/// trailer.
+pub const fn checksum_size(version: u32) -> usize {
+    match version {
+        version::V2 => core::mem::size_of::<u32>(),
+        _ => core::mem::size_of::<u16>(),
+    }
+}
+
+/// Amount of space available for data trailing after a particular `SpToHost`
+/// response.
+///
+/// This is exactly the length of the buffer `serialize` will pass to its
+/// `fill_data` callback when sending `command` with a header of the given
+/// `version`, and is at least [`MIN_SP_TO_HOST_FILL_DATA_LEN`].
+pub fn sp_to_host_fill_data_len(version: u32, command: &SpToHost) -> usize {
+    let mut buf = [0; SpToHost::MAX_SIZE];
+    // `buf` is large enough for any `SpToHost`, so this cannot fail.
+    let command_len = hubpack::serialize(&mut buf, command).unwrap_lite();
+    MAX_MESSAGE_SIZE - Header::MAX_SIZE - checksum_size(version) - command_len
+}
+
+static CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Running checksum over a message, using the trailer algorithm selected by
+/// its protocol version.
+enum Checksum {
+    Fletcher16(fletcher::Fletcher16),
+    Crc32(crc::Digest<'static, u32>),
+}
+
+impl Checksum {
+    fn new(version: u32) -> Self {
+        match version {
+            version::V2 => Self::Crc32(CRC32.digest()),
+            _ => Self::Fletcher16(fletcher::Fletcher16::new()),
+        }
+    }
 
-const CHECKSUM_SIZE: usize = core::mem::size_of::<u16>();
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Fletcher16(c) => c.update(data),
+            Self::Crc32(c) => c.update(data),
+        }
+    }
+
+    /// Writes the little-endian checksum to the front of `out`, returning the
+    /// number of bytes written.
+    fn finish(self, out: &mut [u8]) -> usize {
+        match self {
+            Self::Fletcher16(c) => {
+                let v = c.value().to_le_bytes();
+                out[..v.len()].copy_from_slice(&v);
+                v.len()
+            }
+            Self::Crc32(c) => {
+                let v = c.finalize().to_le_bytes();
+                out[..v.len()].copy_from_slice(&v);
+                v.len()
+            }
+        }
+    }
+}
 
 pub type SensorIndex = u32;
 
 pub mod version {
+    /// Messages are followed by a Fletcher-16 checksum.
     pub const V1: u32 = 1;
+    /// Messages are followed by a CRC-32 (as used by zlib and Ethernet).
+    pub const V2: u32 = 2;
 }
 
+/// Protocol versions understood by this crate; [`deserialize`] rejects messages
+/// whose [`Header::version`] is not in this list.
+pub const SUPPORTED_VERSIONS: &[u32] = &[version::V1, version::V2];
+
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
 )]
@@ -59,6 +158,18 @@ pub struct Header {
     // (depending on the variant of those enums), then a 16-bit checksum.
 }
 
+impl Header {
+    /// Returns a header for a message with the given sequence number, using
+    /// [`MAGIC`] and the current protocol version.
+    pub const fn new(sequence: u64) -> Self {
+        Self {
+            magic: MAGIC,
+            version: version::V1,
+            sequence,
+        }
+    }
+}
+
 /// The order of these cases is critical! We are relying on hubpack's encoding
 /// of enum variants being 0-indexed and using a single byte. The order of
 /// variants in this enum produces a mapping of these that matches both RFD 316
@@ -89,6 +200,7 @@ pub enum HostToSp {
     GetBootStorageUnit,
     GetIdentity,
     GetMacAddresses,
+    // Followed by a binary data blob (the failure message)
     HostBootFailure {
         reason: u8,
     },
@@ -101,6 +213,12 @@ pub enum HostToSp {
     RotAddHostMeasurements, // Followed by a binary data blob?
     /// Get as much phase 2 data as we can from the image identified by `hash`
     /// starting at `offset`.
+    ///
+    /// The SP replies with `SpToHost::Phase2Data`.  If `offset` is at or past
+    /// the end of the image, that response succeeds with an empty data blob
+    /// (see [`phase2_data_at`]); the host should treat an empty blob as having
+    /// no more data at `offset`, not as a failed transfer, and may retry later
+    /// if it expected more.
     GetPhase2Data {
         hash: [u8; 32],
         offset: u64,
@@ -123,6 +241,16 @@ pub enum HostToSp {
         // We use a raw `u8` here for the same reason as in `KeyLookup` above.
         key: u8,
     },
+    /// Get inventory data for up to `count` devices, starting at `start`
+    /// (command value 0x11).
+    ///
+    /// The SP replies with `SpToHost::InventoryDataRange`, which may contain
+    /// fewer entries than requested if they don't all fit in one message; the
+    /// host should then resume from the next index.
+    GetInventoryDataRange {
+        start: u32,
+        count: u16,
+    },
 }
 
 /// The order of these cases is critical! We are relying on hubpack's encoding
@@ -171,7 +299,8 @@ pub enum SpToHost {
     },
     // Followed by a binary data blob (the response)
     RotResponse,
-    // Followed by a binary data blob (the data)
+    // Followed by a binary data blob (the data), which is empty if there is no
+    // data at the requested offset
     Phase2Data,
     // If `result` is `KeyLookupResult::Ok`, this will be followed by a binary
     // blob of length at most `max_response_len` from the corresponding request.
@@ -182,9 +311,116 @@ pub enum SpToHost {
     InventoryData {
         #[count(children)]
         result: InventoryDataResult,
-        name: [u8; 32],
+        name: [u8; MAX_DEVICE_NAME_LEN],
     },
     KeySetResult(#[count(children)] KeySetResult),
+    /// Response to `HostToSp::GetInventoryDataRange` (command value 0x0d).
+    ///
+    /// This is followed by a binary blob of `count` entries for consecutive
+    /// indices beginning at `start`.  Each entry is a hubpack-serialized
+    /// `InventoryDataRangeEntry`; if its `result` is `InventoryDataResult::Ok`,
+    /// it is immediately followed by a hubpack-serialized `InventoryData`.
+    InventoryDataRange {
+        start: u32,
+        count: u16,
+    },
+    /// Several responses packed into one message (command value 0x0e).
+    ///
+    /// This is followed by a binary blob of `count` entries, each a
+    /// little-endian `u16` length followed by that many bytes: a
+    /// hubpack-serialized `SpToHost` and any data blob belonging to it.  See
+    /// [`serialize_batch`] and [`BatchEntries`].
+    Batch {
+        count: u8,
+    },
+}
+
+/// Header for one entry in the data blob following
+/// [`SpToHost::InventoryDataRange`]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub struct InventoryDataRangeEntry {
+    pub result: InventoryDataResult,
+    pub name: [u8; MAX_DEVICE_NAME_LEN],
+}
+
+/// Returns the phase 2 data to send in response to
+/// [`HostToSp::GetPhase2Data`] for `offset` into `image`: everything from
+/// `offset` to the end of the image, which is empty (rather than an error) if
+/// `offset` is at or past the end.
+///
+/// The caller sends as much of the result as fits in the response.
+pub fn phase2_data_at(image: &[u8], offset: u64) -> &[u8] {
+    usize::try_from(offset)
+        .ok()
+        .and_then(|offset| image.get(offset..))
+        .unwrap_or(&[])
+}
+
+/// Expands the `base`/`count`/`stride` of [`SpToHost::MacAddresses`] into
+/// the individual addresses it describes.
+///
+/// Address `i` is `base + i * stride`, treating `base` as a single 48-bit
+/// big-endian integer, so adding the stride carries from the low byte into
+/// the higher ones. Addresses wrap around at the top of the 48-bit space.
+pub fn iter_mac_addresses(
+    base: [u8; 6],
+    count: u16,
+    stride: u8,
+) -> impl Iterator<Item = [u8; 6]> {
+    const MASK: u64 = (1 << 48) - 1;
+
+    let mut wide = [0; 8];
+    wide[2..].copy_from_slice(&base);
+    let base = u64::from_be_bytes(wide);
+
+    (0..u64::from(count)).map(move |i| {
+        let addr = base.wrapping_add(i * u64::from(stride)) & MASK;
+        let mut out = [0; 6];
+        out.copy_from_slice(&addr.to_be_bytes()[2..]);
+        out
+    })
+}
+
+/// Commands which may be followed by a binary data blob
+///
+/// This is used by [`deserialize_strict`] to reject trailing bytes after
+/// commands which are not documented to carry a blob.
+pub trait DataBlob {
+    /// Returns `true` if this command may be followed by a data blob
+    fn has_data_blob(&self) -> bool;
+}
+
+impl DataBlob for HostToSp {
+    fn has_data_blob(&self) -> bool {
+        matches!(
+            self,
+            HostToSp::HostBootFailure { .. }
+                | HostToSp::HostPanic
+                | HostToSp::RotRequest
+                | HostToSp::RotAddHostMeasurements
+                | HostToSp::KeySet { .. }
+        )
+    }
+}
+
+impl DataBlob for SpToHost {
+    fn has_data_blob(&self) -> bool {
+        matches!(
+            self,
+            SpToHost::Alert { .. }
+                | SpToHost::RotResponse
+                | SpToHost::Phase2Data
+                | SpToHost::KeyLookupResult(KeyLookupResult::Ok)
+                | SpToHost::InventoryData {
+                    result: InventoryDataResult::Ok,
+                    ..
+                }
+                | SpToHost::InventoryDataRange { .. }
+                | SpToHost::Batch { .. }
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, num_derive::FromPrimitive)]
@@ -198,6 +434,9 @@ pub enum Key {
     EtcSystem,
     /// `/kernel/drv/dtrace.conf` file content
     DtraceConf,
+    /// Git revision the SP firmware was built from, as an ASCII string (the
+    /// commit hash, with a `-dirty` suffix if built from a modified tree)
+    SpGitVersion,
 }
 
 #[derive(
@@ -292,9 +531,24 @@ impl From<drv_i2c_types::ResponseCode> for InventoryDataResult {
 /// These **cannot be reordered**; the host and SP must agree on them.  New
 /// variants may be added to the end, and existing variants may be extended with
 /// new data (at the end), but no changes should be made to existing bytes.
+///
+/// Each variant has a matching fieldless [`InventoryDataKind`], whose
+/// discriminant is the variant's index on the wire.  Boards describe their
+/// inventory as a slice of kinds (one per index), so that the count reported
+/// for [`Key::InventorySize`] and the set of valid indices come from the same
+/// table.
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    SerializedSize,
+    EnumKind,
 )]
+#[enum_kind(InventoryDataKind, derive(Deserialize))]
 pub enum InventoryData {
     /// Raw DIMM data
     DimmSpd {
@@ -448,8 +702,11 @@ pub enum InventoryData {
     },
 
     Ksz8463 {
-        /// Contents of the CIDER register
+        /// Contents of the CIDER register (family, chip, and revision IDs)
         cider: u16,
+        /// Contents of the CFGR register, which reports the strap-selected
+        /// configuration of the part
+        cfgr: u16,
     },
 
     Max5970 {
@@ -458,7 +715,153 @@ pub enum InventoryData {
     },
 
     /// MAX31790 fan controller
-    Max31790 { speed_sensors: [SensorIndex; 6] },
+    ///
+    /// The part has no identity or revision registers; instead, we report the
+    /// contents of its configuration registers.
+    Max31790 {
+        speed_sensors: [SensorIndex; 6],
+        /// Global Configuration register (0x00)
+        global_configuration: u8,
+        /// PWM Frequency register (0x01)
+        pwm_frequency: u8,
+        /// Fan 1-6 Configuration registers (0x02-0x07)
+        fan_configuration: [u8; 6],
+    },
+
+    /// LTC4306 I2C mux
+    Ltc4306 {
+        /// 7-bit I2C address of the mux
+        address: u8,
+        /// Contents of registers 0-3 (status, GPIO and accelerator control,
+        /// configuration, and bus connection state)
+        registers: [u8; 4],
+    },
+}
+
+// Every `InventoryData` value must fit in a single response message, so that
+// adding a large variant is a build failure rather than a runtime
+// `SerializationError`.
+const_assert!(
+    Header::MAX_SIZE
+        + SpToHost::MAX_SIZE
+        + InventoryData::MAX_SIZE
+        + MAX_CHECKSUM_SIZE
+        <= MAX_MESSAGE_SIZE
+);
+
+// Similarly, an `InventoryDataRange` response must always have room for at
+// least one entry, or the host could never make progress.
+const_assert!(
+    InventoryDataRangeEntry::MAX_SIZE + InventoryData::MAX_SIZE
+        <= MIN_SP_TO_HOST_FILL_DATA_LEN
+);
+
+/// DRAM device type, as reported by the key byte of a DIMM's SPD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdDeviceType {
+    Ddr4,
+    Ddr5,
+    /// Any other key byte value
+    Other(u8),
+}
+
+impl SpdDeviceType {
+    /// Offset of the key byte ("DRAM Device Type") within the SPD; this is the
+    /// same for DDR4 and DDR5.
+    pub const KEY_BYTE: usize = 2;
+
+    const KEY_DDR4: u8 = 0x0c;
+    const KEY_DDR5: u8 = 0x12;
+
+    /// Classifies raw SPD data by its key byte, returning `None` if `spd` is
+    /// too short to contain one.
+    pub fn from_spd(spd: &[u8]) -> Option<Self> {
+        Some(match *spd.get(Self::KEY_BYTE)? {
+            Self::KEY_DDR4 => Self::Ddr4,
+            Self::KEY_DDR5 => Self::Ddr5,
+            other => Self::Other(other),
+        })
+    }
+}
+
+impl InventoryData {
+    /// Size of the SPD data carried by [`InventoryData::DimmSpd`]
+    pub const DDR4_SPD_SIZE: usize = 512;
+
+    /// Builds the inventory variant for raw DIMM SPD data, checking the SPD's
+    /// key byte so that we can't report data for the wrong kind of part.
+    ///
+    /// Only DDR4 (via [`InventoryData::DimmSpd`]) is currently representable;
+    /// a DDR5 SPD is 1024 bytes and has no variant here, so it (like any
+    /// other device type, or a buffer of the wrong size) is rejected with
+    /// [`InventoryDataResult::DeviceFailed`].
+    pub fn dimm_spd(
+        spd: &[u8],
+        temp_sensor: SensorIndex,
+    ) -> Result<Self, InventoryDataResult> {
+        match SpdDeviceType::from_spd(spd) {
+            Some(SpdDeviceType::Ddr4) => {
+                let id = spd
+                    .try_into()
+                    .map_err(|_| InventoryDataResult::DeviceFailed)?;
+                Ok(Self::DimmSpd { id, temp_sensor })
+            }
+            _ => Err(InventoryDataResult::DeviceFailed),
+        }
+    }
+
+    /// Deserializes an `InventoryData` from the front of `blob`, tolerating
+    /// variants newer than this build of the crate.
+    ///
+    /// A host running older software than the SP may be sent a variant it
+    /// doesn't know; rather than failing (and stalling the inventory walk),
+    /// this returns [`InventoryDecode::Unknown`] so that the caller can skip
+    /// that index and carry on.  Because hubpack isn't self-describing, the
+    /// length of an unknown variant can't be known, so it's assumed to extend
+    /// to the end of `blob`.  In an [`SpToHost::InventoryDataRange`] blob,
+    /// that means nothing after an unknown entry can be decoded; the host
+    /// should re-request the following indices individually.
+    ///
+    /// Errors for known variants (e.g. a truncated blob) are returned as-is.
+    pub fn deserialize_forward_compatible(
+        blob: &[u8],
+    ) -> Result<(InventoryDecode<'_>, &[u8]), HubpackError> {
+        // `InventoryDataKind` shares our discriminants, so it only fails to
+        // deserialize if the blob is empty or the discriminant is unknown.
+        match hubpack::deserialize::<InventoryDataKind>(blob) {
+            Ok(_) => {
+                let (data, rest) = hubpack::deserialize::<Self>(blob)?;
+                Ok((InventoryDecode::Known(data), rest))
+            }
+            Err(err) => match blob.split_first() {
+                Some((&discriminant, raw)) => {
+                    Ok((InventoryDecode::Unknown { discriminant, raw }, &[]))
+                }
+                None => Err(err),
+            },
+        }
+    }
+}
+
+/// The result of [`InventoryData::deserialize_forward_compatible`].
+///
+/// This is a separate type (rather than an `Unknown` variant of
+/// [`InventoryData`]) because every `InventoryData` variant occupies a
+/// discriminant on the wire; a placeholder variant would collide with the
+/// next real one added.  It only exists on the decode (host) side, and is
+/// never sent by the SP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
+pub enum InventoryDecode<'a> {
+    Known(InventoryData),
+    /// A variant this build doesn't recognize
+    Unknown {
+        /// The variant's discriminant (its index in the SP's `InventoryData`)
+        discriminant: u8,
+        /// The serialized contents of the variant, through the end of the
+        /// blob
+        raw: &'a [u8],
+    },
 }
 
 #[derive(
@@ -547,6 +950,7 @@ pub enum DecodeFailureReason {
     VersionMismatch = 0x05,
     SequenceInvalid = 0x06,
     DataLengthInvalid = 0x07,
+    TrailingData = 0x08,
 }
 
 // We're using serde_repr for `Bsu`, so we have to supply our own
@@ -678,6 +1082,57 @@ impl From<HostStartupOptions> for gateway_messages::StartupOptions {
     }
 }
 
+/// Error returned by [`Status::try_from_bits`] and
+/// [`HostStartupOptions::try_from_bits`] when the raw value has bits set that
+/// this version of the crate doesn't define; carries just those bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnknownBits(pub u64);
+
+macro_rules! impl_raw_flags {
+    ($name:ident) => {
+        impl $name {
+            /// Size of the raw representation in bytes.
+            pub const SIZE: usize = core::mem::size_of::<Self>();
+
+            /// Returns the raw (native-endian) representation, suitable for
+            /// logging or persisting and later passing to
+            /// [`Self::from_bytes`].
+            pub fn into_bytes(self) -> [u8; Self::SIZE] {
+                let mut out = [0; Self::SIZE];
+                out.copy_from_slice(self.as_bytes());
+                out
+            }
+
+            /// Reconstructs a value from its raw representation, keeping any
+            /// bits we don't know about. Returns `None` if `bytes` is not
+            /// exactly [`Self::SIZE`] bytes long.
+            pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                Self::read_from(bytes)
+            }
+
+            /// Like `from_bits`, but on failure reports which bits are
+            /// unknown rather than discarding them.
+            pub fn try_from_bits(bits: u64) -> Result<Self, UnknownBits> {
+                let unknown = bits & !Self::all().bits();
+                if unknown == 0 {
+                    Ok(Self::from_bits_retain(bits))
+                } else {
+                    Err(UnknownBits(unknown))
+                }
+            }
+
+            /// Returns true if any bits are set that this version of the crate
+            /// doesn't define (e.g. sent by a newer peer).
+            pub fn contains_unknown_bits(&self) -> bool {
+                self.bits() & !Self::all().bits() != 0
+            }
+        }
+    };
+}
+
+impl_raw_flags!(Status);
+impl_raw_flags!(HostStartupOptions);
+
 /// Serializes a response packet containing
 ///
 /// ```text
@@ -695,20 +1150,70 @@ impl From<HostStartupOptions> for gateway_messages::StartupOptions {
 ///
 /// # Errors
 ///
-/// Only fails if `command` fails to serialize into
-/// `out[header_length..out.len() - 2]` (i.e., the space available between the
-/// header and our trailing checksum).
+/// Only fails if `command` fails to serialize into the space available between
+/// the header and our trailing checksum, whose algorithm and size are selected
+/// by `header.version` (see [`checksum_size`]).
 ///
 /// # Panics
 ///
 /// Panics if `fill_data` returns a size greater than the length of the slice it
-/// was given.
+/// was given; see [`try_serialize_checked`] for a non-panicking alternative.
 pub fn try_serialize<F, S>(
     out: &mut [u8; MAX_MESSAGE_SIZE],
     header: &Header,
     command: &S,
     fill_data: F,
 ) -> Result<usize, HubpackError>
+where
+    F: FnOnce(&mut [u8]) -> Result<usize, S>,
+    S: Serialize,
+{
+    try_serialize_checked(out, header, command, |buf| {
+        let len = buf.len();
+        let n = fill_data(buf)?;
+        assert!(n <= len);
+        Ok(n)
+    })
+}
+
+/// Serializes a response packet like [`try_serialize`], but without panicking
+/// if `fill_data` misbehaves.
+///
+/// # Errors
+///
+/// In addition to the errors returned by `try_serialize`, returns
+/// [`HubpackError::Custom`] if `fill_data` returns a size greater than the
+/// length of the slice it was given; the contents of `out` are unspecified in
+/// that case.
+pub fn try_serialize_checked<F, S>(
+    out: &mut [u8; MAX_MESSAGE_SIZE],
+    header: &Header,
+    command: &S,
+    fill_data: F,
+) -> Result<usize, HubpackError>
+where
+    F: FnOnce(&mut [u8]) -> Result<usize, S>,
+    S: Serialize,
+{
+    try_serialize_slice(out, header, command, fill_data)
+}
+
+/// Serializes a response packet like [`try_serialize_checked`], but into a
+/// slice of any length rather than a full `[u8; MAX_MESSAGE_SIZE]`; see
+/// [`max_size_for`] for how small it can safely be. `fill_data` is given
+/// whatever space remains between the command and the checksum.
+///
+/// # Errors
+///
+/// In addition to the errors returned by `try_serialize_checked`, returns
+/// [`HubpackError::Overrun`] if `out` is too small to hold the header,
+/// `command` (or the error returned by `fill_data`), and the checksum.
+pub fn try_serialize_slice<F, S>(
+    out: &mut [u8],
+    header: &Header,
+    command: &S,
+    fill_data: F,
+) -> Result<usize, HubpackError>
 where
     F: FnOnce(&mut [u8]) -> Result<usize, S>,
     S: Serialize,
@@ -716,30 +1221,41 @@ where
     let header_len = hubpack::serialize(out, header)?;
     let mut n = header_len;
 
-    // We know `Header::MAX_SIZE` is much smaller than out.len(), so this
-    // subtraction can't underflow. We don't know how big `command` will be, but
-    // (a) `hubpack::serialize()` will fail if it's too large, and (b) if
-    // serialization succeeds, this subtraction guarantees space for our
-    // trailing checksum.
-    let out_data_end = out.len() - CHECKSUM_SIZE;
+    // We don't know how big `command` will be, but (a) `hubpack::serialize()`
+    // will fail if it's too large, and (b) if serialization succeeds, this
+    // subtraction guarantees space for our trailing checksum.
+    let out_data_end = out
+        .len()
+        .checked_sub(checksum_size(header.version))
+        .filter(|&end| end >= n)
+        .ok_or(HubpackError::Overrun)?;
 
     n += hubpack::serialize(&mut out[n..out_data_end], command)?;
 
+    // Accumulate the checksum as we go, rather than making a second pass over
+    // the whole message at the end.
+    let mut checksum = Checksum::new(header.version);
+    checksum.update(&out[..n]);
+
     match fill_data(&mut out[n..out_data_end]) {
         Ok(data_this_message) => {
-            assert!(data_this_message <= out_data_end - n);
+            if data_this_message > out_data_end - n {
+                return Err(HubpackError::Custom);
+            }
+            checksum.update(&out[n..][..data_this_message]);
             n += data_this_message;
         }
         Err(e) => {
+            // We've overwritten `command`, so the checksum we've accumulated
+            // so far is no good; start over from the header.
             n = header_len;
             n += hubpack::serialize(&mut out[n..out_data_end], &e)?;
+            checksum = Checksum::new(header.version);
+            checksum.update(&out[..n]);
         }
     }
 
-    // Compute checksum over the full message.
-    let checksum = fletcher::calc_fletcher16(&out[..n]);
-    out[n..][..CHECKSUM_SIZE].copy_from_slice(&checksum.to_le_bytes()[..]);
-    n += CHECKSUM_SIZE;
+    n += checksum.finish(&mut out[n..]);
 
     Ok(n)
 }
@@ -756,6 +1272,166 @@ where
     try_serialize(out, header, command, |buf| Ok(fill_data(buf)))
 }
 
+/// Serializes an [`SpToHost::Ack`] response with sequence number `sequence`,
+/// producing exactly the bytes [`serialize`] would for the same message
+/// (using the protocol version of [`Header::new`]).
+///
+/// Acks carry no data, so this writes the fixed-size header and command
+/// directly rather than going through hubpack and a data closure; it exists
+/// to keep the per-message cost of keepalives down.
+pub fn serialize_ack(out: &mut [u8; MAX_MESSAGE_SIZE], sequence: u64) -> usize {
+    // hubpack encodes integers little-endian with no padding, so the header
+    // is exactly its three fields back to back; it encodes a unit variant as
+    // its index, which for `Ack` is 1 (after `SpToHost::_Unused`).
+    const_assert_eq!(Header::MAX_SIZE, 16);
+    const ACK: u8 = 1;
+
+    let header = Header::new(sequence);
+    out[..4].copy_from_slice(&header.magic.to_le_bytes());
+    out[4..8].copy_from_slice(&header.version.to_le_bytes());
+    out[8..16].copy_from_slice(&header.sequence.to_le_bytes());
+    out[16] = ACK;
+    let mut n = 17;
+
+    let mut checksum = Checksum::new(header.version);
+    checksum.update(&out[..n]);
+    n += checksum.finish(&mut out[n..]);
+
+    n
+}
+
+/// Serializes an [`SpToHost::Batch`] response packing as many of `items` as
+/// will fit (in order) into one message, each a response and its data blob.
+///
+/// Returns the length of the message and the number of items packed; any
+/// items beyond that should be sent in a subsequent batch.  Items should not
+/// themselves be batches.
+///
+/// # Errors
+///
+/// Returns an error only if hubpack fails to serialize the header.
+pub fn serialize_batch(
+    out: &mut [u8; MAX_MESSAGE_SIZE],
+    header: &Header,
+    items: &[(SpToHost, &[u8])],
+) -> Result<(usize, usize), HubpackError> {
+    // The encoded size of `Batch` doesn't depend on `count`, so we can find
+    // how many entries will fit before we know it.
+    let mut avail =
+        sp_to_host_fill_data_len(header.version, &SpToHost::Batch { count: 0 });
+    let mut count = 0;
+    for (command, data) in items.iter().take(usize::from(u8::MAX)) {
+        let mut buf = [0; SpToHost::MAX_SIZE];
+        // `buf` is large enough for any `SpToHost`, so this cannot fail.
+        let command_len = hubpack::serialize(&mut buf, command).unwrap_lite();
+        let entry_len = BATCH_ENTRY_LEN_SIZE + command_len + data.len();
+        if entry_len > avail {
+            break;
+        }
+        avail -= entry_len;
+        count += 1;
+    }
+
+    let command = SpToHost::Batch { count: count as u8 };
+    let n = serialize(out, header, &command, |buf| {
+        let mut n = 0;
+        for (command, data) in &items[..count] {
+            let start = n;
+            n += BATCH_ENTRY_LEN_SIZE;
+            // We've checked above that every entry fits.
+            n += hubpack::serialize(&mut buf[n..], command).unwrap_lite();
+            buf[n..][..data.len()].copy_from_slice(data);
+            n += data.len();
+
+            let len = (n - start - BATCH_ENTRY_LEN_SIZE) as u16;
+            buf[start..n][..BATCH_ENTRY_LEN_SIZE]
+                .copy_from_slice(&len.to_le_bytes());
+        }
+        n
+    })?;
+
+    Ok((n, count))
+}
+
+/// Size of the length prefix of each entry in an [`SpToHost::Batch`] blob
+const BATCH_ENTRY_LEN_SIZE: usize = core::mem::size_of::<u16>();
+
+// Entry lengths must fit in their prefix.
+const_assert!(MAX_MESSAGE_SIZE <= u16::MAX as usize);
+
+/// Iterator over the responses packed into the data blob following an
+/// [`SpToHost::Batch`], yielding each response and its data blob in order.
+///
+/// If an entry is malformed, this yields an error and then stops.
+pub struct BatchEntries<'a> {
+    blob: &'a [u8],
+    remaining: u8,
+}
+
+impl<'a> BatchEntries<'a> {
+    /// Returns an iterator over the `count` entries in `blob`, as received
+    /// with `SpToHost::Batch { count }`.
+    pub fn new(blob: &'a [u8], count: u8) -> Self {
+        Self {
+            blob,
+            remaining: count,
+        }
+    }
+
+    fn next_entry(&mut self) -> Result<(SpToHost, &'a [u8]), HubpackError> {
+        let (len, rest) = hubpack::deserialize::<u16>(self.blob)?;
+        let len = usize::from(len);
+        if rest.len() < len {
+            return Err(HubpackError::Overrun);
+        }
+        let (entry, rest) = rest.split_at(len);
+        let (command, data) = hubpack::deserialize::<SpToHost>(entry)?;
+        self.blob = rest;
+        Ok((command, data))
+    }
+}
+
+impl<'a> Iterator for BatchEntries<'a> {
+    type Item = Result<(SpToHost, &'a [u8]), HubpackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let entry = self.next_entry();
+        self.remaining = if entry.is_ok() { self.remaining - 1 } else { 0 };
+        Some(entry)
+    }
+}
+
+/// Deserializes and validates just the [`Header`] at the start of `data`,
+/// returning it along with the remaining bytes.
+///
+/// This does not check the message's checksum; it lets a receiver discard
+/// frames with the wrong magic or version before paying for the rest of
+/// [`deserialize`].
+///
+/// # Errors
+///
+/// Returns [`DecodeFailureReason::Deserialize`] if `data` is too short to hold
+/// a header, [`DecodeFailureReason::MagicMismatch`] if the header does not
+/// start with [`MAGIC`], and [`DecodeFailureReason::VersionMismatch`] if the
+/// header's version is not one of [`SUPPORTED_VERSIONS`].
+pub fn deserialize_header(
+    data: &[u8],
+) -> Result<(Header, &[u8]), DecodeFailureReason> {
+    let (header, leftover) = hubpack::deserialize::<Header>(data)?;
+
+    if header.magic != MAGIC {
+        return Err(DecodeFailureReason::MagicMismatch);
+    }
+    if !SUPPORTED_VERSIONS.contains(&header.version) {
+        return Err(DecodeFailureReason::VersionMismatch);
+    }
+
+    Ok((header, leftover))
+}
+
 /// Deserializes a response packet containing
 ///
 /// ```text
@@ -766,37 +1442,254 @@ where
 ///
 /// # Errors
 ///
-/// Returns [`HubpackError::Custom`] for checksum mismatches.
+/// Returns [`DecodeFailureReason::MagicMismatch`] if the header does not start
+/// with [`MAGIC`], [`DecodeFailureReason::VersionMismatch`] if the header's
+/// version is not one of [`SUPPORTED_VERSIONS`], and
+/// [`DecodeFailureReason::Crc`] for checksum mismatches.
 pub fn deserialize<T: DeserializeOwned>(
     data: &[u8],
 ) -> Result<(Header, T, &[u8]), DecodeFailureReason> {
-    let (header, leftover) = hubpack::deserialize::<Header>(data)?;
+    let (header, leftover) = deserialize_header(data)?;
     let (command, leftover) = hubpack::deserialize::<T>(leftover)?;
 
-    // We expect at least 2 bytes remaining in `leftover` for the checksum; any
+    // We expect enough bytes remaining in `leftover` for the checksum; any
     // additional bytes are treated as the data blob we return.
-    if leftover.len() < CHECKSUM_SIZE {
+    let checksum_len = checksum_size(header.version);
+    if leftover.len() < checksum_len {
         return Err(DecodeFailureReason::DataLengthInvalid);
     }
 
     let (data_blob, checksum) =
-        leftover.split_at(leftover.len() - CHECKSUM_SIZE);
+        leftover.split_at(leftover.len() - checksum_len);
 
-    let checksum = u16::from_le_bytes(checksum.try_into().unwrap_lite());
-    let expected_checksum =
-        fletcher::calc_fletcher16(&data[..data.len() - CHECKSUM_SIZE]);
+    let mut expected_checksum = Checksum::new(header.version);
+    expected_checksum.update(&data[..data.len() - checksum_len]);
+    let mut expected = [0; MAX_CHECKSUM_SIZE];
+    let n = expected_checksum.finish(&mut expected);
 
-    if checksum != expected_checksum {
+    if checksum != &expected[..n] {
         return Err(DecodeFailureReason::Crc);
     }
 
     Ok((header, command, data_blob))
 }
 
+/// Deserializes a message like [`deserialize`], additionally rejecting any
+/// data blob following a command that does not carry one.
+///
+/// If the sender's enum layout differs from ours, a command may deserialize
+/// from fewer bytes than were actually sent; `deserialize` would then hand back
+/// the remainder as a data blob.  This returns
+/// [`DecodeFailureReason::TrailingData`] in that case instead.
+pub fn deserialize_strict<T: DeserializeOwned + DataBlob>(
+    data: &[u8],
+) -> Result<(Header, T, &[u8]), DecodeFailureReason> {
+    let (header, command, data_blob) = deserialize::<T>(data)?;
+    if !data_blob.is_empty() && !command.has_data_blob() {
+        return Err(DecodeFailureReason::TrailingData);
+    }
+    Ok((header, command, data_blob))
+}
+
+/// Host panic information, sent as the data blob following
+/// [`HostToSp::HostPanic`].
+///
+/// This mirrors the host's packed `ipcc_panic_data_t`: all fields are
+/// little-endian with no padding, which is exactly how hubpack encodes this
+/// struct. Only the first `stack_len` entries of `stack` and the first
+/// `data_len` bytes of `data` are meaningful; see [`HostPanicInfo::stack`] and
+/// [`HostPanicInfo::data`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub struct HostPanicInfo {
+    /// Layout version of the data; [`HostPanicInfo::VERSION`] for the layout
+    /// described here.
+    pub version: u8,
+    /// Why the host panicked; see the `CAUSE_*` constants.
+    pub cause: u16,
+    /// Error code associated with the cause (e.g., a trap's error code)
+    pub error: u32,
+    pub cpuid: u32,
+    pub thread: u64,
+    pub addr: u64,
+    pub pc: u64,
+    pub fp: u64,
+    pub rp: u64,
+    /// Panic message, NUL-padded; see [`HostPanicInfo::message`].
+    #[serde(with = "BigArray")]
+    pub message: [u8; HostPanicInfo::MESSAGE_LEN],
+    pub stack_len: u8,
+    pub stack: [HostPanicStackEntry; HostPanicInfo::STACK_LEN],
+    pub data_len: u8,
+    #[serde(with = "BigArray")]
+    pub data: [u8; HostPanicInfo::DATA_LEN],
+}
+
+impl HostPanicInfo {
+    /// The layout version described by this struct.
+    pub const VERSION: u8 = 1;
+
+    pub const MESSAGE_LEN: usize = 0x80;
+    pub const STACK_LEN: usize = 16;
+    pub const DATA_LEN: usize = 0x80;
+
+    /// Explicit call to `panic()`
+    pub const CAUSE_CALL: u16 = 0xca11;
+    /// Kernel trap
+    pub const CAUSE_TRAP: u16 = 0xa900;
+    /// Fatal user trap
+    pub const CAUSE_USERTRAP: u16 = 0x5e00;
+    /// Early boot failure
+    pub const CAUSE_EARLYBOOT: u16 = 0xeb00;
+    /// Early boot failure from the PROM
+    pub const CAUSE_EARLYBOOT_PROM: u16 = 0xeb97;
+    /// Trap during early boot
+    pub const CAUSE_EARLYBOOT_TRAP: u16 = 0xeba9;
+
+    /// Returns the panic message, up to its first NUL byte.
+    pub fn message(&self) -> &[u8] {
+        nul_terminated(&self.message)
+    }
+
+    /// Returns the valid stack entries, innermost frame first.
+    pub fn stack(&self) -> &[HostPanicStackEntry] {
+        &self.stack[..usize::from(self.stack_len).min(Self::STACK_LEN)]
+    }
+
+    /// Returns the valid bytes of additional panic data.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..usize::from(self.data_len).min(Self::DATA_LEN)]
+    }
+}
+
+/// One frame of a host panic stack trace.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, SerializedSize,
+)]
+pub struct HostPanicStackEntry {
+    /// Symbol name, NUL-padded; see [`HostPanicStackEntry::symbol`].
+    pub symbol: [u8; HostPanicStackEntry::SYMBOL_LEN],
+    pub addr: u64,
+    pub offset: u64,
+}
+
+impl HostPanicStackEntry {
+    pub const SYMBOL_LEN: usize = 0x20;
+
+    /// Returns the symbol name, up to its first NUL byte.
+    pub fn symbol(&self) -> &[u8] {
+        nul_terminated(&self.symbol)
+    }
+}
+
+fn nul_terminated(bytes: &[u8]) -> &[u8] {
+    let n = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..n]
+}
+
+/// Parses the data blob following a [`HostToSp::HostPanic`] message.
+///
+/// Any bytes following the [`HostPanicInfo`] are ignored, so that a host can
+/// append fields in a later layout version without breaking older parsers.
+///
+/// # Errors
+///
+/// Fails if `blob` is too short to hold a `HostPanicInfo`.
+pub fn parse_host_panic(blob: &[u8]) -> Result<HostPanicInfo, HubpackError> {
+    let (info, _trailing) = hubpack::deserialize(blob)?;
+    Ok(info)
+}
+
+/// The ordering a [`SequenceChecker`] requires of successive sequence numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceOrder {
+    /// Each sequence number must come after the last one accepted.
+    ///
+    /// "After" uses serial number arithmetic (as in RFC 1982): `b` comes after
+    /// `a` if `b.wrapping_sub(a)` is nonzero and less than 2^63. This allows
+    /// the sequence to wrap around from `u64::MAX` to 0, and allows gaps
+    /// (e.g., from lost messages), but rejects anything replayed or reordered.
+    StrictlyIncreasing,
+    /// Each sequence number must differ from the last one accepted.
+    ///
+    /// This only catches a message being delivered twice in a row; a peer may
+    /// otherwise pick its sequence numbers however it likes.
+    NonRepeating,
+}
+
+/// Tracks the sequence number of the last message accepted from a peer, and
+/// rejects messages whose sequence numbers are out of order according to a
+/// [`SequenceOrder`].
+///
+/// [`deserialize`] itself doesn't look at sequence numbers, since whether a
+/// given sequence number is acceptable depends on what came before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceChecker {
+    order: SequenceOrder,
+    last: Option<u64>,
+}
+
+impl SequenceChecker {
+    /// Returns a checker that will accept any sequence number first.
+    pub const fn new(order: SequenceOrder) -> Self {
+        Self { order, last: None }
+    }
+
+    /// Returns the last sequence number accepted, if any.
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Forgets the last sequence number accepted, e.g. after resynchronizing
+    /// with a peer that may have restarted.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Checks `sequence` against the last sequence number accepted, and
+    /// records it as the new last sequence number if it is acceptable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeFailureReason::SequenceInvalid`] if `sequence` is not
+    /// acceptable, in which case our state is not changed.
+    pub fn check(&mut self, sequence: u64) -> Result<(), DecodeFailureReason> {
+        if let Some(last) = self.last {
+            let ok = match self.order {
+                SequenceOrder::StrictlyIncreasing => {
+                    let delta = sequence.wrapping_sub(last);
+                    delta != 0 && delta < 1 << 63
+                }
+                SequenceOrder::NonRepeating => sequence != last,
+            };
+            if !ok {
+                return Err(DecodeFailureReason::SequenceInvalid);
+            }
+        }
+        self.last = Some(sequence);
+        Ok(())
+    }
+
+    /// Deserializes a message like [`deserialize`], then checks its header's
+    /// sequence number like [`SequenceChecker::check`].
+    pub fn deserialize<'a, T: DeserializeOwned>(
+        &mut self,
+        data: &'a [u8],
+    ) -> Result<(Header, T, &'a [u8]), DecodeFailureReason> {
+        let (header, command, data_blob) = deserialize::<T>(data)?;
+        self.check(header.sequence)?;
+        Ok((header, command, data_blob))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Most tests here use `Header::new`, which selects version 1 (Fletcher-16).
+    const CHECKSUM_SIZE: usize = checksum_size(version::V1);
+
     // Test that confirms our hubpack encoding of `HostToSp` (based on the
     // ordering of its variants) matches the expected command values described
     // in RFD 316.
@@ -833,6 +1726,7 @@ mod tests {
             ),
             (0x0f, HostToSp::GetInventoryData { index: 0 }),
             (0x10, HostToSp::KeySet { key: 0 }),
+            (0x11, HostToSp::GetInventoryDataRange { start: 0, count: 0 }),
         ] {
             let n = hubpack::serialize(&mut buf[..], &variant).unwrap();
             assert!(n >= 1);
@@ -879,6 +1773,8 @@ mod tests {
                 },
             ),
             (0x0c, SpToHost::KeySetResult(KeySetResult::Ok)),
+            (0x0d, SpToHost::InventoryDataRange { start: 0, count: 0 }),
+            (0x0e, SpToHost::Batch { count: 0 }),
         ] {
             let n = hubpack::serialize(&mut buf[..], &variant).unwrap();
             assert!(n >= 1);
@@ -902,6 +1798,26 @@ mod tests {
         }
     }
 
+    // `Key` is sent as a raw `u8` and decoded with `from_u8`, so its variant
+    // order is part of the protocol.
+    #[test]
+    fn key_values() {
+        use num_traits::FromPrimitive;
+
+        for (expected, key) in [
+            (0, Key::Ping),
+            (1, Key::InstallinatorImageId),
+            (2, Key::InventorySize),
+            (3, Key::EtcSystem),
+            (4, Key::DtraceConf),
+            (5, Key::SpGitVersion),
+        ] {
+            assert_eq!(key as u8, expected);
+            assert_eq!(Key::from_u8(expected), Some(key));
+        }
+        assert_eq!(Key::from_u8(6), None);
+    }
+
     #[test]
     fn key_set_result_values() {
         let mut buf = [0; KeySetResult::MAX_SIZE];
@@ -1176,10 +2092,13 @@ mod tests {
         assert_eq!(n, 7);
         assert_eq!(&buf[..n], [11, 1, 100, 200, 255, 0xbc, 0xaa]);
 
-        let d = InventoryData::Ksz8463 { cider: 0x1234 };
+        let d = InventoryData::Ksz8463 {
+            cider: 0x1234,
+            cfgr: 0x5678,
+        };
         let n = hubpack::serialize(&mut buf, &d).unwrap();
-        assert_eq!(n, 3);
-        assert_eq!(&buf[..n], [12, 0x34, 0x12]);
+        assert_eq!(n, 5);
+        assert_eq!(&buf[..n], [12, 0x34, 0x12, 0x78, 0x56]);
 
         let d = InventoryData::Max5970 {
             voltage_sensors: [1, 2],
@@ -1191,15 +2110,133 @@ mod tests {
             &buf[..n],
             [13, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]
         );
+
+        let d = InventoryData::Ltc4306 {
+            address: 0x44,
+            registers: [0x84, 0x00, 0x60, 0x80],
+        };
+        let n = hubpack::serialize(&mut buf, &d).unwrap();
+        assert_eq!(n, 6);
+        assert_eq!(&buf[..n], [15, 0x44, 0x84, 0x00, 0x60, 0x80]);
     }
 
     #[test]
-    fn roundtrip() {
-        let header = Header {
-            magic: MAGIC,
-            version: 123,
-            sequence: 456,
-        };
+    fn inventory_kinds() {
+        // The kind's discriminant must match the variant index on the wire
+        let mut buf = [0; InventoryData::MAX_SIZE];
+        for d in [
+            InventoryData::At24csw08xSerial([0; 16]),
+            InventoryData::Stm32H7 {
+                uid: [1, 2, 3],
+                dbgmcu_rev_id: 4,
+                dbgmcu_dev_id: 5,
+            },
+            InventoryData::Ksz8463 {
+                cider: 0x8452,
+                cfgr: 0x0300,
+            },
+            InventoryData::Max31790 {
+                speed_sensors: [0; 6],
+                global_configuration: 0x20,
+                pwm_frequency: 0x44,
+                fan_configuration: [0x08; 6],
+            },
+            InventoryData::Ltc4306 {
+                address: 0x44,
+                registers: [0; 4],
+            },
+        ] {
+            hubpack::serialize(&mut buf, &d).unwrap();
+            assert_eq!(InventoryDataKind::from(&d) as u8, buf[0]);
+        }
+        assert_eq!(InventoryDataKind::Ltc4306 as u8, 15);
+    }
+
+    #[test]
+    fn inventory_data_range() {
+        let entries = [
+            (
+                InventoryDataRangeEntry {
+                    result: InventoryDataResult::Ok,
+                    name: [b'U'; 32],
+                },
+                Some(InventoryData::Ksz8463 {
+                    cider: 0x8452,
+                    cfgr: 0x0300,
+                }),
+            ),
+            (
+                InventoryDataRangeEntry {
+                    result: InventoryDataResult::DeviceAbsent,
+                    name: [b'J'; 32],
+                },
+                None,
+            ),
+        ];
+
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let header = Header::new(1);
+        let cmd = SpToHost::InventoryDataRange { start: 7, count: 2 };
+        let n = serialize(&mut buf, &header, &cmd, |out| {
+            let mut n = 0;
+            for (entry, data) in &entries {
+                n += hubpack::serialize(&mut out[n..], entry).unwrap();
+                if let Some(data) = data {
+                    n += hubpack::serialize(&mut out[n..], data).unwrap();
+                }
+            }
+            n
+        })
+        .unwrap();
+
+        let (_, cmd, mut blob) =
+            deserialize_strict::<SpToHost>(&buf[..n]).unwrap();
+        assert_eq!(cmd, SpToHost::InventoryDataRange { start: 7, count: 2 });
+        for (entry, data) in &entries {
+            let (e, rest) =
+                hubpack::deserialize::<InventoryDataRangeEntry>(blob).unwrap();
+            assert_eq!(&e, entry);
+            blob = rest;
+            if e.result == InventoryDataResult::Ok {
+                let (d, rest) =
+                    hubpack::deserialize::<InventoryData>(blob).unwrap();
+                assert_eq!(Some(d), *data);
+                blob = rest;
+            }
+        }
+        assert!(blob.is_empty());
+    }
+
+    #[test]
+    fn fill_data_len() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let header = Header::new(1);
+        for cmd in [
+            SpToHost::Ack,
+            SpToHost::Phase2Data,
+            SpToHost::Identity(Identity::default()),
+            SpToHost::InventoryData {
+                result: InventoryDataResult::Ok,
+                name: [0; 32],
+            },
+            SpToHost::InventoryDataRange { start: 0, count: 0 },
+        ] {
+            let len = sp_to_host_fill_data_len(version::V1, &cmd);
+            assert!(len >= MIN_SP_TO_HOST_FILL_DATA_LEN);
+
+            let n = serialize(&mut buf, &header, &cmd, |out| {
+                assert_eq!(out.len(), len);
+                out.fill(0xaa);
+                len
+            })
+            .unwrap();
+            assert_eq!(n, MAX_MESSAGE_SIZE);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let header = Header::new(456);
         let host_to_sp = HostToSp::HostPanic;
         let data_blob = &[1, 2, 3, 4, 5, 6, 7, 8, 9];
 
@@ -1220,11 +2257,7 @@ mod tests {
 
     #[test]
     fn roundtrip_large_data_blob() {
-        let header = Header {
-            magic: MAGIC,
-            version: 123,
-            sequence: 456,
-        };
+        let header = Header::new(456);
         let host_to_sp = HostToSp::HostPanic;
         let data_blob = (0_u32..)
             .into_iter()
@@ -1362,6 +2395,79 @@ mod tests {
         assert_eq!(expected_without_cksum, &buf[..n - CHECKSUM_SIZE]);
     }
 
+    // The checksum is accumulated incrementally during serialization; make
+    // sure it matches a single pass over the finished message.
+    #[test]
+    fn incremental_checksum_matches_full() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let header = Header::new(0x1122_3344_5566_7788);
+
+        let check = |buf: &[u8]| {
+            let (body, cksum) = buf.split_at(buf.len() - CHECKSUM_SIZE);
+            assert_eq!(fletcher::calc_fletcher16(body).to_le_bytes(), cksum);
+        };
+
+        // No data blob.
+        let n = serialize(&mut buf, &header, &SpToHost::Ack, |_| 0).unwrap();
+        check(&buf[..n]);
+
+        // Small and maximally-sized data blobs.
+        for len in [1, 17, MIN_SP_TO_HOST_FILL_DATA_LEN] {
+            let n =
+                serialize(&mut buf, &header, &SpToHost::Phase2Data, |out| {
+                    for (i, b) in out[..len].iter_mut().enumerate() {
+                        *b = i as u8;
+                    }
+                    len
+                })
+                .unwrap();
+            check(&buf[..n]);
+        }
+
+        // `fill_data` fails after scribbling on the buffer, so we reserialize
+        // with the error instead of the original command.
+        let n =
+            try_serialize(&mut buf, &header, &SpToHost::Phase2Data, |out| {
+                out[..64].fill(0xaa);
+                Err(SpToHost::DecodeFailure(DecodeFailureReason::Crc))
+            })
+            .unwrap();
+        check(&buf[..n]);
+        let (_, cmd, data) = deserialize::<SpToHost>(&buf[..n]).unwrap();
+        assert_eq!(cmd, SpToHost::DecodeFailure(DecodeFailureReason::Crc));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn fill_data_overrun() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let header = Header::new(1);
+
+        assert_eq!(
+            try_serialize_checked(&mut buf, &header, &SpToHost::Ack, |out| {
+                Ok(out.len() + 1)
+            }),
+            Err(HubpackError::Custom)
+        );
+
+        // Exactly filling the slice is fine
+        let n =
+            try_serialize_checked(&mut buf, &header, &SpToHost::Ack, |out| {
+                Ok(out.len())
+            })
+            .unwrap();
+        assert_eq!(n, MAX_MESSAGE_SIZE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fill_data_overrun_panics() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let _ = serialize(&mut buf, &Header::new(1), &SpToHost::Ack, |out| {
+            out.len() + 1
+        });
+    }
+
     #[test]
     fn bad_host_sp_command() {
         #[rustfmt::skip]
@@ -1369,7 +2475,7 @@ mod tests {
             // magic
             0xcc, 0x19, 0xde, 0x01,
             // version
-            0x67, 0x45, 0x23, 0x01,
+            0x01, 0x00, 0x00, 0x00,
             // sequence
             0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11,
             // command that does not map to a `HostToSp` variant
@@ -1382,6 +2488,240 @@ mod tests {
         );
     }
 
+    #[test]
+    fn version_check() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let mut header = Header::new(1);
+
+        let n =
+            serialize(&mut buf, &header, &HostToSp::GetStatus, |_| 0).unwrap();
+        let (h, cmd, data) = deserialize::<HostToSp>(&buf[..n]).unwrap();
+        assert_eq!(h, header);
+        assert_eq!(cmd, HostToSp::GetStatus);
+        assert!(data.is_empty());
+
+        header.version = version::V2 + 1;
+        assert!(!SUPPORTED_VERSIONS.contains(&header.version));
+        let n =
+            serialize(&mut buf, &header, &HostToSp::GetStatus, |_| 0).unwrap();
+        assert_eq!(
+            deserialize::<HostToSp>(&buf[..n]),
+            Err(DecodeFailureReason::VersionMismatch)
+        );
+
+        header.version = version::V1;
+        header.magic = !MAGIC;
+        let n =
+            serialize(&mut buf, &header, &HostToSp::GetStatus, |_| 0).unwrap();
+        assert_eq!(
+            deserialize::<HostToSp>(&buf[..n]),
+            Err(DecodeFailureReason::MagicMismatch)
+        );
+    }
+
+    #[test]
+    fn header_prefilter() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let mut header = Header::new(1);
+
+        let n =
+            serialize(&mut buf, &header, &HostToSp::GetStatus, |_| 0).unwrap();
+        let (h, rest) = deserialize_header(&buf[..n]).unwrap();
+        assert_eq!(h, header);
+        assert_eq!(rest, &buf[Header::MAX_SIZE..n]);
+
+        // Anything short of a full header fails without looking further.
+        assert_eq!(
+            deserialize_header(&buf[..Header::MAX_SIZE - 1]),
+            Err(DecodeFailureReason::Deserialize)
+        );
+        assert_eq!(
+            deserialize_header(&[]),
+            Err(DecodeFailureReason::Deserialize)
+        );
+
+        // A header alone is enough to reject bad magic or version, even if
+        // what follows is garbage.
+        header.magic = !MAGIC;
+        hubpack::serialize(&mut buf, &header).unwrap();
+        assert_eq!(
+            deserialize_header(&buf[..Header::MAX_SIZE]),
+            Err(DecodeFailureReason::MagicMismatch)
+        );
+
+        header.magic = MAGIC;
+        header.version = version::V2 + 1;
+        hubpack::serialize(&mut buf, &header).unwrap();
+        assert_eq!(
+            deserialize_header(&buf[..Header::MAX_SIZE]),
+            Err(DecodeFailureReason::VersionMismatch)
+        );
+    }
+
+    #[test]
+    fn ack_fast_path() {
+        for sequence in [0, 1, 0x1122_3344_5566_7788, u64::MAX] {
+            let mut expected = [0; MAX_MESSAGE_SIZE];
+            let n = serialize(
+                &mut expected,
+                &Header::new(sequence),
+                &SpToHost::Ack,
+                |_| 0,
+            )
+            .unwrap();
+
+            let mut buf = [0xff; MAX_MESSAGE_SIZE];
+            assert_eq!(serialize_ack(&mut buf, sequence), n);
+            assert_eq!(buf[..n], expected[..n]);
+        }
+    }
+
+    #[test]
+    fn batch_round_trip() {
+        let header = Header::new(9);
+        let status = SpToHost::Status {
+            status: Status::SP_TASK_RESTARTED,
+            startup: HostStartupOptions::empty(),
+        };
+        let alert = SpToHost::Alert { action: 3 };
+        let items: [(SpToHost, &[u8]); 2] =
+            [(status, &[]), (alert, &[0xa1, 0xe2, 0x7])];
+
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        for k in 1..=items.len() {
+            let (n, packed) =
+                serialize_batch(&mut buf, &header, &items[..k]).unwrap();
+            assert_eq!(packed, k);
+
+            let (h, cmd, blob) =
+                deserialize_strict::<SpToHost>(&buf[..n]).unwrap();
+            assert_eq!(h, header);
+            assert_eq!(cmd, SpToHost::Batch { count: k as u8 });
+
+            let entries = BatchEntries::new(blob, k as u8)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(entries, items[..k]);
+        }
+    }
+
+    #[test]
+    fn batch_overflow() {
+        let header = Header::new(9);
+        let data = [0x5a; 1000];
+        let items = [(SpToHost::Phase2Data, &data[..]); 5];
+
+        // Only four of these fit in one message; the fifth must be sent on
+        // its own.
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let (n, packed) = serialize_batch(&mut buf, &header, &items).unwrap();
+        assert_eq!(packed, 4);
+        let (_, cmd, blob) = deserialize_strict::<SpToHost>(&buf[..n]).unwrap();
+        assert_eq!(cmd, SpToHost::Batch { count: 4 });
+        let entries = BatchEntries::new(blob, 4)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries, items[..4]);
+
+        // A truncated blob is an error, after which the iterator stops.
+        let mut entries = BatchEntries::new(&blob[..1500], 4);
+        assert_eq!(entries.next(), Some(Ok(items[0])));
+        assert_eq!(entries.next(), Some(Err(HubpackError::Overrun)));
+        assert_eq!(entries.next(), None);
+
+        let (_, packed) =
+            serialize_batch(&mut buf, &header, &items[packed..]).unwrap();
+        assert_eq!(packed, 1);
+    }
+
+    #[test]
+    fn phase2_offsets() {
+        let image = [1, 2, 3, 4, 5];
+        assert_eq!(phase2_data_at(&image, 0), image);
+        assert_eq!(phase2_data_at(&image, 3), [4, 5]);
+
+        // At and past the end of the image, the response is empty but still
+        // a successful `Phase2Data`.
+        for offset in [5, 6, u64::MAX] {
+            let data = phase2_data_at(&image, offset);
+            assert!(data.is_empty());
+
+            let mut buf = [0; MAX_MESSAGE_SIZE];
+            let n = serialize(
+                &mut buf,
+                &Header::new(1),
+                &SpToHost::Phase2Data,
+                |out| {
+                    out[..data.len()].copy_from_slice(data);
+                    data.len()
+                },
+            )
+            .unwrap();
+            let (_, cmd, blob) =
+                deserialize_strict::<SpToHost>(&buf[..n]).unwrap();
+            assert_eq!(cmd, SpToHost::Phase2Data);
+            assert!(blob.is_empty());
+        }
+    }
+
+    #[test]
+    fn crc32_trailer() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let mut header = Header::new(1);
+        header.version = version::V2;
+        let data_blob = &[1, 2, 3, 4, 5];
+
+        let n = serialize(&mut buf, &header, &HostToSp::HostPanic, |out| {
+            out[..data_blob.len()].copy_from_slice(data_blob);
+            data_blob.len()
+        })
+        .unwrap();
+        assert_eq!(checksum_size(version::V2), 4);
+        let (body, cksum) = buf[..n].split_at(n - 4);
+        assert_eq!(CRC32.checksum(body).to_le_bytes(), cksum);
+
+        let (h, cmd, data) = deserialize::<HostToSp>(&buf[..n]).unwrap();
+        assert_eq!(h, header);
+        assert_eq!(cmd, HostToSp::HostPanic);
+        assert_eq!(data, data_blob);
+
+        // The full message space is available, less the larger trailer
+        let len = sp_to_host_fill_data_len(version::V2, &SpToHost::Phase2Data);
+        let n = serialize(&mut buf, &header, &SpToHost::Phase2Data, |out| {
+            assert_eq!(out.len(), len);
+            len
+        })
+        .unwrap();
+        assert_eq!(n, MAX_MESSAGE_SIZE);
+        assert!(deserialize::<SpToHost>(&buf[..n]).is_ok());
+
+        // Corrupting any byte (including the checksum) is detected
+        for i in [0, 17, n - 1] {
+            buf[i] ^= 0x10;
+            assert!(deserialize::<SpToHost>(&buf[..n]).is_err());
+            buf[i] ^= 0x10;
+        }
+
+        // A V1 trailer is too short
+        #[rustfmt::skip]
+        let data: &[u8] = &[
+            // magic
+            0xcc, 0x19, 0xde, 0x01,
+            // version
+            0x02, 0x00, 0x00, 0x00,
+            // sequence
+            0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11,
+            // HostToSp::RequestReboot
+            0x01,
+            // Fletcher-16 checksum
+            0xff, 0xff,
+        ];
+        assert_eq!(
+            deserialize::<HostToSp>(data),
+            Err(DecodeFailureReason::DataLengthInvalid)
+        );
+    }
+
     #[test]
     fn bad_crc() {
         #[rustfmt::skip]
@@ -1389,7 +2729,7 @@ mod tests {
             // magic
             0xcc, 0x19, 0xde, 0x01,
             // version
-            0x67, 0x45, 0x23, 0x01,
+            0x01, 0x00, 0x00, 0x00,
             // sequence
             0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11,
             // HostToSp::RequestReboot
@@ -1411,7 +2751,7 @@ mod tests {
             // magic
             0xcc, 0x19, 0xde, 0x01,
             // version
-            0x67, 0x45, 0x23, 0x01,
+            0x01, 0x00, 0x00, 0x00,
             // sequence
             0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11,
             // HostToSp::RequestReboot
@@ -1424,4 +2764,507 @@ mod tests {
             Err(DecodeFailureReason::DataLengthInvalid)
         );
     }
+
+    #[test]
+    fn trailing_data() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let header = Header::new(1);
+        let blob = |out: &mut [u8]| {
+            out[..3].copy_from_slice(&[1, 2, 3]);
+            3
+        };
+
+        // A blob after a command that doesn't carry one is rejected...
+        let n =
+            serialize(&mut buf, &header, &HostToSp::GetStatus, blob).unwrap();
+        assert!(deserialize::<HostToSp>(&buf[..n]).is_ok());
+        assert_eq!(
+            deserialize_strict::<HostToSp>(&buf[..n]),
+            Err(DecodeFailureReason::TrailingData)
+        );
+
+        // ...but allowed for commands that do
+        let n =
+            serialize(&mut buf, &header, &HostToSp::HostPanic, blob).unwrap();
+        let (_, cmd, data) = deserialize_strict::<HostToSp>(&buf[..n]).unwrap();
+        assert_eq!(cmd, HostToSp::HostPanic);
+        assert_eq!(data, [1, 2, 3]);
+
+        // An `InventoryData` response only carries a blob on success
+        let cmd = SpToHost::InventoryData {
+            result: InventoryDataResult::InvalidIndex,
+            name: [0; 32],
+        };
+        let n = serialize(&mut buf, &header, &cmd, blob).unwrap();
+        assert_eq!(
+            deserialize_strict::<SpToHost>(&buf[..n]),
+            Err(DecodeFailureReason::TrailingData)
+        );
+    }
+
+    // Every command that is followed by a data blob must say so, or
+    // `deserialize_strict` will drop it as `TrailingData`.
+    #[test]
+    fn data_blob_commands() {
+        for (blob, cmd) in [
+            (false, HostToSp::RequestReboot),
+            (false, HostToSp::RequestPowerOff),
+            (false, HostToSp::GetBootStorageUnit),
+            (false, HostToSp::GetIdentity),
+            (false, HostToSp::GetMacAddresses),
+            (true, HostToSp::HostBootFailure { reason: 0 }),
+            (true, HostToSp::HostPanic),
+            (false, HostToSp::GetStatus),
+            (false, HostToSp::AckSpStart),
+            (false, HostToSp::GetAlert),
+            (true, HostToSp::RotRequest),
+            (true, HostToSp::RotAddHostMeasurements),
+            (
+                false,
+                HostToSp::GetPhase2Data {
+                    hash: [0; 32],
+                    offset: 0,
+                },
+            ),
+            (
+                false,
+                HostToSp::KeyLookup {
+                    key: 0,
+                    max_response_len: 0,
+                },
+            ),
+            (false, HostToSp::GetInventoryData { index: 0 }),
+            (true, HostToSp::KeySet { key: 0 }),
+            (
+                false,
+                HostToSp::GetInventoryDataRange { start: 0, count: 0 },
+            ),
+        ] {
+            assert_eq!(cmd.has_data_blob(), blob, "{cmd:?}");
+        }
+
+        for (blob, cmd) in [
+            (false, SpToHost::Ack),
+            (false, SpToHost::DecodeFailure(DecodeFailureReason::Cobs)),
+            (false, SpToHost::BootStorageUnit(Bsu::A)),
+            (false, SpToHost::Identity(Identity::default())),
+            (
+                false,
+                SpToHost::MacAddresses {
+                    base: [0; 6],
+                    count: 0,
+                    stride: 0,
+                },
+            ),
+            (
+                false,
+                SpToHost::Status {
+                    status: Status::empty(),
+                    startup: HostStartupOptions::empty(),
+                },
+            ),
+            (true, SpToHost::Alert { action: 0 }),
+            (true, SpToHost::RotResponse),
+            (true, SpToHost::Phase2Data),
+            (true, SpToHost::KeyLookupResult(KeyLookupResult::Ok)),
+            (
+                false,
+                SpToHost::KeyLookupResult(KeyLookupResult::InvalidKey),
+            ),
+            (
+                true,
+                SpToHost::InventoryData {
+                    result: InventoryDataResult::Ok,
+                    name: [0u8; 32],
+                },
+            ),
+            (
+                false,
+                SpToHost::InventoryData {
+                    result: InventoryDataResult::DeviceAbsent,
+                    name: [0u8; 32],
+                },
+            ),
+            (false, SpToHost::KeySetResult(KeySetResult::Ok)),
+            (true, SpToHost::InventoryDataRange { start: 0, count: 0 }),
+            (true, SpToHost::Batch { count: 0 }),
+        ] {
+            assert_eq!(cmd.has_data_blob(), blob, "{cmd:?}");
+        }
+    }
+
+    #[test]
+    fn sequence_in_order() {
+        for order in [
+            SequenceOrder::StrictlyIncreasing,
+            SequenceOrder::NonRepeating,
+        ] {
+            let mut checker = SequenceChecker::new(order);
+            for sequence in [5, 6, 7, 100, 1 << 40] {
+                assert_eq!(checker.check(sequence), Ok(()), "{order:?}");
+            }
+            assert_eq!(checker.last(), Some(1 << 40));
+        }
+    }
+
+    #[test]
+    fn sequence_replay() {
+        let mut strict =
+            SequenceChecker::new(SequenceOrder::StrictlyIncreasing);
+        let mut nonrepeating =
+            SequenceChecker::new(SequenceOrder::NonRepeating);
+        for checker in [&mut strict, &mut nonrepeating] {
+            checker.check(10).unwrap();
+            assert_eq!(
+                checker.check(10),
+                Err(DecodeFailureReason::SequenceInvalid)
+            );
+            // A rejected sequence number doesn't change our state.
+            assert_eq!(checker.last(), Some(10));
+        }
+
+        // Going backwards is only a problem if we want strictly increasing.
+        assert_eq!(strict.check(9), Err(DecodeFailureReason::SequenceInvalid));
+        assert_eq!(nonrepeating.check(9), Ok(()));
+        assert_eq!(nonrepeating.check(10), Ok(()));
+
+        // A reset allows anything.
+        strict.reset();
+        assert_eq!(strict.check(9), Ok(()));
+    }
+
+    #[test]
+    fn sequence_wraparound() {
+        let mut checker =
+            SequenceChecker::new(SequenceOrder::StrictlyIncreasing);
+        checker.check(u64::MAX - 1).unwrap();
+        assert_eq!(checker.check(u64::MAX), Ok(()));
+        assert_eq!(checker.check(0), Ok(()));
+        assert_eq!(checker.check(1), Ok(()));
+        assert_eq!(
+            checker.check(u64::MAX),
+            Err(DecodeFailureReason::SequenceInvalid)
+        );
+
+        // Anything up to half the sequence space ahead is "after"...
+        let mut checker =
+            SequenceChecker::new(SequenceOrder::StrictlyIncreasing);
+        checker.check(u64::MAX).unwrap();
+        assert_eq!(checker.check((1 << 63) - 2), Ok(()));
+        // ...but no further.
+        let mut checker =
+            SequenceChecker::new(SequenceOrder::StrictlyIncreasing);
+        checker.check(u64::MAX).unwrap();
+        assert_eq!(
+            checker.check((1 << 63) - 1),
+            Err(DecodeFailureReason::SequenceInvalid)
+        );
+    }
+
+    #[test]
+    fn sequence_checker_deserialize() {
+        let mut buf = [0; MAX_MESSAGE_SIZE];
+        let mut checker =
+            SequenceChecker::new(SequenceOrder::StrictlyIncreasing);
+        let header = Header {
+            magic: MAGIC,
+            version: version::V1,
+            sequence: 3,
+        };
+        let n =
+            serialize(&mut buf, &header, &HostToSp::GetStatus, |_| 0).unwrap();
+
+        let (h, cmd, _) = checker.deserialize::<HostToSp>(&buf[..n]).unwrap();
+        assert_eq!(h, header);
+        assert_eq!(cmd, HostToSp::GetStatus);
+        assert_eq!(
+            checker.deserialize::<HostToSp>(&buf[..n]),
+            Err(DecodeFailureReason::SequenceInvalid)
+        );
+    }
+
+    #[test]
+    fn host_panic() {
+        // Build a blob field-by-field, as the host lays it out, rather than
+        // with hubpack, so that we're checking the layout independently.
+        let mut blob = vec![HostPanicInfo::VERSION];
+        blob.extend_from_slice(&HostPanicInfo::CAUSE_TRAP.to_le_bytes());
+        blob.extend_from_slice(&0x0e_u32.to_le_bytes()); // error
+        blob.extend_from_slice(&3_u32.to_le_bytes()); // cpuid
+
+        // thread, addr, pc, fp, rp
+        for x in [
+            0xffff_fcc2_4a5c_0c20_u64,
+            0x18,
+            0xffff_ffff_fb85_a5e2,
+            0xffff_fcc2_4a5c_09f0,
+            0xffff_ffff_fb82_e0b1,
+        ] {
+            blob.extend_from_slice(&x.to_le_bytes());
+        }
+
+        let mut message = [0; HostPanicInfo::MESSAGE_LEN];
+        let text = b"BAD TRAP: type=e (#pf Page fault)";
+        message[..text.len()].copy_from_slice(text);
+        blob.extend_from_slice(&message);
+
+        let frames: [(&[u8], u64, u64); 2] = [
+            (b"mutex_enter", 0xffff_ffff_fb85_a5a0, 0x42),
+            (b"zio_done", 0xffff_ffff_f7a1_3d00, 0x1f0),
+        ];
+        blob.push(frames.len() as u8);
+        for i in 0..HostPanicInfo::STACK_LEN {
+            let (sym, addr, offset) =
+                frames.get(i).copied().unwrap_or_default();
+            let mut symbol = [0; HostPanicStackEntry::SYMBOL_LEN];
+            symbol[..sym.len()].copy_from_slice(sym);
+            blob.extend_from_slice(&symbol);
+            blob.extend_from_slice(&addr.to_le_bytes());
+            blob.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        blob.push(4);
+        let mut data = [0; HostPanicInfo::DATA_LEN];
+        data[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        blob.extend_from_slice(&data);
+
+        assert_eq!(blob.len(), HostPanicInfo::MAX_SIZE);
+
+        // Trailing bytes (e.g. from a newer host) are ignored.
+        blob.extend_from_slice(&[0xaa; 7]);
+        let info = parse_host_panic(&blob).unwrap();
+
+        assert_eq!(info.version, HostPanicInfo::VERSION);
+        assert_eq!(info.cause, HostPanicInfo::CAUSE_TRAP);
+        assert_eq!(info.error, 0x0e);
+        assert_eq!(info.cpuid, 3);
+        assert_eq!(info.addr, 0x18);
+        assert_eq!(info.pc, 0xffff_ffff_fb85_a5e2);
+        assert_eq!(info.message(), text);
+        assert_eq!(info.stack().len(), 2);
+        assert_eq!(info.stack()[0].symbol(), b"mutex_enter");
+        assert_eq!(info.stack()[1].addr, 0xffff_ffff_f7a1_3d00);
+        assert_eq!(info.stack()[1].offset, 0x1f0);
+        assert_eq!(info.data(), [0xde, 0xad, 0xbe, 0xef]);
+
+        // A short blob is an error.
+        let short = &blob[..HostPanicInfo::MAX_SIZE - 1];
+        assert!(parse_host_panic(short).is_err());
+    }
+
+    #[test]
+    fn raw_flags_roundtrip() {
+        let status = Status::SP_TASK_RESTARTED | Status::ALERTS_AVAILABLE;
+        assert_eq!(Status::from_bytes(status.as_bytes()), Some(status));
+        assert_eq!(Status::from_bytes(&status.into_bytes()), Some(status));
+        assert_eq!(Status::from_bytes(&[0; 7]), None);
+
+        let startup = HostStartupOptions::all();
+        assert_eq!(
+            HostStartupOptions::from_bytes(&startup.into_bytes()),
+            Some(startup)
+        );
+        assert!(!startup.contains_unknown_bits());
+
+        // Bits from a newer peer survive the round trip and are detectable.
+        let future = Status::from_bits_retain(1 << 40 | 1);
+        let decoded = Status::from_bytes(&future.into_bytes()).unwrap();
+        assert_eq!(decoded.bits(), future.bits());
+        assert!(decoded.contains_unknown_bits());
+    }
+
+    #[test]
+    fn raw_flags_try_from_bits() {
+        assert_eq!(
+            Status::try_from_bits(0b11),
+            Ok(Status::SP_TASK_RESTARTED | Status::ALERTS_AVAILABLE)
+        );
+        assert_eq!(Status::try_from_bits(1 << 2 | 1), Err(UnknownBits(1 << 2)));
+        assert_eq!(
+            HostStartupOptions::try_from_bits(1 << 63),
+            Err(UnknownBits(1 << 63))
+        );
+        assert_eq!(
+            HostStartupOptions::try_from_bits(HostStartupOptions::all().bits()),
+            Ok(HostStartupOptions::all())
+        );
+    }
+
+    #[test]
+    fn dimm_spd_key_byte() {
+        // Start of a DDR4 RDIMM SPD: 384 bytes used / 512 total, revision 1.1,
+        // DDR4 SDRAM, RDIMM.
+        let mut ddr4 = [0u8; InventoryData::DDR4_SPD_SIZE];
+        ddr4[..4].copy_from_slice(&[0x23, 0x11, 0x0c, 0x01]);
+        assert_eq!(SpdDeviceType::from_spd(&ddr4), Some(SpdDeviceType::Ddr4));
+        assert_eq!(
+            InventoryData::dimm_spd(&ddr4, 7),
+            Ok(InventoryData::DimmSpd {
+                id: ddr4,
+                temp_sensor: 7
+            })
+        );
+
+        // A DDR4 key byte with the wrong amount of data is still refused.
+        assert_eq!(
+            InventoryData::dimm_spd(&ddr4[..256], 7),
+            Err(InventoryDataResult::DeviceFailed)
+        );
+
+        // Start of a DDR5 RDIMM SPD: 1024 bytes total, revision 1.0, DDR5
+        // SDRAM, RDIMM.
+        let mut ddr5 = [0u8; 1024];
+        ddr5[..4].copy_from_slice(&[0x30, 0x10, 0x12, 0x01]);
+        assert_eq!(SpdDeviceType::from_spd(&ddr5), Some(SpdDeviceType::Ddr5));
+        assert_eq!(
+            InventoryData::dimm_spd(&ddr5, 7),
+            Err(InventoryDataResult::DeviceFailed)
+        );
+
+        // Same size as a DDR4 SPD, but not DDR4
+        let mut ddr5_short = ddr4;
+        ddr5_short[SpdDeviceType::KEY_BYTE] = 0x12;
+        assert_eq!(
+            InventoryData::dimm_spd(&ddr5_short, 7),
+            Err(InventoryDataResult::DeviceFailed)
+        );
+
+        assert_eq!(
+            SpdDeviceType::from_spd(&[0x23, 0x11, 0x0b]),
+            Some(SpdDeviceType::Other(0x0b))
+        );
+        assert_eq!(SpdDeviceType::from_spd(&[0x23, 0x11]), None);
+    }
+
+    #[test]
+    fn serialize_slice() {
+        let header = Header {
+            magic: MAGIC,
+            version: version::V2,
+            sequence: 7,
+        };
+        let command = HostToSp::GetStatus;
+
+        let mut full = [0; MAX_MESSAGE_SIZE];
+        let n = serialize(&mut full, &header, &command, |_| 0).unwrap();
+
+        // A buffer sized for the command alone produces the same message.
+        let mut small = [0; max_size_for::<HostToSp>()];
+        assert!(small.len() < MAX_MESSAGE_SIZE);
+        let m = try_serialize_slice(&mut small, &header, &command, |_| {
+            Ok::<_, HostToSp>(0)
+        })
+        .unwrap();
+        assert_eq!(&small[..m], &full[..n]);
+
+        // Data that doesn't fit is refused rather than written out of bounds.
+        let mut small = [0; max_size_for::<HostToSp>()];
+        assert_eq!(
+            try_serialize_slice(&mut small, &header, &command, |buf| {
+                Ok::<_, HostToSp>(buf.len() + 1)
+            }),
+            Err(HubpackError::Custom)
+        );
+
+        // Buffers too small for the header, command, or checksum fail.
+        for len in [0, Header::MAX_SIZE, Header::MAX_SIZE + 2] {
+            let mut tiny = vec![0; len];
+            assert_eq!(
+                try_serialize_slice(&mut tiny, &header, &command, |_| {
+                    Ok::<_, HostToSp>(0)
+                }),
+                Err(HubpackError::Overrun),
+                "len {len}"
+            );
+        }
+    }
+
+    #[test]
+    fn mac_address_expansion() {
+        let base = [0xa8, 0x40, 0x25, 0x00, 0x00, 0x10];
+        let addrs: Vec<_> = iter_mac_addresses(base, 3, 2).collect();
+        assert_eq!(
+            addrs,
+            [
+                [0xa8, 0x40, 0x25, 0x00, 0x00, 0x10],
+                [0xa8, 0x40, 0x25, 0x00, 0x00, 0x12],
+                [0xa8, 0x40, 0x25, 0x00, 0x00, 0x14],
+            ]
+        );
+
+        // A stride past `...ff` carries through every byte that overflows.
+        let base = [0xa8, 0x40, 0x25, 0x01, 0xff, 0xfe];
+        let addrs: Vec<_> = iter_mac_addresses(base, 3, 1).collect();
+        assert_eq!(
+            addrs,
+            [
+                [0xa8, 0x40, 0x25, 0x01, 0xff, 0xfe],
+                [0xa8, 0x40, 0x25, 0x01, 0xff, 0xff],
+                [0xa8, 0x40, 0x25, 0x02, 0x00, 0x00],
+            ]
+        );
+        let base = [0xa8, 0x40, 0x25, 0xff, 0xff, 0xf0];
+        assert_eq!(
+            iter_mac_addresses(base, 2, 0x20).last(),
+            Some([0xa8, 0x40, 0x26, 0x00, 0x00, 0x10])
+        );
+
+        // The top of the 48-bit space wraps rather than spilling over.
+        let base = [0xff; 6];
+        assert_eq!(iter_mac_addresses(base, 2, 1).last(), Some([0; 6]));
+
+        // A stride of 0 repeats the base address.
+        let addrs: Vec<_> = iter_mac_addresses(base, 4, 0).collect();
+        assert_eq!(addrs, [base; 4]);
+
+        assert_eq!(iter_mac_addresses(base, 0, 1).count(), 0);
+    }
+
+    #[test]
+    fn inventory_decode_unknown() {
+        let d = InventoryData::Ksz8463 {
+            cider: 0x8452,
+            cfgr: 0x0300,
+        };
+        let mut buf = [0; InventoryData::MAX_SIZE + 2];
+        let n = hubpack::serialize(&mut buf, &d).unwrap();
+        buf[n] = 0xaa;
+        let (decoded, rest) =
+            InventoryData::deserialize_forward_compatible(&buf[..n + 1])
+                .unwrap();
+        assert_eq!(decoded, InventoryDecode::Known(d));
+        assert_eq!(rest, [0xaa]);
+
+        // One past the last variant we know about is "from the future".
+        let next = InventoryDataKind::Ltc4306 as u8 + 1;
+        let blob = [next, 1, 2, 3];
+        let (decoded, rest) =
+            InventoryData::deserialize_forward_compatible(&blob).unwrap();
+        assert_eq!(
+            decoded,
+            InventoryDecode::Unknown {
+                discriminant: next,
+                raw: &[1, 2, 3],
+            }
+        );
+        assert!(rest.is_empty());
+
+        // ...and so is every later one.
+        let blob = [0xff];
+        assert!(matches!(
+            InventoryData::deserialize_forward_compatible(&blob),
+            Ok((
+                InventoryDecode::Unknown {
+                    discriminant: 0xff,
+                    raw: &[]
+                },
+                _
+            ))
+        ));
+
+        // Errors decoding a known variant are still errors.
+        assert!(InventoryData::deserialize_forward_compatible(&buf[..n - 1])
+            .is_err());
+        assert!(InventoryData::deserialize_forward_compatible(&[]).is_err());
+    }
 }
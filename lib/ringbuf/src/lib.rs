@@ -163,6 +163,28 @@
 //! counted_ringbuf!(MyEvent, 16, MyEvent::NothingHappened, no_dedup);
 //! ```
 //!
+//! ### Timestamped ring buffers
+//!
+//! When chasing down timing problems, it's useful to know not just the order
+//! in which entries were recorded, but when.  Adding the `timestamped` argument
+//! at the end of the [`ringbuf!`] or [`counted_ringbuf!`] macro stamps each
+//! entry with the kernel's timestamp (that is, `sys_get_timer().now`) when it
+//! is recorded:
+//!
+//! ```
+//! counted_ringbuf!(MyEvent, 16, MyEvent::NothingHappened, timestamped);
+//! ```
+//!
+//! Entries are recorded with the same [`ringbuf_entry!`] and
+//! [`ringbuf_entry_root!`] macros, but are stored as [`Timestamped`] payloads.
+//! This costs a `sys_get_timer` syscall and eight bytes per entry, which is
+//! why it is opt-in.  Because the timestamp is part of the payload, an entry
+//! will only be de-duplicated against the previous one if it was recorded in
+//! the same tick.  The code that expands the macro must depend on `userlib`
+//! (for `sys_get_timer`).  If the "disabled" feature is set, a timestamped
+//! ring buffer is identical to one without timestamps, and no syscall is
+//! made.
+//!
 //! ## Inspecting a ring buffer via Humility
 //!
 //! Humility has built-in support for dumping a ring buffer, and will (by
@@ -249,6 +271,9 @@ macro_rules! ringbuf {
     ($name:ident, $t:ty, $n:expr, $init:expr, no_dedup) => {
         $crate::ringbuf!($name, $t, $n, $init)
     };
+    ($name:ident, $t:ty, $n:expr, $init:expr, timestamped) => {
+        $crate::ringbuf!($name, $t, $n, $init);
+    };
     ($name:ident, $t:ty, $n:expr, $init:expr) => {
         #[allow(dead_code)]
         const _: $t = $init;
@@ -257,6 +282,9 @@ macro_rules! ringbuf {
     ($t:ty, $n:expr, $init:expr, no_dedup) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init);
     };
+    ($t:ty, $n:expr, $init:expr, timestamped) => {
+        $crate::ringbuf!(__RINGBUF, $t, $n, $init);
+    };
     ($t:ty, $n:expr, $init:expr) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init);
     };
@@ -276,6 +304,11 @@ macro_rules! ringbuf {
 ///
 /// To support the common case of having one quickly-installed ringbuffer per
 /// module, if you omit the name, it will default to `__RINGBUF`.
+///
+/// `ringbuf!(NAME, Type, N, expr, timestamped)` instead makes a
+/// [`TimestampedRingbuf`], which stamps each entry with the time at which it
+/// was recorded; see [the crate-level
+/// documentation](crate#timestamped-ring-buffers).
 #[cfg(not(feature = "disabled"))]
 #[macro_export]
 macro_rules! ringbuf {
@@ -305,9 +338,34 @@ macro_rules! ringbuf {
                 }; $n],
             });
     };
+    ($name:ident, $t:ty, $n:expr, $init:expr, timestamped) => {
+        #[used]
+        static $name: $crate::TimestampedRingbuf<
+            $crate::StaticCell<
+                $crate::Ringbuf<$crate::Timestamped<$t>, u16, $n>,
+            >,
+        > = $crate::TimestampedRingbuf {
+            ringbuf: $crate::StaticCell::new($crate::Ringbuf {
+                last: None,
+                buffer: [$crate::RingbufEntry {
+                    line: 0,
+                    generation: 0,
+                    count: 0,
+                    payload: $crate::Timestamped {
+                        timestamp: 0,
+                        payload: $init,
+                    },
+                }; $n],
+            }),
+            now: || userlib::sys_get_timer().now,
+        };
+    };
     ($t:ty, $n:expr, $init:expr, no_dedup) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init, no_dedup);
     };
+    ($t:ty, $n:expr, $init:expr, timestamped) => {
+        $crate::ringbuf!(__RINGBUF, $t, $n, $init, timestamped);
+    };
     ($t:ty, $n:expr, $init:expr) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init);
     };
@@ -330,6 +388,9 @@ macro_rules! ringbuf {
 /// To support the common case of having one quickly-installed ringbuffer per
 /// module, if you omit the name, it will default to `__RINGBUF`.
 ///
+/// As with [`ringbuf!`], adding `timestamped` stamps each entry with the time
+/// at which it was recorded.
+///
 #[cfg(all(
     not(feature = "disabled"),
     not(feature = "counters-disabled"),
@@ -369,9 +430,35 @@ macro_rules! counted_ringbuf {
                 counters: <$t as $crate::Count>::NEW_COUNTERS,
             };
     };
+    ($name:ident, $t:ident, $n:expr, $init:expr, timestamped) => {
+        #[used]
+        static $name: $crate::TimestampedRingbuf<
+            $crate::CountedRingbuf<$crate::Timestamped<$t>, u16, $n>,
+        > = $crate::TimestampedRingbuf {
+            ringbuf: $crate::CountedRingbuf {
+                ringbuf: $crate::StaticCell::new($crate::Ringbuf {
+                    last: None,
+                    buffer: [$crate::RingbufEntry {
+                        line: 0,
+                        generation: 0,
+                        count: 0,
+                        payload: $crate::Timestamped {
+                            timestamp: 0,
+                            payload: $init,
+                        },
+                    }; $n],
+                }),
+                counters: <$t as $crate::Count>::NEW_COUNTERS,
+            },
+            now: || userlib::sys_get_timer().now,
+        };
+    };
     ($t:ident, $n:expr, $init:expr, no_dedup) => {
         $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init, no_dedup);
     };
+    ($t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init, timestamped);
+    };
     ($t:ident, $n:expr, $init:expr) => {
         $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init);
     };
@@ -400,9 +487,15 @@ macro_rules! counted_ringbuf {
                 _c: core::marker::PhantomData,
             };
     };
+    ($name:ident, $t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::counted_ringbuf!($name, $t, $n, $init);
+    };
     ($t:ident, $n:expr, $init:expr, no_dedup) => {
         $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init, no_dedup);
     };
+    ($t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init);
+    };
     ($t:ident, $n:expr, $init:expr) => {
         $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init);
     };
@@ -418,12 +511,18 @@ macro_rules! counted_ringbuf {
     ($name:ident, $t:ident, $n:expr, $init:expr, no_dedup) => {
         $crate::ringbuf!($name, $t, $n, $init, no_dedup)
     };
+    ($name:ident, $t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::ringbuf!($name, $t, $n, $init, timestamped);
+    };
     ($name:ident, $t:ident, $n:expr, $init:expr) => {
         $crate::ringbuf!($name, $t, $n, $init)
     };
     ($t:ident, $n:expr, $init:expr, no_dedup) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init, no_dedup);
     };
+    ($t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::ringbuf!(__RINGBUF, $t, $n, $init, timestamped);
+    };
     ($t:ident, $n:expr, $init:expr) => {
         $crate::ringbuf!(__RINGBUF, $t, $n, $init);
     };
@@ -439,6 +538,9 @@ macro_rules! counted_ringbuf {
     ($name:ident, $t:ident, $n:expr, $init:expr, no_dedup) => {
         $crate::counted_ringbuf!(%name, $t, $n, $init)
     };
+    ($name:ident, $t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::counted_ringbuf!($name, $t, $n, $init);
+    };
     ($name:ident, $t:ident, $n:expr, $init:expr) => {
         #[allow(dead_code)]
         const _: $t = $init;
@@ -447,6 +549,9 @@ macro_rules! counted_ringbuf {
     ($t:ident, $n:expr, $init:expr, no_dedup) => {
         $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init);
     };
+    ($t:ident, $n:expr, $init:expr, timestamped) => {
+        $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init);
+    };
     ($t:ident, $n:expr, $init:expr) => {
         $crate::counted_ringbuf!(__RINGBUF, $t, $n, $init);
     };
@@ -476,6 +581,27 @@ macro_rules! ringbuf_entry {
     };
 }
 
+/// Clears a named ringbuffer (which should have been declared with the
+/// [`ringbuf!`] or [`counted_ringbuf!`] macro), refilling it with `init`.
+///
+/// `ringbuf_reset!(NAME, init)` discards every entry in the ringbuffer called
+/// `NAME`; `init` should be the same initializer the ringbuffer was declared
+/// with. If the ringbuffer was declared with [`counted_ringbuf!`], its
+/// counters are reset to 0 as well.
+///
+/// If you declared your ringbuffer without a name, you can also use this
+/// without a name, and it will default to `__RINGBUF`.
+#[macro_export]
+macro_rules! ringbuf_reset {
+    ($buf:expr, $init:expr) => {{
+        let (init, buf) = ($init, &$buf);
+        $crate::ResetRingbuf::reset(buf, init);
+    }};
+    ($init:expr) => {
+        $crate::ringbuf_reset!(__RINGBUF, $init);
+    };
+}
+
 /// Inserts data into a ringbuffer at the root of this crate (which should have
 /// been declared with the [`ringbuf!`] or [`counted_ringbuf!`] macro).
 ///
@@ -542,6 +668,43 @@ pub struct CountedRingbuf<T: Count + Copy, C, const N: usize> {
     pub counters: T::Counters,
 }
 
+///
+/// A ring buffer payload, stamped with the time at which it was recorded.
+///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Timestamped<T> {
+    /// The kernel timestamp (as returned by `sys_get_timer`).
+    pub timestamp: u64,
+    pub payload: T,
+}
+
+#[cfg(feature = "counters")]
+impl<T: Count> Count for Timestamped<T> {
+    type Counters = T::Counters;
+    const NEW_COUNTERS: Self::Counters = T::NEW_COUNTERS;
+
+    fn count(&self, counters: &Self::Counters) {
+        self.payload.count(counters)
+    }
+    fn reset(counters: &Self::Counters) {
+        T::reset(counters)
+    }
+}
+
+///
+/// A ring buffer (either a [`Ringbuf`] or a [`CountedRingbuf`]) of
+/// [`Timestamped`] entries, along with the means to tell the time.  Recording
+/// an entry of type `T` stamps it with the time returned by `now`.
+///
+/// In practice, instantiating this directly is strange -- see the
+/// `timestamped` argument to the [`ringbuf!`] and [`counted_ringbuf!`]
+/// macros.
+///
+pub struct TimestampedRingbuf<B> {
+    pub ringbuf: B,
+    pub now: fn() -> u64,
+}
+
 ///
 /// An abstraction over types in which ring buffer entries can be recorded.
 ///
@@ -557,6 +720,9 @@ pub struct CountedRingbuf<T: Count + Copy, C, const N: usize> {
 ///   [`ringbuf!`] macro, when the "disabled" feature flag is not enabled.
 /// - `()`: used by ringbufs declared using the [`ringbuf!`] macro, when the
 ///   "disabled" feature flag is enabled. This implementation is a no-op.
+/// - [`TimestampedRingbuf`]: used by ringbufs declared with the `timestamped`
+///   argument. This implementation stamps the entry with the current time and
+///   records it in the underlying ringbuf.
 ///
 /// It's typically unnecessary to implement this trait for other types, as its
 /// only purpose is to allow the [`ringbuf_entry!`] and [`ringbuf_entry_root!`]
@@ -646,6 +812,18 @@ where
     }
 }
 
+impl<T, B> RecordEntry<T> for TimestampedRingbuf<B>
+where
+    T: Copy,
+    B: RecordEntry<Timestamped<T>>,
+{
+    fn record_entry(&self, line: u16, payload: T) {
+        let timestamp = (self.now)();
+        self.ringbuf
+            .record_entry(line, Timestamped { timestamp, payload })
+    }
+}
+
 impl<T> RecordEntry<T> for ()
 where
     T: Copy + PartialEq,
@@ -653,6 +831,75 @@ where
     fn record_entry(&self, _: u16, _: T) {}
 }
 
+///
+/// An abstraction over ring buffers that can be cleared back to their initial
+/// state.
+///
+/// This is the counterpart to [`RecordEntry`], and is implemented for the
+/// same set of types, so that the [`ringbuf_reset!`] macro works regardless
+/// of how the ringbuf was declared. Resetting a [`CountedRingbuf`] also
+/// resets its counters to 0.
+pub trait ResetRingbuf<T: Copy> {
+    /// Discard every entry in this ringbuf, refilling it with `init`.
+    ///
+    /// `init` should be the same value the ringbuf was declared with, so that
+    /// tools reading the ringbuf see the same contents as they would for a
+    /// freshly started task.
+    fn reset(&self, init: T);
+}
+
+impl<T: Copy, C: Default, const N: usize> ResetRingbuf<T>
+    for StaticCell<Ringbuf<T, C, { N }>>
+{
+    fn reset(&self, init: T) {
+        // As in `record_entry`, quietly do nothing rather than panicking if
+        // the ringbuf is somehow already borrowed.
+        let Some(mut ring) = self.try_borrow_mut() else {
+            return;
+        };
+        for ent in ring.buffer.iter_mut() {
+            *ent = RingbufEntry {
+                line: 0,
+                generation: 0,
+                count: C::default(),
+                payload: init,
+            };
+        }
+        ring.last = None;
+    }
+}
+
+#[cfg(feature = "counters")]
+impl<T, C, const N: usize> ResetRingbuf<T> for CountedRingbuf<T, C, { N }>
+where
+    T: Count + Copy,
+    StaticCell<Ringbuf<T, C, N>>: ResetRingbuf<T>,
+{
+    fn reset(&self, _init: T) {
+        T::reset(&self.counters);
+
+        #[cfg(not(feature = "disabled"))]
+        self.ringbuf.reset(_init)
+    }
+}
+
+impl<T, B> ResetRingbuf<T> for TimestampedRingbuf<B>
+where
+    T: Copy,
+    B: ResetRingbuf<Timestamped<T>>,
+{
+    fn reset(&self, init: T) {
+        self.ringbuf.reset(Timestamped {
+            timestamp: 0,
+            payload: init,
+        })
+    }
+}
+
+impl<T: Copy> ResetRingbuf<T> for () {
+    fn reset(&self, _: T) {}
+}
+
 impl<T: Copy, C, const N: usize> Ringbuf<T, C, N> {
     fn do_record(&mut self, last: usize, line: u16, count: C, payload: T) {
         // Either we were unable to reuse the entry, or the last index was out
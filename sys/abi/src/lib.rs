@@ -87,6 +87,50 @@ impl From<u8> for Generation {
     }
 }
 
+/// A point in kernel time, in ticks since boot, as taken by the `SET_TIMER`
+/// syscall.
+///
+/// Timer deadlines are absolute, and treating a duration as one (by
+/// forgetting to add the current time) is an easy mistake that a bare `u64`
+/// can't catch. A `Deadline` can only be made from a time that is explicitly
+/// absolute ([`Deadline::at`]), or from a time and a duration
+/// ([`Deadline::after_from`]); userlib adds constructors that read the current
+/// time for you.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct Deadline(u64);
+
+impl Deadline {
+    /// Returns the deadline at the absolute time `ticks`.
+    pub const fn at(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    /// Returns the deadline `ticks` after the time `now`.
+    ///
+    /// Rather than wrapping around to a time in the past (which would fire
+    /// immediately), a deadline past the end of time saturates to it.
+    pub const fn after_from(now: u64, ticks: u64) -> Self {
+        Self(now.saturating_add(ticks))
+    }
+
+    /// Returns the absolute time of this deadline, in ticks since boot.
+    pub const fn ticks(self) -> u64 {
+        self.0
+    }
+
+    /// Returns `true` if this deadline has been reached at the time `now`.
+    pub const fn is_elapsed_at(self, now: u64) -> bool {
+        now >= self.0
+    }
+
+    /// Returns the number of ticks from `now` until this deadline, or 0 if it
+    /// has been reached.
+    pub const fn remaining_at(self, now: u64) -> u64 {
+        self.0.saturating_sub(now)
+    }
+}
+
 /// Newtype wrapper for an interrupt index
 #[derive(
     Copy,
@@ -183,6 +227,44 @@ pub struct ULease {
     pub length: u32,
 }
 
+impl ULease {
+    /// Creates a lease of `length` bytes at `base_address`, checking that
+    /// the leased memory can be described in the (32-bit) ABI: that is, that
+    /// both the base address and the length fit in a `u32`, and that the end
+    /// of the leased memory does not wrap around the address space.
+    pub fn try_new(
+        attributes: LeaseAttributes,
+        base_address: usize,
+        length: usize,
+    ) -> Result<Self, LeaseError> {
+        let base = u32::try_from(base_address)
+            .map_err(|_| LeaseError::AddressTooLarge)?;
+        let len =
+            u32::try_from(length).map_err(|_| LeaseError::LengthTooLarge)?;
+
+        if u64::from(base) + u64::from(len) > 1 << 32 {
+            return Err(LeaseError::Wraps);
+        }
+
+        Ok(Self {
+            attributes,
+            base_address: base,
+            length: len,
+        })
+    }
+}
+
+/// Reasons why a slice cannot be leased.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LeaseError {
+    /// The base address does not fit in 32 bits.
+    AddressTooLarge,
+    /// The length does not fit in 32 bits.
+    LengthTooLarge,
+    /// The leased memory extends past the end of the 32-bit address space.
+    Wraps,
+}
+
 #[derive(Copy, Clone, Debug, FromBytes, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct LeaseAttributes(u32);
@@ -216,6 +298,119 @@ pub const fn extract_new_generation(code: u32) -> Option<Generation> {
     }
 }
 
+/// Ways in which a SEND can fail.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SendError {
+    /// The server replied with this (nonzero) response code.
+    Server(u32),
+    /// The peer died or was restarted; this is its new generation.
+    Dead(Generation),
+    /// The server replied successfully, but with this (unexpected) number of
+    /// bytes.
+    BadReplyLen(usize),
+}
+
+/// Decodes the response code from a SEND.
+///
+/// The response code is a `u32`, interpreted as follows:
+///
+/// - `0` indicates success.
+/// - If the top 24 bits are all set (that is, `rc & FIRST_DEAD_CODE ==
+///   FIRST_DEAD_CODE`), the code was generated by the kernel to indicate that
+///   the peer died or was restarted, and the bottom 8 bits are the peer's new
+///   generation (see `dead_response_code`).  Servers must therefore not use
+///   codes in this range.
+/// - Any other value is an error code from the server itself.
+pub const fn decode_send_rc(rc: u32) -> Result<(), SendError> {
+    if rc == 0 {
+        Ok(())
+    } else if let Some(gen) = extract_new_generation(rc) {
+        Err(SendError::Dead(gen))
+    } else {
+        Err(SendError::Server(rc))
+    }
+}
+
+/// Ways in which a POST can fail.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PostError {
+    /// The target died or was restarted; this is its new generation.  The
+    /// notification was not delivered.
+    Dead(Generation),
+}
+
+/// Decodes the response code from a POST.
+///
+/// The kernel returns `0` if the notification was posted, or a dead code (see
+/// `dead_response_code`) if the target's generation doesn't match the one in
+/// the `TaskId`.  An out-of-range task index is a fault rather than an error,
+/// so there are no other codes.
+pub const fn decode_post_rc(rc: u32) -> Result<(), PostError> {
+    match extract_new_generation(rc) {
+        Some(gen) => Err(PostError::Dead(gen)),
+        None => Ok(()),
+    }
+}
+
+/// A set of notification bits, as passed to RECV, SET_TIMER, and POST.
+///
+/// This is a zero-cost wrapper around a `u32`, allowing notification masks to
+/// be distinguished from other integers (and converted to and from them).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct Notifications(pub u32);
+
+impl Notifications {
+    /// The empty set of notifications.
+    pub const NONE: Self = Self(0);
+
+    /// Returns the set containing only notification bit `n`.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is not less than 32.
+    pub const fn bit(n: u32) -> Self {
+        assert!(n < 32);
+        Self(1 << n)
+    }
+
+    /// Returns the union of `self` and `other`.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns true if every notification in `other` is also in `self`.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if no notifications are in the set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u32> for Notifications {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<Notifications> for u32 {
+    fn from(n: Notifications) -> Self {
+        n.0
+    }
+}
+
+impl core::ops::BitOr for Notifications {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
 /// Response code returned by the kernel if a lender has defected.
 pub const DEFECT: u32 = 1;
 
@@ -259,6 +454,35 @@ impl TaskState {
     pub fn can_accept_notification(&self) -> bool {
         matches!(self, TaskState::Healthy(SchedState::InRecv(_)))
     }
+
+    /// Returns what a task in this state is blocked on, if it is healthy and
+    /// blocked in IPC.
+    pub fn block_info(&self) -> Option<BlockInfo> {
+        match *self {
+            TaskState::Healthy(SchedState::InSend(peer)) => {
+                Some(BlockInfo::Send(peer))
+            }
+            TaskState::Healthy(SchedState::InReply(peer)) => {
+                Some(BlockInfo::Reply(peer))
+            }
+            TaskState::Healthy(SchedState::InRecv(peer)) => {
+                Some(BlockInfo::Recv(peer))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Describes what a task that is blocked in IPC is waiting on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlockInfo {
+    /// Waiting to deliver a message to the given task.
+    Send(TaskId),
+    /// Waiting for a reply from the given task.
+    Reply(TaskId),
+    /// Waiting for a message, either from any source (`None`) or from a
+    /// particular sender only.
+    Recv(Option<TaskId>),
 }
 
 impl Default for TaskState {
@@ -473,6 +697,18 @@ impl core::convert::TryFrom<u32> for Sysnum {
     }
 }
 
+/// A task's record of faults and restarts since boot, which (unlike its
+/// `TaskState`) survives the task being restarted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct FaultHistory {
+    /// Number of times the task has been restarted.
+    pub restarts: u32,
+    /// Number of faults the task has taken, including any double faults.
+    pub faults: u32,
+    /// The most recent of those faults, if any.
+    pub last_fault: Option<FaultInfo>,
+}
+
 /// A region to be dumped from a task
 #[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct TaskDumpRegion {
@@ -491,6 +727,7 @@ pub enum Kipcnum {
     ReadTaskDumpRegion = 7,
     SoftwareIrq = 8,
     FindFaultedTask = 9,
+    ReadTaskFaultHistory = 10,
 }
 
 impl core::convert::TryFrom<u16> for Kipcnum {
@@ -507,6 +744,7 @@ impl core::convert::TryFrom<u16> for Kipcnum {
             7 => Ok(Self::ReadTaskDumpRegion),
             8 => Ok(Self::SoftwareIrq),
             9 => Ok(Self::FindFaultedTask),
+            10 => Ok(Self::ReadTaskFaultHistory),
             _ => Err(()),
         }
     }
@@ -561,3 +799,151 @@ bitflags::bitflags! {
         const CLEAR_PENDING = 1 << 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_info() {
+        let peer = TaskId::for_index_and_gen(3, Generation::ZERO);
+
+        assert_eq!(
+            TaskState::Healthy(SchedState::InSend(peer)).block_info(),
+            Some(BlockInfo::Send(peer))
+        );
+        assert_eq!(
+            TaskState::Healthy(SchedState::InReply(peer)).block_info(),
+            Some(BlockInfo::Reply(peer))
+        );
+        assert_eq!(
+            TaskState::Healthy(SchedState::InRecv(None)).block_info(),
+            Some(BlockInfo::Recv(None))
+        );
+        assert_eq!(TaskState::Healthy(SchedState::Runnable).block_info(), None);
+        assert_eq!(
+            TaskState::Faulted {
+                fault: FaultInfo::Panic,
+                original_state: SchedState::InSend(peer),
+            }
+            .block_info(),
+            None
+        );
+    }
+
+    #[test]
+    fn deadline() {
+        let d = Deadline::after_from(1000, 25);
+        assert_eq!(d, Deadline::at(1025));
+        assert_eq!(d.ticks(), 1025);
+        assert!(!d.is_elapsed_at(1024));
+        assert!(d.is_elapsed_at(1025));
+        assert!(d.is_elapsed_at(u64::MAX));
+        assert_eq!(d.remaining_at(1000), 25);
+        assert_eq!(d.remaining_at(1025), 0);
+        assert_eq!(d.remaining_at(2000), 0);
+        assert!(Deadline::after_from(1000, 0).is_elapsed_at(1000));
+
+        // Near the end of time, deadlines saturate rather than wrapping
+        // around into the past.
+        let late = Deadline::after_from(u64::MAX - 10, 25);
+        assert_eq!(late, Deadline::at(u64::MAX));
+        assert!(!late.is_elapsed_at(u64::MAX - 10));
+        assert_eq!(late.remaining_at(u64::MAX - 10), 10);
+        assert!(late.is_elapsed_at(u64::MAX));
+        assert!(d < late);
+    }
+
+    #[test]
+    fn send_rc() {
+        assert_eq!(decode_send_rc(0), Ok(()));
+        assert_eq!(decode_send_rc(1), Err(SendError::Server(1)));
+        assert_eq!(
+            decode_send_rc(0xffff_fe00),
+            Err(SendError::Server(0xffff_fe00))
+        );
+        assert_eq!(
+            decode_send_rc(FIRST_DEAD_CODE),
+            Err(SendError::Dead(Generation::ZERO))
+        );
+        assert_eq!(
+            decode_send_rc(dead_response_code(Generation::from(0x2a))),
+            Err(SendError::Dead(Generation::from(0x2a)))
+        );
+        assert_eq!(
+            decode_send_rc(0xffff_ffff),
+            Err(SendError::Dead(Generation::from(0xff)))
+        );
+    }
+
+    #[test]
+    fn post_rc() {
+        assert_eq!(decode_post_rc(0), Ok(()));
+        assert_eq!(
+            decode_post_rc(dead_response_code(Generation::from(3))),
+            Err(PostError::Dead(Generation::from(3)))
+        );
+    }
+
+    #[test]
+    fn notification_bits() {
+        let a = Notifications::bit(0);
+        let b = Notifications::bit(31);
+
+        assert_eq!(a, Notifications(1));
+        assert_eq!(b, Notifications(0x8000_0000));
+        assert_eq!(a.union(b), Notifications(0x8000_0001));
+        assert_eq!(a | b, a.union(b));
+
+        assert!(a.union(b).contains(a));
+        assert!(a.union(b).contains(b));
+        assert!(!a.contains(b));
+        assert!(a.contains(Notifications::NONE));
+        assert!(Notifications::NONE.is_empty());
+        assert!(!a.is_empty());
+
+        assert_eq!(u32::from(Notifications::from(0x1234)), 0x1234);
+    }
+
+    #[test]
+    #[should_panic]
+    fn notification_bit_out_of_range() {
+        let n = core::hint::black_box(32);
+        let _ = Notifications::bit(n);
+    }
+
+    #[test]
+    fn lease_bounds() {
+        let rw = LeaseAttributes::READ | LeaseAttributes::WRITE;
+
+        let lease = ULease::try_new(rw, 0x2000_0000, 0x100).unwrap();
+        assert_eq!(lease.base_address, 0x2000_0000);
+        assert_eq!(lease.length, 0x100);
+
+        // A lease may run right up to the end of the address space...
+        assert!(ULease::try_new(rw, 0xffff_ff00, 0x100).is_ok());
+
+        // ...but not past it.
+        assert_eq!(
+            ULease::try_new(rw, 0xffff_ff00, 0x101).unwrap_err(),
+            LeaseError::Wraps
+        );
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn lease_too_large() {
+        let ro = LeaseAttributes::READ;
+
+        // A length that would be truncated to 0x10 by an `as` cast
+        assert_eq!(
+            ULease::try_new(ro, 0x1000, 0x1_0000_0010).unwrap_err(),
+            LeaseError::LengthTooLarge
+        );
+
+        assert_eq!(
+            ULease::try_new(ro, 0x1_0000_1000, 0x10).unwrap_err(),
+            LeaseError::AddressTooLarge
+        );
+    }
+}
@@ -42,6 +42,12 @@ pub fn handle_kernel_message(
         Ok(Kipcnum::FindFaultedTask) => {
             find_faulted_task(tasks, caller, args.message?, args.response?)
         }
+        Ok(Kipcnum::ReadTaskFaultHistory) => read_task_fault_history(
+            tasks,
+            caller,
+            args.message?,
+            args.response?,
+        ),
 
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
@@ -111,6 +117,28 @@ fn read_task_status(
     Ok(NextTask::Same)
 }
 
+fn read_task_fault_history(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let history = tasks[index as usize].fault_history();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &history)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
 fn restart_task(
     tasks: &mut [Task],
     caller: usize,
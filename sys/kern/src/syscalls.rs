@@ -768,17 +768,41 @@ fn irq_control(
     let also_clear_pending = control.contains(IrqControlArg::CLEAR_PENDING);
 
     let caller = caller as u32;
-
-    let irqs = crate::startup::HUBRIS_TASK_IRQ_LOOKUP
-        .get(abi::InterruptOwner {
+    let lookup = |notification| {
+        crate::startup::HUBRIS_TASK_IRQ_LOOKUP.get(abi::InterruptOwner {
             task: caller,
-            notification: args.notification_bitmask,
+            notification,
         })
-        .ok_or(UserError::Unrecoverable(FaultInfo::SyscallUsage(
-            UsageError::NoIrq,
-        )))?;
-    for i in irqs.iter() {
-        operation(i.0, also_clear_pending);
+    };
+    let no_irq =
+        UserError::Unrecoverable(FaultInfo::SyscallUsage(UsageError::NoIrq));
+
+    let mask = args.notification_bitmask;
+    if let Some(irqs) = lookup(mask) {
+        for i in irqs.iter() {
+            operation(i.0, also_clear_pending);
+        }
+        return Ok(NextTask::Same);
+    }
+
+    // Otherwise, the mask may name several notifications, each mapped to its
+    // own interrupt(s). Check all of them before touching any, so that a bad
+    // mask doesn't leave things half-done.
+    if mask == 0 {
+        return Err(no_irq);
+    }
+    let bits = || {
+        (0..32)
+            .map(|i| 1u32 << i)
+            .filter(move |&bit| mask & bit != 0)
+    };
+    if bits().any(|bit| lookup(bit).is_none()) {
+        return Err(no_irq);
+    }
+    for irqs in bits().filter_map(lookup) {
+        for i in irqs.iter() {
+            operation(i.0, also_clear_pending);
+        }
     }
     Ok(NextTask::Same)
 }
@@ -7,8 +7,8 @@
 use core::ops::Range;
 
 use abi::{
-    FaultInfo, FaultSource, Generation, ReplyFaultReason, SchedState, TaskId,
-    TaskState, ULease, UsageError,
+    FaultHistory, FaultInfo, FaultSource, Generation, ReplyFaultReason,
+    SchedState, TaskId, TaskState, ULease, UsageError,
 };
 use zerocopy::FromBytes;
 
@@ -42,6 +42,11 @@ pub struct Task {
     /// Restart count for this task. We increment this whenever we reinitialize
     /// the task. The low bits of this become the task's generation number.
     generation: u32,
+    /// Number of faults taken by this task, which (like `generation`) is not
+    /// reset when the task is reinitialized.
+    faults: u32,
+    /// The most recent fault taken by this task, kept across reinitialization.
+    last_fault: Option<FaultInfo>,
 
     /// Notification status.
     notifications: u32,
@@ -66,6 +71,8 @@ impl Task {
             descriptor,
 
             generation: 0,
+            faults: 0,
+            last_fault: None,
             notifications: 0,
             save: crate::arch::SavedState::default(),
             timer: crate::task::TimerState::default(),
@@ -341,6 +348,15 @@ impl Task {
         Generation::from(self.generation as u8 & MASK)
     }
 
+    /// Returns this task's record of faults and restarts.
+    pub fn fault_history(&self) -> FaultHistory {
+        FaultHistory {
+            restarts: self.generation,
+            faults: self.faults,
+            last_fault: self.last_fault,
+        }
+    }
+
     /// Returns this task's priority.
     pub fn priority(&self) -> Priority {
         self.priority
@@ -894,6 +910,8 @@ pub fn force_fault(
     fault: FaultInfo,
 ) -> NextTask {
     let task = &mut tasks[index];
+    task.faults = task.faults.wrapping_add(1);
+    task.last_fault = Some(fault);
     task.state = match task.state {
         TaskState::Healthy(sched) => TaskState::Faulted {
             original_state: sched,
@@ -7,14 +7,15 @@
 //! This is intended to provide a more ergonomic interface than the raw
 //! syscalls.
 
-use abi::{Generation, TaskId};
+use abi::{decode_send_rc, Generation, SendError, TaskId};
 use core::marker::PhantomData;
 use zerocopy::{AsBytes, FromBytes, LayoutVerified};
 
 use crate::{
-    sys_borrow_info, sys_borrow_read, sys_borrow_write, sys_get_timer,
-    sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_reply_fault,
-    sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive,
+    set_panic_operation, sys_borrow_info, sys_borrow_read, sys_borrow_write,
+    sys_get_timer, sys_recv, sys_recv_closed, sys_recv_notification,
+    sys_recv_open, sys_reply, sys_reply_fault, sys_send, sys_set_timer,
+    BorrowInfo, ClosedRecvError, FromPrimitive, RecvMessage,
 };
 
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
@@ -93,7 +94,7 @@ pub fn recv<'a, O, E, S>(
     let sender = rm.sender;
     if rm.sender == TaskId::KERNEL {
         notify(state, rm.operation);
-    } else if let Some(op) = O::from_u32(rm.operation) {
+    } else if let Ok(op) = rm.decode_operation::<O>() {
         if let Some(buffer) = buffer.get(..rm.message_len) {
             let m = Message {
                 buffer,
@@ -101,9 +102,11 @@ pub fn recv<'a, O, E, S>(
                 response_capacity: rm.response_capacity,
                 lease_count: rm.lease_count,
             };
+            set_panic_operation(Some(rm.operation));
             if let Err(e) = msg(state, op, m) {
                 sys_reply(sender, e.into(), &[]);
             }
+            set_panic_operation(None);
         } else {
             sys_reply_fault(sender, abi::ReplyFaultReason::BadMessageSize);
         }
@@ -153,16 +156,18 @@ where
         notify(state, rm.operation);
         Ok(())
     } else {
-        if let Some(op) = O::from_u32(rm.operation) {
+        if let Ok(op) = rm.decode_operation::<O>() {
             let m = Message {
                 buffer: &buffer[..rm.message_len],
                 sender: rm.sender,
                 response_capacity: rm.response_capacity,
                 lease_count: rm.lease_count,
             };
+            set_panic_operation(Some(rm.operation));
             if let Err(e) = msg(state, op, m) {
                 sys_reply(sender, e.into(), &[]);
             }
+            set_panic_operation(None);
         } else {
             sys_reply(sender, 1, &[]);
         }
@@ -187,6 +192,63 @@ where
     recv_from(source, buffer, 0, (), |_, _| (), |_, op, m| msg(op, m))
 }
 
+/// Sends `args` to `target` as operation `op`, expecting a reply of type `R`.
+///
+/// This takes care of interpreting the response code (see
+/// `abi::decode_send_rc` for its layout): a nonzero code from the server is
+/// returned as `SendError::Server`, while the kernel's indication that the
+/// peer has died or restarted is returned as `SendError::Dead` with the peer's
+/// new generation.  A successful reply that is not exactly the size of `R`
+/// results in `SendError::BadReplyLen`.
+///
+/// See `send_typed_refresh` for a variant that updates `target` with the new
+/// generation.
+pub fn send_typed<A, R>(
+    target: TaskId,
+    op: u16,
+    args: &A,
+) -> Result<R, SendError>
+where
+    A: AsBytes,
+    R: AsBytes + FromBytes,
+{
+    let mut reply = R::new_zeroed();
+    let (rc, len) =
+        sys_send(target, op, args.as_bytes(), reply.as_bytes_mut(), &[]);
+
+    decode_send_rc(rc)?;
+
+    if len != core::mem::size_of::<R>() {
+        return Err(SendError::BadReplyLen(len));
+    }
+
+    Ok(reply)
+}
+
+/// Variant of `send_typed` that, if the peer has died or restarted, updates
+/// `target` with its new generation (before returning `SendError::Dead`), so
+/// that a subsequent send will reach the new incarnation.
+///
+/// Note that the operation is not retried: whether that's safe depends on the
+/// operation.
+pub fn send_typed_refresh<A, R>(
+    target: &mut TaskId,
+    op: u16,
+    args: &A,
+) -> Result<R, SendError>
+where
+    A: AsBytes,
+    R: AsBytes + FromBytes,
+{
+    let rval = send_typed(*target, op, args);
+
+    if let Err(SendError::Dead(gen)) = rval {
+        *target = TaskId::for_index_and_gen(target.index(), gen);
+    }
+
+    rval
+}
+
 /// Represents a received message (not a notification).
 ///
 /// This type gets passed by `recv` (and related operations) into the message
@@ -418,6 +480,86 @@ impl Borrow<'_> {
     }
 }
 
+/// Performs an open receive that gives up at `deadline`.
+///
+/// This sets the timer to post `timer_notification` at `deadline`, and then
+/// receives messages and notifications in `notification_mask`.  It returns
+/// `None` if the deadline passes before anything else arrives, and otherwise
+/// returns the message (or notification) as `sys_recv_open` would.  A
+/// `deadline` that is already in the past is not an error: the timer fires
+/// immediately, and this returns `None`.  Because the kernel delivers pending
+/// notifications ahead of messages, this is true even if a message was
+/// already waiting; only another pending notification bit in
+/// `notification_mask` is returned instead.
+///
+/// # Notification bits
+///
+/// `timer_notification` must be owned exclusively by this function for the
+/// duration of the call: it must not be used for interrupts or be posted by
+/// other tasks, and any pending notification on it is consumed.  It is never
+/// reported to the caller, even if it is also set in `notification_mask`.
+/// Any other bits in `notification_mask` are returned in the `operation` of a
+/// notification, as with `sys_recv_open`.
+///
+/// On return, the timer notification is guaranteed not to be pending, and the
+/// timer is restored to the deadline (and notification) it had on entry, if
+/// any.
+pub fn recv_with_deadline(
+    buffer: &mut [u8],
+    notification_mask: u32,
+    deadline: u64,
+    timer_notification: u32,
+) -> Option<RecvMessage> {
+    let prev = sys_get_timer();
+    sys_set_timer(Some(deadline), timer_notification);
+
+    let mask = notification_mask | timer_notification;
+
+    let rval = loop {
+        let mut rm = sys_recv_open(buffer, mask);
+
+        if rm.sender != TaskId::KERNEL {
+            break Some(rm);
+        }
+
+        let fired = rm.operation & timer_notification != 0;
+        rm.operation &= !timer_notification;
+
+        if rm.operation != 0 {
+            //
+            // Other notifications arrived along with (or instead of) our
+            // timer; hand them back.  If the timer had fired, we'll clear it
+            // below, and a retry will find the deadline in the past.
+            //
+            break Some(rm);
+        }
+
+        if fired && sys_get_timer().now >= deadline {
+            break None;
+        }
+
+        // Spurious wakeup from a stale timer; go back to waiting.
+    };
+
+    if rval.is_some() {
+        //
+        // The timer may still be armed, or may have fired while we were
+        // returning a message.  Setting a deadline in the past posts the
+        // notification immediately (and disarms the timer), allowing us to
+        // consume it without blocking.
+        //
+        sys_set_timer(Some(0), timer_notification);
+        sys_recv_notification(timer_notification);
+    }
+
+    // Restore previous timer deadline and notifications
+    if let Some(deadline) = prev.deadline {
+        sys_set_timer(Some(deadline), prev.on_dl);
+    }
+
+    rval
+}
+
 /// Suspends the calling task until the kernel time is `>= time`.
 ///
 /// TODO: once we figure out how to convert between ticks and seconds here, this
@@ -35,6 +35,30 @@ pub fn read_task_status(task: usize) -> abi::TaskState {
     ssmarshal::deserialize(&response[..len]).unwrap_lite().0
 }
 
+/// Reads what the task at index `task` is blocked on, if it is healthy and
+/// blocked in IPC (sending, awaiting a reply, or receiving).  This is derived
+/// from the task's scheduling state, so it has no side effects.
+pub fn read_task_block_info(task: usize) -> Option<abi::BlockInfo> {
+    read_task_status(task).block_info()
+}
+
+/// Reads the faults and restarts recorded for the task at index `task` since
+/// boot.  Unlike [`read_task_status`], this remembers the last fault after
+/// the task has been restarted.
+pub fn read_task_fault_history(task: usize) -> abi::FaultHistory {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::FaultHistory>()];
+    let (_rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskFaultHistory as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
 /// Scans forward from index `task` looking for a task in faulted state.
 ///
 /// If no tasks at `task` or greater indices are faulted, this returns `None`.
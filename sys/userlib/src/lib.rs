@@ -55,32 +55,55 @@ pub struct Lease<'a> {
 }
 
 impl<'a> Lease<'a> {
+    /// Leases `x` read-only, failing if it cannot be described in the ABI.
+    pub fn try_read_only(x: &'a [u8]) -> Result<Self, LeaseError> {
+        Self::try_new(LeaseAttributes::READ, x)
+    }
+
+    /// Leases `x` read-write, failing if it cannot be described in the ABI.
+    pub fn try_read_write(x: &'a mut [u8]) -> Result<Self, LeaseError> {
+        Self::try_new(LeaseAttributes::READ | LeaseAttributes::WRITE, x)
+    }
+
+    /// Leases `x` write-only, failing if it cannot be described in the ABI.
+    pub fn try_write_only(x: &'a mut [u8]) -> Result<Self, LeaseError> {
+        Self::try_new(LeaseAttributes::WRITE, x)
+    }
+
     pub fn read_only(x: &'a [u8]) -> Self {
-        Self {
-            _kern_rep: abi::ULease {
-                attributes: abi::LeaseAttributes::READ,
-                base_address: x.as_ptr() as u32,
-                length: x.len() as u32,
-            },
-            _marker: PhantomData,
-        }
+        Self::new(LeaseAttributes::READ, x)
     }
 
     pub fn read_write(x: &'a mut [u8]) -> Self {
-        Self {
-            _kern_rep: abi::ULease {
-                attributes: LeaseAttributes::READ | LeaseAttributes::WRITE,
-                base_address: x.as_ptr() as u32,
-                length: x.len() as u32,
-            },
-            _marker: PhantomData,
-        }
+        Self::new(LeaseAttributes::READ | LeaseAttributes::WRITE, x)
     }
 
     pub fn write_only(x: &'a mut [u8]) -> Self {
+        Self::new(LeaseAttributes::WRITE, x)
+    }
+
+    fn try_new(
+        attributes: LeaseAttributes,
+        x: &[u8],
+    ) -> Result<Self, LeaseError> {
+        Ok(Self {
+            _kern_rep: abi::ULease::try_new(
+                attributes,
+                x.as_ptr() as usize,
+                x.len(),
+            )?,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Infallible version of `try_new`: on our 32-bit targets, a slice can
+    /// always be described in the ABI, so we only check this in debug builds.
+    fn new(attributes: LeaseAttributes, x: &[u8]) -> Self {
+        debug_assert!(Self::try_new(attributes, x).is_ok());
+
         Self {
             _kern_rep: abi::ULease {
-                attributes: LeaseAttributes::WRITE,
+                attributes,
                 base_address: x.as_ptr() as u32,
                 length: x.len() as u32,
             },
@@ -117,6 +140,16 @@ impl From<RcLen> for (u32, usize) {
     }
 }
 
+/// Sends a message to `target` and blocks until it replies, returning the
+/// response code and the length of the reply written into `incoming`.
+///
+/// Sending to your own `TaskId` (e.g. through a stale constant) is not
+/// detected: the kernel accepts it and blocks the task in send to itself
+/// forever. In a dump this shows up as a task whose state is `InSend` naming
+/// its own index. We don't check for this here, even under debug
+/// assertions, because a task has no cheap way to learn its own index --
+/// there's no syscall for it, and baking it in at build time would make
+/// `userlib` differ (and be rebuilt) for every task in the image.
 #[inline(always)]
 pub fn sys_send(
     target: TaskId,
@@ -236,8 +269,11 @@ unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
 /// This operation cannot fail -- it can be interrupted by a notification if you
 /// let it, but it always receives _something_.
 #[inline(always)]
-pub fn sys_recv_open(buffer: &mut [u8], notification_mask: u32) -> RecvMessage {
-    match sys_recv(buffer, notification_mask, None) {
+pub fn sys_recv_open(
+    buffer: &mut [u8],
+    notification_mask: impl Into<Notifications>,
+) -> RecvMessage {
+    match sys_recv(buffer, notification_mask.into().0, None) {
         Ok(rm) => rm,
         Err(_) => {
             // Safety: the open-receive version of the syscall is defined as
@@ -279,6 +315,25 @@ pub fn sys_recv_closed(
     })
 }
 
+/// Variant of `sys_recv_closed` that, on failure, returns the response code
+/// from the kernel rather than a `ClosedRecvError`, for callers that want to
+/// record exactly what the kernel said.
+///
+/// The only failure the kernel currently defines for a closed receive is a
+/// dead code: `abi::FIRST_DEAD_CODE` with `sender`'s new generation in the
+/// low 8 bits (see `abi::dead_response_code` and `abi::extract_new_generation`).
+/// This is returned if `sender`'s generation is stale when you call this, or
+/// if `sender` is restarted while you're blocked.  Any other value would
+/// indicate a kernel bug.
+#[inline(always)]
+pub fn sys_recv_closed_raw(
+    buffer: &mut [u8],
+    notification_mask: u32,
+    sender: TaskId,
+) -> Result<RecvMessage, u32> {
+    sys_recv(buffer, notification_mask, Some(sender))
+}
+
 /// Things that can go wrong (without faulting) during a closed receive
 /// operation.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -360,6 +415,58 @@ pub struct RecvMessage {
     pub lease_count: usize,
 }
 
+impl RecvMessage {
+    /// Interprets this message's operation code as an `O`, typically an enum
+    /// deriving `FromPrimitive` whose variants are the server's valid
+    /// operations.
+    ///
+    /// An operation code outside that set means the client is misconfigured
+    /// (most likely, it meant to send to some other kind of server); the
+    /// returned [`UnrecognizedOp`] can be used to fault it.
+    pub fn decode_operation<O: FromPrimitive>(
+        &self,
+    ) -> Result<O, UnrecognizedOp> {
+        O::from_u32(self.operation).ok_or(UnrecognizedOp {
+            sender: self.sender,
+            operation: self.operation,
+        })
+    }
+
+    /// Replies to the sender of this message, like [`sys_reply`].
+    ///
+    /// A reply longer than the sender's `response_capacity` is a programming
+    /// error, but the kernel currently just delivers the prefix that fits.
+    /// To catch it at the reply site instead, in debug builds this panics if
+    /// `message` won't fit. In release builds, it's exactly [`sys_reply`].
+    #[inline(always)]
+    pub fn reply(&self, code: u32, message: &[u8]) {
+        debug_assert!(
+            message.len() <= self.response_capacity,
+            "reply of {} bytes exceeds response capacity of {}",
+            message.len(),
+            self.response_capacity,
+        );
+        sys_reply(self.sender, code, message)
+    }
+}
+
+/// A message whose operation code the receiving server doesn't implement; see
+/// [`RecvMessage::decode_operation`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnrecognizedOp {
+    pub sender: TaskId,
+    pub operation: u32,
+}
+
+impl UnrecognizedOp {
+    /// Faults the sender with [`ReplyFaultReason::UndefinedOperation`], the
+    /// recommended response to a client that sends an operation we don't
+    /// implement.
+    pub fn reply_fault(self) {
+        sys_reply_fault(self.sender, ReplyFaultReason::UndefinedOperation);
+    }
+}
+
 /// Core implementation of the RECV syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
@@ -564,14 +671,17 @@ unsafe extern "C" fn sys_reply_stub(
 /// -- the `notifications` will be posted immediately and the timer will not be
 /// enabled.
 #[inline(always)]
-pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
+pub fn sys_set_timer(
+    deadline: Option<u64>,
+    notifications: impl Into<Notifications>,
+) {
     let raw_deadline = deadline.unwrap_or(0);
     unsafe {
         sys_set_timer_stub(
             deadline.is_some() as u32,
             raw_deadline as u32,
             (raw_deadline >> 32) as u32,
-            notifications,
+            notifications.into().0,
         )
     }
 }
@@ -585,7 +695,10 @@ pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
 /// 49.7 days of the "end of time" in 584 million years.
 ///
 /// Returns the actual computed wake time for your reference.
-pub fn set_timer_relative(interval: u32, notifications: u32) -> u64 {
+pub fn set_timer_relative(
+    interval: u32,
+    notifications: impl Into<Notifications>,
+) -> u64 {
     // wrapping add because the uptime is likely to be less than 584 million
     // years.
     let wake = sys_get_timer().now.wrapping_add(u64::from(interval));
@@ -593,6 +706,35 @@ pub fn set_timer_relative(interval: u32, notifications: u32) -> u64 {
     wake
 }
 
+/// Convenience wrapper for `sys_set_timer` taking a [`Deadline`], so that the
+/// deadline can't accidentally be a duration.
+#[inline(always)]
+pub fn sys_set_timer_deadline(
+    deadline: Option<Deadline>,
+    notifications: impl Into<Notifications>,
+) {
+    sys_set_timer(deadline.map(Deadline::ticks), notifications)
+}
+
+/// Methods of [`Deadline`] that read the current time.
+pub trait DeadlineExt: Sized {
+    /// Returns the deadline `ticks` from now (saturating at the end of time).
+    fn after(ticks: u64) -> Self;
+
+    /// Returns `true` if the deadline has been reached.
+    fn is_elapsed(self) -> bool;
+}
+
+impl DeadlineExt for Deadline {
+    fn after(ticks: u64) -> Self {
+        Deadline::after_from(sys_get_timer().now, ticks)
+    }
+
+    fn is_elapsed(self) -> bool {
+        self.is_elapsed_at(sys_get_timer().now)
+    }
+}
+
 /// Core implementation of the SET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
@@ -991,6 +1133,36 @@ pub fn sys_irq_control_clear_pending(mask: u32, enable: bool) {
     }
 }
 
+/// Enables and disables several interrupts at once, given as `(mask, enable)`
+/// pairs, with the same effect as calling [`sys_irq_control`] for each pair in
+/// order.
+///
+/// The masks are combined by polarity, so this makes at most two syscalls:
+/// one enabling every interrupt to be enabled, and one disabling the rest.
+/// (If every pair has the same polarity, it makes only one, and if `irqs` is
+/// empty, none.) If the same notification bit appears in more than one pair,
+/// the last pair wins.
+pub fn sys_irq_control_multi(irqs: &[(u32, bool)]) {
+    let mut enable = 0;
+    let mut disable = 0;
+    for &(mask, en) in irqs {
+        if en {
+            enable |= mask;
+            disable &= !mask;
+        } else {
+            disable |= mask;
+            enable &= !mask;
+        }
+    }
+
+    if enable != 0 {
+        sys_irq_control(enable, true);
+    }
+    if disable != 0 {
+        sys_irq_control(disable, false);
+    }
+}
+
 /// Core implementation of the IRQ_CONTROL syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
@@ -1369,6 +1541,41 @@ compile_error!(
      this check in userlib.)"
 );
 
+/// Operation being serviced by the task, for inclusion in panic messages, or
+/// `NO_OPERATION` if none.
+#[cfg(feature = "panic-messages")]
+static PANIC_OPERATION: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(NO_OPERATION);
+
+/// Sentinel for `PANIC_OPERATION`; IPC operation codes are 16 bits, so this
+/// can't collide with a real operation.
+#[cfg(feature = "panic-messages")]
+const NO_OPERATION: u32 = u32::MAX;
+
+/// Records the operation that the task is currently servicing (or `None` if
+/// it is not servicing one), to be included in the message if the task
+/// panics.  The `hl::recv` family does this for each message it dispatches;
+/// receive loops that don't use those should do the same.
+///
+/// Note that Idol servers, which dispatch through `idol_runtime` rather than
+/// `hl`, do not call this, so their panics carry no operation.
+///
+/// With the `panic-messages` feature, a recorded operation is prepended to the
+/// panic message as `op=NN: `; without it, this does nothing.
+#[inline(always)]
+pub fn set_panic_operation(operation: Option<u32>) {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "panic-messages")] {
+            PANIC_OPERATION.store(
+                operation.unwrap_or(NO_OPERATION),
+                core::sync::atomic::Ordering::Relaxed,
+            );
+        } else {
+            let _ = operation;
+        }
+    }
+}
+
 /// Panic handler for user tasks with the `panic-messages` feature enabled. This
 /// handler will try its best to generate a panic message, up to a maximum
 /// buffer size (configured below).
@@ -1376,7 +1583,9 @@ compile_error!(
 /// Including this panic handler permanently reserves a buffer in the RAM of a
 /// task, to ensure that memory is available for the panic message, even if the
 /// resources have been trimmed aggressively using `xtask sizes` and `humility
-/// stackmargin`.
+/// stackmargin`.  This buffer is 128 bytes unless one of the
+/// `panic-messages-64`, `panic-messages-256`, or `panic-messages-512` features
+/// is enabled to select a different size.
 #[cfg(all(not(feature = "no-panic"), feature = "panic-messages"))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
@@ -1392,10 +1601,23 @@ fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
     // does not panic, so we have to work very carefully.
 
     // There's a tradeoff here between "getting a useful message" and "wasting a
-    // lot of RAM." Somewhat arbitrarily, we choose to collect this many bytes
-    // of panic message (and permanently reserve the same number of bytes of
-    // RAM):
-    const BUFSIZE: usize = 128;
+    // lot of RAM." We collect this many bytes of panic message (and
+    // permanently reserve the same number of bytes of RAM). Somewhat
+    // arbitrarily, this defaults to 128 bytes, but tasks that format a lot of
+    // state into their panics can ask for more (and tasks that are very tight
+    // on RAM can ask for less) via the `panic-messages-N` features. The size
+    // is a constant either way, so none of the code below changes.
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "panic-messages-512")] {
+            const BUFSIZE: usize = 512;
+        } else if #[cfg(feature = "panic-messages-256")] {
+            const BUFSIZE: usize = 256;
+        } else if #[cfg(feature = "panic-messages-64")] {
+            const BUFSIZE: usize = 64;
+        } else {
+            const BUFSIZE: usize = 128;
+        }
+    }
 
     // Panic messages get constructed using `core::fmt::Write`. If we implement
     // that trait, we can provide our own type that will back the
@@ -1509,6 +1731,15 @@ fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
         buf: panic_buffer,
         pos: 0,
     };
+
+    // If we know what operation we were servicing, lead with that. Formatting
+    // an integer can't panic, and PrefixWrite truncates as needed, so the
+    // context still fits in (and can't overrun) the buffer.
+    let op = PANIC_OPERATION.load(core::sync::atomic::Ordering::Relaxed);
+    if op != NO_OPERATION {
+        write!(pw, "op={}: ", op).ok();
+    }
+
     write!(pw, "{}", info).ok();
 
     // Get the written part of the message.
@@ -1614,9 +1845,30 @@ unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
     }
 }
 
+/// Posts `bits` to the notification set of the task `task_id`.
+///
+/// Returns `0` if the notification was posted.  If `task_id` names a dead
+/// generation of its task (because the task has since restarted), nothing is
+/// posted and the result is a dead code carrying the task's current generation
+/// (see `abi::dead_response_code`).  An out-of-range task index faults the
+/// caller.
+///
+/// Most callers want [`sys_post_checked`], which decodes this.
+#[inline(always)]
+pub fn sys_post(task_id: TaskId, bits: impl Into<Notifications>) -> u32 {
+    unsafe { sys_post_stub(task_id.0 as u32, bits.into().0) }
+}
+
+/// Posts `bits` to the task `task_id` as [`sys_post`] does, returning
+/// `Err(PostError::Dead(gen))` if the notification went nowhere because the
+/// task restarted.  The caller can then refresh its `TaskId` (e.g. with
+/// [`sys_refresh_task_id`]) and post again if appropriate.
 #[inline(always)]
-pub fn sys_post(task_id: TaskId, bits: u32) -> u32 {
-    unsafe { sys_post_stub(task_id.0 as u32, bits) }
+pub fn sys_post_checked(
+    task_id: TaskId,
+    bits: impl Into<Notifications>,
+) -> Result<(), PostError> {
+    decode_post_rc(sys_post(task_id, bits))
 }
 
 /// Core implementation of the POST syscall.
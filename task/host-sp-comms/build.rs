@@ -4,6 +4,7 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     build_util::expose_target_board();
+    build_util::expose_git_version();
     build_util::build_notifications()?;
 
     #[cfg(feature = "gimlet")]
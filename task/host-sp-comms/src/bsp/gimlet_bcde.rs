@@ -10,13 +10,16 @@ use super::ServerImpl;
 use drv_i2c_api::I2cDevice;
 use drv_i2c_api::ResponseCode;
 use drv_i2c_devices::at24csw080::{At24Csw080, Error as EepromError};
+use drv_i2c_devices::max31790::Max31790;
 use drv_oxide_vpd::VpdError;
 use drv_spi_api::SpiServer;
 use task_sensor_api::SensorId;
 use userlib::TaskId;
 use zerocopy::AsBytes;
 
-use host_sp_messages::{InventoryData, InventoryDataResult};
+use host_sp_messages::{
+    InventoryData, InventoryDataKind, InventoryDataResult, SpdDeviceType,
+};
 
 userlib::task_slot!(I2C, i2c_driver);
 userlib::task_slot!(SPI, spi_driver);
@@ -46,10 +49,82 @@ macro_rules! by_refdes {
     };
 }
 
-impl ServerImpl {
-    /// Number of devices in our inventory
-    pub(crate) const INVENTORY_COUNT: u32 = 72;
+/// Expands `(kind, first, last)` rows into the kind of each inventory index,
+/// checking that the rows cover every index from 0 in order.
+const fn inventory_kinds<const N: usize>(
+    rows: &[(InventoryDataKind, u32, u32)],
+) -> [InventoryDataKind; N] {
+    let mut out = [rows[0].0; N];
+    let mut next = 0;
+    let mut i = 0;
+    while i < rows.len() {
+        let (kind, first, last) = rows[i];
+        assert!(first == next, "inventory indices must be contiguous");
+        assert!(last >= first, "inventory index range is empty");
+        while next <= last {
+            out[next as usize] = kind;
+            next += 1;
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Defines both `ServerImpl::INVENTORY` and the lookup function from a single
+/// list of `(indices, kind) => { lookup }` entries, so that the kind reported
+/// for an index can't drift from the lookup that serves it.
+macro_rules! inventory {
+    (@last $first:literal) => { $first };
+    (@last $first:literal $last:literal) => { $last };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident(
+            &mut $self:ident,
+            $sequence:ident: u64,
+            $index:ident: u32 $(,)?
+        ) {
+            $(
+                ($first:literal $(..= $last:literal)?, $kind:ident)
+                    => $body:block
+            )*
+        }
+    ) => {
+        const INVENTORY_ROWS: &[(InventoryDataKind, u32, u32)] = &[$(
+            (
+                InventoryDataKind::$kind,
+                $first,
+                inventory!(@last $first $($last)?),
+            ),
+        )*];
+
+        const INVENTORY_KINDS: [InventoryDataKind;
+            INVENTORY_ROWS[INVENTORY_ROWS.len() - 1].2 as usize + 1] =
+            inventory_kinds(INVENTORY_ROWS);
+
+        impl ServerImpl {
+            /// Kind of data returned for each index in our inventory
+            pub(crate) const INVENTORY: &'static [InventoryDataKind] =
+                &INVENTORY_KINDS;
+
+            $(#[$meta])*
+            $vis fn $name(
+                &mut $self,
+                $sequence: u64,
+                $index: u32,
+            ) -> Result<(), InventoryDataResult> {
+                #[forbid(unreachable_patterns)]
+                match $index {
+                    $($first $(..= $last)? => $body)*
+                    _ => return Err(InventoryDataResult::InvalidIndex),
+                }
 
+                Ok(())
+            }
+        }
+    };
+}
+
+inventory! {
     /// Look up a device in our inventory, by index
     ///
     /// Indexes are assigned arbitrarily and may change freely with SP
@@ -71,516 +146,532 @@ impl ServerImpl {
         &mut self,
         sequence: u64,
         index: u32,
-    ) -> Result<(), InventoryDataResult> {
-        #[forbid(unreachable_patterns)]
-        match index {
-            0..=15 => {
-                self.dimm_inventory_lookup(sequence, index);
-            }
-            16 => {
-                // U615/ID: SP barcode is available in packrat
-                let packrat = &self.packrat;
-                let mut data = InventoryData::VpdIdentity(Default::default());
-                self.tx_buf.try_encode_inventory(sequence, b"U615/ID", || {
-                    let InventoryData::VpdIdentity(identity) = &mut data else {
-                        unreachable!();
-                    };
-                    *identity = packrat
-                        .get_identity()
-                        .map_err(|_| InventoryDataResult::DeviceAbsent)?
-                        .into();
-                    Ok(&data)
-                });
-            }
-            17 => {
-                // U615: Gimlet VPD EEPROM
-                //
-                // Note that for VPD AT24CSW080 identities, we allocate our
-                // InventoryData in the outer frame then pass it in as a
-                // reference; `read_at24csw080_id` typically isn't inlined, and
-                // we're already paying a stack frame for the data in this
-                // function, so it saves us 512 bytes of stack.
-                let (name, f, _sensors) = by_refdes!(U615, at24csw080);
-                let mut data = InventoryData::At24csw08xSerial([0u8; 16]);
-                self.read_at24csw080_id(sequence, &name, f, &mut data)
-            }
-            18 => {
-                // J180/ID: Fan VPD barcode (not available in packrat)
-                self.read_fan_barcodes(
-                    sequence,
-                    b"J180/ID",
-                    i2c_config::devices::at24csw080_fan_vpd,
-                )
-            }
-            19 => {
-                // J180: Fan VPD EEPROM (on the daughterboard)
-                let mut data = InventoryData::At24csw08xSerial([0u8; 16]);
-                self.read_at24csw080_id(
-                    sequence,
-                    b"J180/U1",
-                    i2c_config::devices::at24csw080_fan_vpd,
-                    &mut data,
-                )
-            }
-            // Welcome to The Sharkfin Zone
-            //
-            // Each Sharkfin has 3 inventory items:
-            // - Oxide barcode
-            // - Raw VPD EEPROM ID register
-            // - Hot-swap controller
-            //
-            // Sharkfin connectors start at J206 and are numbered sequentially
-            20..=29 => {
-                let (designator, f): ([u8; 4], _) =
-                    Self::get_sharkfin_vpd(index as usize - 20);
-                let mut name = *b"____/U7/ID";
-                name[0..4].copy_from_slice(&designator);
-                self.read_eeprom_barcode(sequence, &name, f)
-            }
-            30..=39 => {
-                let (designator, f): ([u8; 4], _) =
-                    Self::get_sharkfin_vpd(index as usize - 30);
-                let mut name = *b"____/U7";
-                name[0..4].copy_from_slice(&designator);
-                let mut data = InventoryData::At24csw08xSerial([0u8; 16]);
-                self.read_at24csw080_id(sequence, &name, f, &mut data)
-            }
-            40 => {
-                // U12: the service processor itself
-                // The UID is readable by stm32xx_sys
-                let sys =
-                    drv_stm32xx_sys_api::Sys::from(crate::SYS.get_task_id());
-                let uid = sys.read_uid();
-
-                let idc = drv_stm32h7_dbgmcu::read_idc();
-                let dbgmcu_rev_id = (idc >> 16) as u16;
-                let dbgmcu_dev_id = (idc & 4095) as u16;
-                let data = InventoryData::Stm32H7 {
-                    uid,
-                    dbgmcu_rev_id,
-                    dbgmcu_dev_id,
-                };
-                self.tx_buf
-                    .try_encode_inventory(sequence, b"U12", || Ok(&data));
-            }
-            41 => {
-                // U431: BRM491
-                let (name, f, sensors) = by_refdes!(U431, bmr491);
-                let dev = f(I2C.get_task_id());
-                // To be stack-friendly, we declare our output here,
-                // then bind references to all the member variables.
-                let mut data = InventoryData::Bmr491 {
-                    mfr_id: [0u8; 12],
-                    mfr_model: [0u8; 20],
-                    mfr_revision: [0u8; 12],
-                    mfr_location: [0u8; 12],
-                    mfr_date: [0u8; 12],
-                    mfr_serial: [0u8; 20],
-                    mfr_firmware_data: [0u8; 20],
-                    temp_sensor: sensors.temperature.into(),
-                    voltage_sensor: sensors.voltage.into(),
-                    current_sensor: sensors.current.into(),
-                    power_sensor: sensors.power.into(),
+    ) {
+        (0..=15, DimmSpd) => {
+            self.dimm_inventory_lookup(sequence, index);
+        }
+        (16, VpdIdentity) => {
+            // U615/ID: SP barcode is available in packrat
+            let packrat = &self.packrat;
+            let mut data = InventoryData::VpdIdentity(Default::default());
+            self.tx_buf.try_encode_inventory(sequence, b"U615/ID", || {
+                let InventoryData::VpdIdentity(identity) = &mut data else {
+                    unreachable!();
                 };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    use pmbus::commands::bmr491::CommandCode;
-                    let InventoryData::Bmr491 {
-                        mfr_id,
-                        mfr_model,
-                        mfr_revision,
-                        mfr_location,
-                        mfr_date,
-                        mfr_serial,
-                        mfr_firmware_data,
-                        temp_sensor: _,
-                        voltage_sensor: _,
-                        current_sensor: _,
-                        power_sensor: _,
-                    } = &mut data
-                    else {
-                        unreachable!()
-                    };
-                    dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
-                    dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
-                    dev.read_block(
-                        CommandCode::MFR_REVISION as u8,
-                        mfr_revision,
-                    )?;
-                    dev.read_block(
-                        CommandCode::MFR_LOCATION as u8,
-                        mfr_location,
-                    )?;
-                    dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
-                    dev.read_block(CommandCode::MFR_SERIAL as u8, mfr_serial)?;
-                    dev.read_block(
-                        CommandCode::MFR_FIRMWARE_DATA as u8,
-                        mfr_firmware_data,
-                    )?;
-                    Ok(&data)
-                })
-            }
+                *identity = packrat
+                    .get_identity()
+                    .map_err(|_| InventoryDataResult::DeviceAbsent)?
+                    .into();
+                Ok(&data)
+            });
+        }
+        (17, At24csw08xSerial) => {
+            // U615: Gimlet VPD EEPROM
+            //
+            // Note that for VPD AT24CSW080 identities, we allocate our
+            // InventoryData in the outer frame then pass it in as a
+            // reference; `read_at24csw080_id` typically isn't inlined, and
+            // we're already paying a stack frame for the data in this
+            // function, so it saves us 512 bytes of stack.
+            let (name, f, _sensors) = by_refdes!(U615, at24csw080);
+            let mut data = InventoryData::At24csw08xSerial([0u8; 16]);
+            self.read_at24csw080_id(sequence, &name, f, &mut data)
+        }
+        (18, FanIdentity) => {
+            // J180/ID: Fan VPD barcode (not available in packrat)
+            self.read_fan_barcodes(
+                sequence,
+                b"J180/ID",
+                i2c_config::devices::at24csw080_fan_vpd,
+            )
+        }
+        (19, At24csw08xSerial) => {
+            // J180: Fan VPD EEPROM (on the daughterboard)
+            let mut data = InventoryData::At24csw08xSerial([0u8; 16]);
+            self.read_at24csw080_id(
+                sequence,
+                b"J180/U1",
+                i2c_config::devices::at24csw080_fan_vpd,
+                &mut data,
+            )
+        }
+        // Welcome to The Sharkfin Zone
+        //
+        // Each Sharkfin has 3 inventory items:
+        // - Oxide barcode
+        // - Raw VPD EEPROM ID register
+        // - Hot-swap controller
+        //
+        // Sharkfin connectors start at J206 and are numbered sequentially
+        (20..=29, VpdIdentity) => {
+            let (designator, f): ([u8; 4], _) =
+                Self::get_sharkfin_vpd(index as usize - 20);
+            let mut name = *b"____/U7/ID";
+            name[0..4].copy_from_slice(&designator);
+            self.read_eeprom_barcode(sequence, &name, f)
+        }
+        (30..=39, At24csw08xSerial) => {
+            let (designator, f): ([u8; 4], _) =
+                Self::get_sharkfin_vpd(index as usize - 30);
+            let mut name = *b"____/U7";
+            name[0..4].copy_from_slice(&designator);
+            let mut data = InventoryData::At24csw08xSerial([0u8; 16]);
+            self.read_at24csw080_id(sequence, &name, f, &mut data)
+        }
+        (40, Stm32H7) => {
+            // U12: the service processor itself
+            // The UID is readable by stm32xx_sys
+            let sys =
+                drv_stm32xx_sys_api::Sys::from(crate::SYS.get_task_id());
+            let uid = sys.read_uid();
 
-            42 => {
-                let (name, f, sensors) = by_refdes!(U352, isl68224);
-                let dev = f(I2C.get_task_id());
-                // To be stack-friendly, we declare our output here,
-                // then bind references to all the member variables.
-                let mut data = InventoryData::Isl68224 {
-                    mfr_id: [0u8; 4],
-                    mfr_model: [0u8; 4],
-                    mfr_revision: [0u8; 4],
-                    mfr_date: [0u8; 4],
-                    ic_device_id: [0u8; 4],
-                    ic_device_rev: [0u8; 4],
-                    voltage_sensors: SensorId::into_u32_array(sensors.voltage),
-                    current_sensors: SensorId::into_u32_array(sensors.current),
-                };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    use pmbus::commands::isl68224::CommandCode;
-                    let InventoryData::Isl68224 {
-                        mfr_id,
-                        mfr_model,
-                        mfr_revision,
-                        mfr_date,
-                        ic_device_id,
-                        ic_device_rev,
-                        voltage_sensors: _,
-                        current_sensors: _,
-                    } = &mut data
-                    else {
-                        unreachable!()
-                    };
-                    dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
-                    dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
-                    dev.read_block(
-                        CommandCode::MFR_REVISION as u8,
-                        mfr_revision,
-                    )?;
-                    dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
-                    dev.read_block(
-                        CommandCode::IC_DEVICE_ID as u8,
-                        ic_device_id,
-                    )?;
-                    dev.read_block(
-                        CommandCode::IC_DEVICE_REV as u8,
-                        ic_device_rev,
-                    )?;
-                    Ok(&data)
-                })
-            }
-            43 | 44 => {
-                let (name, f, sensors) = match index - 43 {
-                    0 => by_refdes!(U350, raa229618),
-                    1 => by_refdes!(U351, raa229618),
-                    _ => unreachable!(),
-                };
-                let dev = f(I2C.get_task_id());
-
-                // To be stack-friendly, we declare our output here,
-                // then bind references to all the member variables.
-                let mut data = InventoryData::Raa229618 {
-                    mfr_id: [0u8; 4],
-                    mfr_model: [0u8; 4],
-                    mfr_revision: [0u8; 4],
-                    mfr_date: [0u8; 4],
-                    ic_device_id: [0u8; 4],
-                    ic_device_rev: [0u8; 4],
-                    temp_sensors: SensorId::into_u32_array(sensors.temperature),
-                    power_sensors: SensorId::into_u32_array(sensors.power),
-                    voltage_sensors: SensorId::into_u32_array(sensors.voltage),
-                    current_sensors: SensorId::into_u32_array(sensors.current),
+            let idc = drv_stm32h7_dbgmcu::read_idc();
+            let dbgmcu_rev_id = (idc >> 16) as u16;
+            let dbgmcu_dev_id = (idc & 4095) as u16;
+            let data = InventoryData::Stm32H7 {
+                uid,
+                dbgmcu_rev_id,
+                dbgmcu_dev_id,
+            };
+            self.tx_buf
+                .try_encode_inventory(sequence, b"U12", || Ok(&data));
+        }
+        (41, Bmr491) => {
+            // U431: BRM491
+            let (name, f, sensors) = by_refdes!(U431, bmr491);
+            let dev = f(I2C.get_task_id());
+            // To be stack-friendly, we declare our output here,
+            // then bind references to all the member variables.
+            let mut data = InventoryData::Bmr491 {
+                mfr_id: [0u8; 12],
+                mfr_model: [0u8; 20],
+                mfr_revision: [0u8; 12],
+                mfr_location: [0u8; 12],
+                mfr_date: [0u8; 12],
+                mfr_serial: [0u8; 20],
+                mfr_firmware_data: [0u8; 20],
+                temp_sensor: sensors.temperature.into(),
+                voltage_sensor: sensors.voltage.into(),
+                current_sensor: sensors.current.into(),
+                power_sensor: sensors.power.into(),
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                use pmbus::commands::bmr491::CommandCode;
+                let InventoryData::Bmr491 {
+                    mfr_id,
+                    mfr_model,
+                    mfr_revision,
+                    mfr_location,
+                    mfr_date,
+                    mfr_serial,
+                    mfr_firmware_data,
+                    temp_sensor: _,
+                    voltage_sensor: _,
+                    current_sensor: _,
+                    power_sensor: _,
+                } = &mut data
+                else {
+                    unreachable!()
                 };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    use pmbus::commands::raa229618::CommandCode;
-                    let InventoryData::Raa229618 {
-                        mfr_id,
-                        mfr_model,
-                        mfr_revision,
-                        mfr_date,
-                        ic_device_id,
-                        ic_device_rev,
-                        temp_sensors: _,
-                        power_sensors: _,
-                        voltage_sensors: _,
-                        current_sensors: _,
-                    } = &mut data
-                    else {
-                        unreachable!()
-                    };
-                    dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
-                    dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
-                    dev.read_block(
-                        CommandCode::MFR_REVISION as u8,
-                        mfr_revision,
-                    )?;
-                    dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
-                    dev.read_block(
-                        CommandCode::IC_DEVICE_ID as u8,
-                        ic_device_id,
-                    )?;
-                    dev.read_block(
-                        CommandCode::IC_DEVICE_REV as u8,
-                        ic_device_rev,
-                    )?;
-                    Ok(&data)
-                })
-            }
+                dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
+                dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
+                dev.read_block(
+                    CommandCode::MFR_REVISION as u8,
+                    mfr_revision,
+                )?;
+                dev.read_block(
+                    CommandCode::MFR_LOCATION as u8,
+                    mfr_location,
+                )?;
+                dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
+                dev.read_block(CommandCode::MFR_SERIAL as u8, mfr_serial)?;
+                dev.read_block(
+                    CommandCode::MFR_FIRMWARE_DATA as u8,
+                    mfr_firmware_data,
+                )?;
+                Ok(&data)
+            })
+        }
 
-            45..=49 => {
-                let (name, f, sensors) = match index - 45 {
-                    0 => by_refdes!(U522, tps546b24a),
-                    1 => by_refdes!(U560, tps546b24a),
-                    2 => by_refdes!(U524, tps546b24a),
-                    3 => by_refdes!(U561, tps546b24a),
-                    4 => by_refdes!(U565, tps546b24a),
-                    _ => unreachable!(),
+        (42, Isl68224) => {
+            let (name, f, sensors) = by_refdes!(U352, isl68224);
+            let dev = f(I2C.get_task_id());
+            // To be stack-friendly, we declare our output here,
+            // then bind references to all the member variables.
+            let mut data = InventoryData::Isl68224 {
+                mfr_id: [0u8; 4],
+                mfr_model: [0u8; 4],
+                mfr_revision: [0u8; 4],
+                mfr_date: [0u8; 4],
+                ic_device_id: [0u8; 4],
+                ic_device_rev: [0u8; 4],
+                voltage_sensors: SensorId::into_u32_array(sensors.voltage),
+                current_sensors: SensorId::into_u32_array(sensors.current),
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                use pmbus::commands::isl68224::CommandCode;
+                let InventoryData::Isl68224 {
+                    mfr_id,
+                    mfr_model,
+                    mfr_revision,
+                    mfr_date,
+                    ic_device_id,
+                    ic_device_rev,
+                    voltage_sensors: _,
+                    current_sensors: _,
+                } = &mut data
+                else {
+                    unreachable!()
                 };
-                let dev = f(I2C.get_task_id());
-                let mut data = InventoryData::Tps546b24a {
-                    mfr_id: [0u8; 3],
-                    mfr_model: [0u8; 3],
-                    mfr_revision: [0u8; 3],
-                    mfr_serial: [0u8; 3],
-                    ic_device_id: [0u8; 6],
-                    ic_device_rev: [0u8; 2],
-                    nvm_checksum: 0u16,
-                    temp_sensor: sensors.temperature.into(),
-                    voltage_sensor: sensors.voltage.into(),
-                    current_sensor: sensors.current.into(),
+                dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
+                dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
+                dev.read_block(
+                    CommandCode::MFR_REVISION as u8,
+                    mfr_revision,
+                )?;
+                dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
+                dev.read_block(
+                    CommandCode::IC_DEVICE_ID as u8,
+                    ic_device_id,
+                )?;
+                dev.read_block(
+                    CommandCode::IC_DEVICE_REV as u8,
+                    ic_device_rev,
+                )?;
+                Ok(&data)
+            })
+        }
+        (43..=44, Raa229618) => {
+            let (name, f, sensors) = match index - 43 {
+                0 => by_refdes!(U350, raa229618),
+                1 => by_refdes!(U351, raa229618),
+                _ => unreachable!(),
+            };
+            let dev = f(I2C.get_task_id());
+
+            // To be stack-friendly, we declare our output here,
+            // then bind references to all the member variables.
+            let mut data = InventoryData::Raa229618 {
+                mfr_id: [0u8; 4],
+                mfr_model: [0u8; 4],
+                mfr_revision: [0u8; 4],
+                mfr_date: [0u8; 4],
+                ic_device_id: [0u8; 4],
+                ic_device_rev: [0u8; 4],
+                temp_sensors: SensorId::into_u32_array(sensors.temperature),
+                power_sensors: SensorId::into_u32_array(sensors.power),
+                voltage_sensors: SensorId::into_u32_array(sensors.voltage),
+                current_sensors: SensorId::into_u32_array(sensors.current),
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                use pmbus::commands::raa229618::CommandCode;
+                let InventoryData::Raa229618 {
+                    mfr_id,
+                    mfr_model,
+                    mfr_revision,
+                    mfr_date,
+                    ic_device_id,
+                    ic_device_rev,
+                    temp_sensors: _,
+                    power_sensors: _,
+                    voltage_sensors: _,
+                    current_sensors: _,
+                } = &mut data
+                else {
+                    unreachable!()
                 };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    use pmbus::commands::tps546b24a::CommandCode;
-                    let InventoryData::Tps546b24a {
-                        mfr_id,
-                        mfr_model,
-                        mfr_revision,
-                        mfr_serial,
-                        ic_device_id,
-                        ic_device_rev,
-                        nvm_checksum,
-                        temp_sensor: _,
-                        voltage_sensor: _,
-                        current_sensor: _,
-                    } = &mut data
-                    else {
-                        unreachable!()
-                    };
-                    dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
-                    dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
-                    dev.read_block(
-                        CommandCode::MFR_REVISION as u8,
-                        mfr_revision,
-                    )?;
-                    dev.read_block(CommandCode::MFR_SERIAL as u8, mfr_serial)?;
-                    dev.read_block(
-                        CommandCode::IC_DEVICE_ID as u8,
-                        ic_device_id,
-                    )?;
-                    dev.read_block(
-                        CommandCode::IC_DEVICE_REV as u8,
-                        ic_device_rev,
-                    )?;
-                    dev.read_reg_into(
-                        CommandCode::NVM_CHECKSUM as u8,
-                        nvm_checksum.as_bytes_mut(),
-                    )?;
-                    Ok(&data)
-                })
-            }
-            50 | 51 => {
-                // U452 and U419, both ADM1272
-                let (name, f, sensors) = match index - 50 {
-                    0 => by_refdes!(U419, adm1272),
-                    1 => by_refdes!(U452, adm1272),
-                    _ => unreachable!(),
+                dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
+                dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
+                dev.read_block(
+                    CommandCode::MFR_REVISION as u8,
+                    mfr_revision,
+                )?;
+                dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
+                dev.read_block(
+                    CommandCode::IC_DEVICE_ID as u8,
+                    ic_device_id,
+                )?;
+                dev.read_block(
+                    CommandCode::IC_DEVICE_REV as u8,
+                    ic_device_rev,
+                )?;
+                Ok(&data)
+            })
+        }
+
+        (45..=49, Tps546b24a) => {
+            let (name, f, sensors) = match index - 45 {
+                0 => by_refdes!(U522, tps546b24a),
+                1 => by_refdes!(U560, tps546b24a),
+                2 => by_refdes!(U524, tps546b24a),
+                3 => by_refdes!(U561, tps546b24a),
+                4 => by_refdes!(U565, tps546b24a),
+                _ => unreachable!(),
+            };
+            let dev = f(I2C.get_task_id());
+            let mut data = InventoryData::Tps546b24a {
+                mfr_id: [0u8; 3],
+                mfr_model: [0u8; 3],
+                mfr_revision: [0u8; 3],
+                mfr_serial: [0u8; 3],
+                ic_device_id: [0u8; 6],
+                ic_device_rev: [0u8; 2],
+                nvm_checksum: 0u16,
+                temp_sensor: sensors.temperature.into(),
+                voltage_sensor: sensors.voltage.into(),
+                current_sensor: sensors.current.into(),
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                use pmbus::commands::tps546b24a::CommandCode;
+                let InventoryData::Tps546b24a {
+                    mfr_id,
+                    mfr_model,
+                    mfr_revision,
+                    mfr_serial,
+                    ic_device_id,
+                    ic_device_rev,
+                    nvm_checksum,
+                    temp_sensor: _,
+                    voltage_sensor: _,
+                    current_sensor: _,
+                } = &mut data
+                else {
+                    unreachable!()
                 };
-                let dev = f(I2C.get_task_id());
+                dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
+                dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
+                dev.read_block(
+                    CommandCode::MFR_REVISION as u8,
+                    mfr_revision,
+                )?;
+                dev.read_block(CommandCode::MFR_SERIAL as u8, mfr_serial)?;
+                dev.read_block(
+                    CommandCode::IC_DEVICE_ID as u8,
+                    ic_device_id,
+                )?;
+                dev.read_block(
+                    CommandCode::IC_DEVICE_REV as u8,
+                    ic_device_rev,
+                )?;
+                dev.read_reg_into(
+                    CommandCode::NVM_CHECKSUM as u8,
+                    nvm_checksum.as_bytes_mut(),
+                )?;
+                Ok(&data)
+            })
+        }
+        (50..=51, Adm1272) => {
+            // U452 and U419, both ADM1272
+            let (name, f, sensors) = match index - 50 {
+                0 => by_refdes!(U419, adm1272),
+                1 => by_refdes!(U452, adm1272),
+                _ => unreachable!(),
+            };
+            let dev = f(I2C.get_task_id());
 
-                let mut data = InventoryData::Adm1272 {
-                    mfr_id: [0u8; 3],
-                    mfr_model: [0u8; 10],
-                    mfr_revision: [0u8; 2],
-                    mfr_date: [0u8; 6],
+            let mut data = InventoryData::Adm1272 {
+                mfr_id: [0u8; 3],
+                mfr_model: [0u8; 10],
+                mfr_revision: [0u8; 2],
+                mfr_date: [0u8; 6],
 
-                    temp_sensor: sensors.temperature.into(),
-                    voltage_sensor: sensors.voltage.into(),
-                    current_sensor: sensors.current.into(),
+                temp_sensor: sensors.temperature.into(),
+                voltage_sensor: sensors.voltage.into(),
+                current_sensor: sensors.current.into(),
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                use pmbus::commands::tps546b24a::CommandCode;
+                let InventoryData::Adm1272 {
+                    mfr_id,
+                    mfr_model,
+                    mfr_revision,
+                    mfr_date,
+                    temp_sensor: _,
+                    voltage_sensor: _,
+                    current_sensor: _,
+                } = &mut data
+                else {
+                    unreachable!()
                 };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    use pmbus::commands::tps546b24a::CommandCode;
-                    let InventoryData::Adm1272 {
-                        mfr_id,
-                        mfr_model,
-                        mfr_revision,
-                        mfr_date,
-                        temp_sensor: _,
-                        voltage_sensor: _,
-                        current_sensor: _,
-                    } = &mut data
-                    else {
-                        unreachable!()
-                    };
-                    dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
-                    dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
-                    dev.read_block(
-                        CommandCode::MFR_REVISION as u8,
-                        mfr_revision,
-                    )?;
-                    dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
-                    Ok(&data)
-                })
-            }
+                dev.read_block(CommandCode::MFR_ID as u8, mfr_id)?;
+                dev.read_block(CommandCode::MFR_MODEL as u8, mfr_model)?;
+                dev.read_block(
+                    CommandCode::MFR_REVISION as u8,
+                    mfr_revision,
+                )?;
+                dev.read_block(CommandCode::MFR_DATE as u8, mfr_date)?;
+                Ok(&data)
+            })
+        }
 
-            52..=57 => {
-                let (connector_name, f, sensors): ([u8; 4], _, _) =
-                    match index - 52 {
-                        0 => by_refdes!(J194, tmp117),
-                        1 => by_refdes!(J195, tmp117),
-                        2 => by_refdes!(J196, tmp117),
-                        3 => by_refdes!(J197, tmp117),
-                        4 => by_refdes!(J198, tmp117),
-                        5 => by_refdes!(J199, tmp117),
-                        _ => unreachable!(),
-                    };
-                let dev = f(I2C.get_task_id());
-
-                // Convert the name from Jxxx (in the TOML file) -> Jxxx/U1
-                let mut name = *b"Jxxx/U1";
-                // All connector names should have length 4; that's checked by
-                // the type in the tuple assignment above.
-                name[..4].copy_from_slice(&connector_name);
-
-                let mut data = InventoryData::Tmp117 {
-                    id: 0,
-                    eeprom1: 0,
-                    eeprom2: 0,
-                    eeprom3: 0,
-                    temp_sensor: sensors.temperature.into(),
+        (52..=57, Tmp117) => {
+            let (connector_name, f, sensors): ([u8; 4], _, _) =
+                match index - 52 {
+                    0 => by_refdes!(J194, tmp117),
+                    1 => by_refdes!(J195, tmp117),
+                    2 => by_refdes!(J196, tmp117),
+                    3 => by_refdes!(J197, tmp117),
+                    4 => by_refdes!(J198, tmp117),
+                    5 => by_refdes!(J199, tmp117),
+                    _ => unreachable!(),
                 };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    let InventoryData::Tmp117 {
-                        id,
-                        eeprom1,
-                        eeprom2,
-                        eeprom3,
-                        temp_sensor: _,
-                    } = &mut data
-                    else {
-                        unreachable!();
-                    };
-                    *id = dev.read_reg(0x0Fu8)?;
-                    *eeprom1 = dev.read_reg(0x05u8)?;
-                    *eeprom2 = dev.read_reg(0x06u8)?;
-                    *eeprom3 = dev.read_reg(0x08u8)?;
-                    Ok(&data)
-                })
-            }
+            let dev = f(I2C.get_task_id());
+
+            // Convert the name from Jxxx (in the TOML file) -> Jxxx/U1
+            let mut name = *b"Jxxx/U1";
+            // All connector names should have length 4; that's checked by
+            // the type in the tuple assignment above.
+            name[..4].copy_from_slice(&connector_name);
 
-            58 => {
-                let (name, f, _sensors) = by_refdes!(U446, idt8a34003);
-                let dev = f(I2C.get_task_id());
-                let mut data = InventoryData::Idt8a34003 {
-                    hw_rev: 0,
-                    major_rel: 0,
-                    minor_rel: 0,
-                    hotfix_rel: 0,
-                    product_id: 0,
+            let mut data = InventoryData::Tmp117 {
+                id: 0,
+                eeprom1: 0,
+                eeprom2: 0,
+                eeprom3: 0,
+                temp_sensor: sensors.temperature.into(),
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                let InventoryData::Tmp117 {
+                    id,
+                    eeprom1,
+                    eeprom2,
+                    eeprom3,
+                    temp_sensor: _,
+                } = &mut data
+                else {
+                    unreachable!();
                 };
-                self.tx_buf.try_encode_inventory(sequence, &name, || {
-                    let InventoryData::Idt8a34003 {
-                        hw_rev,
-                        major_rel,
-                        minor_rel,
-                        hotfix_rel,
-                        product_id,
-                    } = &mut data
-                    else {
-                        unreachable!();
-                    };
-                    // This chip includes a separate register that controls the
-                    // upper address byte, i.e. a paged memory implementation.
-                    // We'll use `write_read_reg` to avoid the possibility of
-                    // race conditions here.
-                    *hw_rev = dev.write_read_reg(
-                        0x1eu8,
-                        &[0xfc, 0x00, 0xc0, 0x10, 0x20],
-                    )?;
-                    *major_rel = dev.write_read_reg(
-                        0x24u8,
-                        &[0xfc, 0x00, 0xc0, 0x10, 0x20],
-                    )?;
-                    *minor_rel = dev.write_read_reg(
-                        0x25u8,
-                        &[0xfc, 0x00, 0xc0, 0x10, 0x20],
-                    )?;
-                    *hotfix_rel = dev.write_read_reg(
-                        0x26u8,
-                        &[0xfc, 0x00, 0xc0, 0x10, 0x20],
-                    )?;
-                    *product_id = dev.write_read_reg(
-                        0x32u8,
-                        &[0xfc, 0x00, 0xc0, 0x10, 0x20],
-                    )?;
-                    Ok(&data)
-                })
-            }
+                *id = dev.read_reg(0x0Fu8)?;
+                *eeprom1 = dev.read_reg(0x05u8)?;
+                *eeprom2 = dev.read_reg(0x06u8)?;
+                *eeprom3 = dev.read_reg(0x08u8)?;
+                Ok(&data)
+            })
+        }
 
-            59 => {
-                let spi = drv_spi_api::Spi::from(SPI.get_task_id());
-                let ksz8463_dev = spi.device(drv_spi_api::devices::KSZ8463);
-                let ksz8463 = ksz8463::Ksz8463::new(ksz8463_dev);
-                let mut data = InventoryData::Ksz8463 { cider: 0 };
-                self.tx_buf.try_encode_inventory(sequence, b"U401", || {
-                    let InventoryData::Ksz8463 { cider } = &mut data else {
-                        unreachable!();
-                    };
-                    *cider = ksz8463
-                        .read(ksz8463::Register::CIDER)
-                        .map_err(|_| InventoryDataResult::DeviceFailed)?;
-                    Ok(&data)
-                });
-            }
-            60..=70 => {
-                let i = index - 60;
-                let (name, _f, sensors) = match i {
-                    0 => by_refdes!(J206, max5970),
-                    1 => by_refdes!(J207, max5970),
-                    2 => by_refdes!(J208, max5970),
-                    3 => by_refdes!(J209, max5970),
-                    4 => by_refdes!(J210, max5970),
-                    5 => by_refdes!(J211, max5970),
-                    6 => by_refdes!(J212, max5970),
-                    7 => by_refdes!(J213, max5970),
-                    8 => by_refdes!(J214, max5970),
-                    9 => by_refdes!(J215, max5970),
-                    10 => by_refdes!(U275, max5970),
-                    _ => panic!(),
+        (58, Idt8a34003) => {
+            let (name, f, _sensors) = by_refdes!(U446, idt8a34003);
+            let dev = f(I2C.get_task_id());
+            let mut data = InventoryData::Idt8a34003 {
+                hw_rev: 0,
+                major_rel: 0,
+                minor_rel: 0,
+                hotfix_rel: 0,
+                product_id: 0,
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                let InventoryData::Idt8a34003 {
+                    hw_rev,
+                    major_rel,
+                    minor_rel,
+                    hotfix_rel,
+                    product_id,
+                } = &mut data
+                else {
+                    unreachable!();
                 };
-                let data = InventoryData::Max5970 {
-                    voltage_sensors: SensorId::into_u32_array(sensors.voltage),
-                    current_sensors: SensorId::into_u32_array(sensors.current),
+                // This chip includes a separate register that controls the
+                // upper address byte, i.e. a paged memory implementation.
+                // We'll use `write_read_reg` to avoid the possibility of
+                // race conditions here.
+                *hw_rev = dev.write_read_reg(
+                    0x1eu8,
+                    &[0xfc, 0x00, 0xc0, 0x10, 0x20],
+                )?;
+                *major_rel = dev.write_read_reg(
+                    0x24u8,
+                    &[0xfc, 0x00, 0xc0, 0x10, 0x20],
+                )?;
+                *minor_rel = dev.write_read_reg(
+                    0x25u8,
+                    &[0xfc, 0x00, 0xc0, 0x10, 0x20],
+                )?;
+                *hotfix_rel = dev.write_read_reg(
+                    0x26u8,
+                    &[0xfc, 0x00, 0xc0, 0x10, 0x20],
+                )?;
+                *product_id = dev.write_read_reg(
+                    0x32u8,
+                    &[0xfc, 0x00, 0xc0, 0x10, 0x20],
+                )?;
+                Ok(&data)
+            })
+        }
+
+        (59, Ksz8463) => {
+            let spi = drv_spi_api::Spi::from(SPI.get_task_id());
+            let ksz8463_dev = spi.device(drv_spi_api::devices::KSZ8463);
+            let ksz8463 = ksz8463::Ksz8463::new(ksz8463_dev);
+            let mut data = InventoryData::Ksz8463 { cider: 0, cfgr: 0 };
+            self.tx_buf.try_encode_inventory(sequence, b"U401", || {
+                let InventoryData::Ksz8463 { cider, cfgr } = &mut data
+                else {
+                    unreachable!();
                 };
-                self.tx_buf
-                    .try_encode_inventory(sequence, &name, || Ok(&data));
-            }
-            71 => {
-                let (name, _f, sensors) = by_refdes!(U321, max31790);
-                let data = InventoryData::Max31790 {
-                    speed_sensors: SensorId::into_u32_array(sensors.speed),
+                *cider = ksz8463
+                    .read(ksz8463::Register::CIDER)
+                    .map_err(|_| InventoryDataResult::DeviceFailed)?;
+                *cfgr = ksz8463
+                    .read(ksz8463::Register::CFGR)
+                    .map_err(|_| InventoryDataResult::DeviceFailed)?;
+                Ok(&data)
+            });
+        }
+        (60..=70, Max5970) => {
+            let i = index - 60;
+            let (name, _f, sensors) = match i {
+                0 => by_refdes!(J206, max5970),
+                1 => by_refdes!(J207, max5970),
+                2 => by_refdes!(J208, max5970),
+                3 => by_refdes!(J209, max5970),
+                4 => by_refdes!(J210, max5970),
+                5 => by_refdes!(J211, max5970),
+                6 => by_refdes!(J212, max5970),
+                7 => by_refdes!(J213, max5970),
+                8 => by_refdes!(J214, max5970),
+                9 => by_refdes!(J215, max5970),
+                10 => by_refdes!(U275, max5970),
+                _ => panic!(),
+            };
+            let data = InventoryData::Max5970 {
+                voltage_sensors: SensorId::into_u32_array(sensors.voltage),
+                current_sensors: SensorId::into_u32_array(sensors.current),
+            };
+            self.tx_buf
+                .try_encode_inventory(sequence, &name, || Ok(&data));
+        }
+        (71, Max31790) => {
+            let (name, f, sensors) = by_refdes!(U321, max31790);
+            let dev = Max31790::new(&f(I2C.get_task_id()));
+            let mut data = InventoryData::Max31790 {
+                speed_sensors: SensorId::into_u32_array(sensors.speed),
+                global_configuration: 0,
+                pwm_frequency: 0,
+                fan_configuration: [0; 6],
+            };
+            self.tx_buf.try_encode_inventory(sequence, &name, || {
+                let InventoryData::Max31790 {
+                    speed_sensors: _,
+                    global_configuration,
+                    pwm_frequency,
+                    fan_configuration,
+                } = &mut data
+                else {
+                    unreachable!();
                 };
-                self.tx_buf
-                    .try_encode_inventory(sequence, &name, || Ok(&data));
-            }
-
-            // We need to specify INVENTORY_COUNT individually here to trigger
-            // an error if we've overlapped it with a previous range
-            Self::INVENTORY_COUNT | Self::INVENTORY_COUNT..=u32::MAX => {
-                return Err(InventoryDataResult::InvalidIndex)
-            }
+                let config = dev.configuration()?;
+                *global_configuration = config.global;
+                *pwm_frequency = config.pwm_frequency;
+                *fan_configuration = config.fans;
+                Ok(&data)
+            })
         }
-
-        Ok(())
     }
+}
+
+impl ServerImpl {
+    /// Number of devices in our inventory
+    pub(crate) const INVENTORY_COUNT: u32 = Self::INVENTORY.len() as u32;
 
     /// Looks up a Sharkfin VPD EEPROM by sharkfin index (0-9)
     ///
@@ -627,6 +718,11 @@ impl ServerImpl {
                     unreachable!();
                 };
                 packrat.get_full_spd_data(index as usize, id);
+                // Only DDR4 SPD data fits in this variant; refuse anything else
+                // rather than sending the host something it can't parse.
+                if SpdDeviceType::from_spd(id) != Some(SpdDeviceType::Ddr4) {
+                    return Err(InventoryDataResult::DeviceFailed);
+                }
                 Ok(&data)
             } else {
                 Err(InventoryDataResult::DeviceAbsent)
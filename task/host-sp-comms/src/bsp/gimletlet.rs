@@ -6,11 +6,15 @@
 //!
 //! This reduces clutter in the main `ServerImpl` implementation
 use super::ServerImpl;
-use host_sp_messages::{InventoryData, InventoryDataResult};
+use host_sp_messages::{InventoryData, InventoryDataKind, InventoryDataResult};
 
 impl ServerImpl {
+    /// Kind of data returned for each index in our inventory
+    pub(crate) const INVENTORY: &'static [InventoryDataKind] =
+        &[InventoryDataKind::Stm32H7];
+
     /// Number of devices in our inventory
-    pub(crate) const INVENTORY_COUNT: u32 = 1;
+    pub(crate) const INVENTORY_COUNT: u32 = Self::INVENTORY.len() as u32;
 
     pub(crate) fn perform_inventory_lookup(
         &mut self,
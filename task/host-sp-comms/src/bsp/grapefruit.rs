@@ -9,13 +9,17 @@ use super::ServerImpl;
 
 use drv_spi_api::SpiServer;
 
-use host_sp_messages::{InventoryData, InventoryDataResult};
+use host_sp_messages::{InventoryData, InventoryDataKind, InventoryDataResult};
 
 userlib::task_slot!(SPI, spi_driver);
 
 impl ServerImpl {
+    /// Kind of data returned for each index in our inventory
+    pub(crate) const INVENTORY: &'static [InventoryDataKind] =
+        &[InventoryDataKind::Stm32H7, InventoryDataKind::Ksz8463];
+
     /// Number of devices in our inventory
-    pub(crate) const INVENTORY_COUNT: u32 = 2;
+    pub(crate) const INVENTORY_COUNT: u32 = Self::INVENTORY.len() as u32;
 
     /// Look up a device in our inventory, by index
     ///
@@ -66,14 +70,18 @@ impl ServerImpl {
                 let spi = drv_spi_api::Spi::from(SPI.get_task_id());
                 let ksz8463_dev = spi.device(drv_spi_api::devices::KSZ8463);
                 let ksz8463 = ksz8463::Ksz8463::new(ksz8463_dev);
-                let mut data = InventoryData::Ksz8463 { cider: 0 };
+                let mut data = InventoryData::Ksz8463 { cider: 0, cfgr: 0 };
                 self.tx_buf.try_encode_inventory(sequence, b"U401", || {
-                    let InventoryData::Ksz8463 { cider } = &mut data else {
+                    let InventoryData::Ksz8463 { cider, cfgr } = &mut data
+                    else {
                         unreachable!();
                     };
                     *cider = ksz8463
                         .read(ksz8463::Register::CIDER)
                         .map_err(|_| InventoryDataResult::DeviceFailed)?;
+                    *cfgr = ksz8463
+                        .read(ksz8463::Register::CFGR)
+                        .map_err(|_| InventoryDataResult::DeviceFailed)?;
                     Ok(&data)
                 });
             }
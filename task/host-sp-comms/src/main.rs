@@ -20,8 +20,8 @@ use drv_usart::Usart;
 use enum_map::Enum;
 use heapless::Vec;
 use host_sp_messages::{
-    Bsu, DecodeFailureReason, Header, HostToSp, Key, KeyLookupResult,
-    KeySetResult, SpToHost, Status, MAX_MESSAGE_SIZE,
+    Bsu, DecodeFailureReason, HostToSp, InventoryDataKind, InventoryDataResult,
+    Key, KeyLookupResult, KeySetResult, SpToHost, Status, MAX_MESSAGE_SIZE,
     MIN_SP_TO_HOST_FILL_DATA_LEN,
 };
 use hubpack::SerializedSize;
@@ -138,15 +138,48 @@ enum Trace {
 
 counted_ringbuf!(Trace, 20, Trace::None);
 
+/// Counts of inventory lookup results, indexed by inventory index, so that a
+/// device that persistently fails to read is visible in a dump without having
+/// to catch it in the ringbuf.
+///
+/// Requests for indices beyond `ServerImpl::INVENTORY_COUNT` are not counted.
+#[used]
+static INVENTORY_COUNTERS: [InventoryCounters;
+    ServerImpl::INVENTORY_COUNT as usize] =
+    [INVENTORY_COUNTERS_INIT; ServerImpl::INVENTORY_COUNT as usize];
+
+// Check the BSP's inventory table at compile time: every index must fit in the
+// `u32` reported for `Key::InventorySize`, and the SP itself must appear
+// exactly once.
+const _: () = {
+    let inventory = ServerImpl::INVENTORY;
+    assert!(!inventory.is_empty(), "inventory is empty");
+    assert!(
+        inventory.len() <= u32::MAX as usize,
+        "inventory is too large"
+    );
+
+    let mut sp = 0;
+    let mut i = 0;
+    while i < inventory.len() {
+        if matches!(inventory[i], InventoryDataKind::Stm32H7) {
+            sp += 1;
+        }
+        i += 1;
+    }
+    assert!(sp == 1, "inventory must include the SP exactly once");
+};
+
+type InventoryCounters = <InventoryDataResult as counters::Count>::Counters;
+const INVENTORY_COUNTERS_INIT: InventoryCounters =
+    <InventoryDataResult as counters::Count>::NEW_COUNTERS;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum TimerDisposition {
     LeaveRunning,
     Cancel,
 }
 
-/// We set the high bit of the sequence number before replying to host requests.
-const SEQ_REPLY: u64 = 0x8000_0000_0000_0000;
-
 /// We wrap host/sp messages in corncobs; derive our max packet length from the
 /// max unwrapped message length.
 const MAX_PACKET_SIZE: usize = corncobs::max_encoded_len(MAX_MESSAGE_SIZE);
@@ -214,7 +247,10 @@ impl HostKeyValueStorage {
             // * `Ping` always returns PONG
             // * InstallinatorImageId is set via MGS
             // * InventorySize always returns our static inventory size
-            Key::Ping | Key::InstallinatorImageId | Key::InventorySize => {
+            Key::Ping
+            | Key::InstallinatorImageId
+            | Key::InventorySize
+            | Key::SpGitVersion => {
                 return KeySetResult::ReadOnlyKey;
             }
             Key::EtcSystem => {
@@ -260,6 +296,23 @@ struct ServerImpl {
 }
 
 impl ServerImpl {
+    /// Calls `perform_inventory_lookup`, recording the result of the lookup
+    /// (if one was performed) in `INVENTORY_COUNTERS`.
+    fn counted_inventory_lookup(
+        &mut self,
+        sequence: u64,
+        index: u32,
+    ) -> Result<(), InventoryDataResult> {
+        let r = self.perform_inventory_lookup(sequence, index);
+        if let (Some(result), Some(counters)) = (
+            self.tx_buf.take_inventory_result(),
+            INVENTORY_COUNTERS.get(index as usize),
+        ) {
+            counters::Count::count(&result, counters);
+        }
+        r
+    }
+
     fn claim_static_resources() -> Self {
         let sys = sys_api::Sys::from(SYS.get_task_id());
         let uart = configure_uart_device(&sys);
@@ -700,6 +753,8 @@ impl ServerImpl {
                     //
                     // If we can't get data, all we can do is send the host a
                     // response with no data; it can decide to retry later.
+                    // This is also the response for an offset at or past the
+                    // end of the image (see `HostToSp::GetPhase2Data`).
                     cp_agent
                         .get_host_phase2_data(phase2.hash, phase2.offset, dst)
                         .unwrap_or(0)
@@ -729,15 +784,18 @@ impl ServerImpl {
         &mut self,
         reset_tx_buf: bool,
     ) -> Result<(), DecodeFailureReason> {
-        let (header, request, data) = match parse_received_message(self.rx_buf)
-        {
-            Ok((header, request, data)) => (header, request, data),
-            Err(err) => {
-                ringbuf_entry!(Trace::ParseError(err));
-                self.rx_buf.clear();
-                return Err(err);
-            }
-        };
+        // `decode_host_request` checks the header's magic, version and
+        // sequence number for us, and rejects data blobs following commands
+        // that shouldn't have one.
+        let (header, request, data) =
+            match host_sp_messages::framing::decode_host_request(self.rx_buf) {
+                Ok((header, request, data)) => (header, request, data),
+                Err(err) => {
+                    ringbuf_entry!(Trace::ParseError(err));
+                    self.rx_buf.clear();
+                    return Err(err);
+                }
+            };
         ringbuf_entry!(Trace::Request {
             now: sys_get_timer().now,
             sequence: header.sequence,
@@ -750,6 +808,9 @@ impl ServerImpl {
             self.tx_buf.reset();
         }
 
+        // Reply using the same protocol version the host used.
+        self.tx_buf.set_version(header.version);
+
         // We defer any actions until after we've serialized our response to
         // avoid borrow checker issues with calling methods on `self`.
         let mut action = None;
@@ -959,7 +1020,7 @@ impl ServerImpl {
                 self.host_kv_storage.key_set(key, data),
             )),
             HostToSp::GetInventoryData { index } => {
-                match self.perform_inventory_lookup(header.sequence, index) {
+                match self.counted_inventory_lookup(header.sequence, index) {
                     Ok(()) => None,
                     Err(err) => Some(SpToHost::InventoryData {
                         result: err,
@@ -967,6 +1028,25 @@ impl ServerImpl {
                     }),
                 }
             }
+            HostToSp::GetInventoryDataRange { start, count } => {
+                // Pack as many entries as fit into a single response; the
+                // host will ask again for any we leave off the end.
+                let end = start
+                    .saturating_add(u32::from(count))
+                    .min(Self::INVENTORY_COUNT);
+                self.tx_buf.start_inventory_batch();
+                for index in start..end {
+                    if self
+                        .counted_inventory_lookup(header.sequence, index)
+                        .is_err()
+                        || self.tx_buf.inventory_batch_is_full()
+                    {
+                        break;
+                    }
+                }
+                self.tx_buf.finish_inventory_batch(header.sequence, start);
+                None
+            }
         };
 
         if let Some(response) = response {
@@ -1339,6 +1419,24 @@ impl ServerImpl {
                 );
                 response_len
             }
+            Key::SpGitVersion => {
+                const VERSION: &[u8] = env!("HUBRIS_GIT_VERSION").as_bytes();
+
+                self.tx_buf.encode_response(
+                    sequence,
+                    &SpToHost::KeyLookupResult(KeyLookupResult::Ok),
+                    |buf| {
+                        // Statically guarantee we have sufficient space in
+                        // `buf` for the version string.
+                        const_assert!(
+                            MIN_SP_TO_HOST_FILL_DATA_LEN >= VERSION.len()
+                        );
+                        buf[..VERSION.len()].copy_from_slice(VERSION);
+                        VERSION.len()
+                    },
+                );
+                VERSION.len()
+            }
         };
 
         if response_len > max_response_len {
@@ -1410,33 +1508,6 @@ impl NotificationHandler for ServerImpl {
     }
 }
 
-// This is conceptually a method on `ServerImpl`, but it takes a reference to
-// `rx_buf` instead of `self` to avoid borrow checker issues.
-fn parse_received_message(
-    rx_buf: &mut [u8],
-) -> Result<(Header, HostToSp, &[u8]), DecodeFailureReason> {
-    let n = corncobs::decode_in_place(rx_buf)
-        .map_err(|_| DecodeFailureReason::Cobs)?;
-    let deframed = &rx_buf[..n];
-
-    let (header, request, data) =
-        host_sp_messages::deserialize::<HostToSp>(deframed)?;
-
-    if header.magic != host_sp_messages::MAGIC {
-        return Err(DecodeFailureReason::MagicMismatch);
-    }
-
-    if header.version != host_sp_messages::version::V1 {
-        return Err(DecodeFailureReason::VersionMismatch);
-    }
-
-    if header.sequence & SEQ_REPLY != 0 {
-        return Err(DecodeFailureReason::SequenceInvalid);
-    }
-
-    Ok((header, request, data))
-}
-
 // This is conceptually a method on `ServerImpl`, but it takes references to
 // several of its fields instead of `self` to avoid borrow checker issues.
 fn handle_reboot_waiting_in_a2_timer(
@@ -5,14 +5,13 @@
 use crate::{Trace, MAX_MESSAGE_SIZE, MAX_PACKET_SIZE};
 use core::ops::Range;
 use host_sp_messages::{
-    DecodeFailureReason, Header, InventoryData, InventoryDataResult, SpToHost,
+    DecodeFailureReason, Header, InventoryData, InventoryDataRangeEntry,
+    InventoryDataResult, SpToHost, MAX_DEVICE_NAME_LEN, SEQ_REPLY,
 };
+use hubpack::SerializedSize;
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
 use userlib::{sys_get_timer, UnwrapLite};
 
-/// We set the high bit of the sequence number before replying to host requests.
-const SEQ_REPLY: u64 = 0x8000_0000_0000_0000;
-
 #[derive(Debug, Clone, Copy)]
 pub(super) struct WaitingForPhase2Data {
     pub(super) sequence: u64,
@@ -31,6 +30,28 @@ pub(super) struct TxBuf {
     // of `pkt`.
     pkt: &'static mut [u8; MAX_PACKET_SIZE + 1],
     state: State,
+    // Protocol version of our responses, which selects the checksum trailer.
+    version: u32,
+    // Entries packed so far for an `SpToHost::InventoryDataRange` response, if
+    // we're building one.
+    inventory_batch: Option<InventoryBatch>,
+    // Outcome of the most recent inventory lookup passed to
+    // `try_encode_inventory()`, if not yet collected.
+    inventory_result: Option<InventoryDataResult>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InventoryBatch {
+    // Offset in `TxBuf::msg` of the first entry
+    data_start: usize,
+    // Space available for entries
+    capacity: usize,
+    // Number of bytes of entries packed so far
+    len: usize,
+    // Number of entries packed so far
+    count: u16,
+    // Set once the remaining space might not hold another entry
+    full: bool,
 }
 
 pub(super) struct StaticBufs {
@@ -58,6 +79,9 @@ impl TxBuf {
             msg,
             pkt,
             state: State::Idle,
+            version: host_sp_messages::version::V1,
+            inventory_batch: None,
+            inventory_result: None,
         }
     }
 
@@ -72,6 +96,14 @@ impl TxBuf {
         self.state = State::Idle;
     }
 
+    /// Sets the protocol version used for subsequent responses.
+    ///
+    /// `version` should come from a successfully-deserialized request, and is
+    /// therefore one of `host_sp_messages::SUPPORTED_VERSIONS`.
+    pub(crate) fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
     /// Set our state to note that we do not have a response to send currently
     /// because we are waiting for host phase2 data to arrive from
     /// `control-plane-agent`.
@@ -154,12 +186,8 @@ impl TxBuf {
         reason: DecodeFailureReason,
     ) {
         assert!(!matches!(self.state, State::ToSend(_)));
-        let header = Header {
-            magic: host_sp_messages::MAGIC,
-            version: host_sp_messages::version::V1,
-            // We failed to decode, so don't know the sequence number.
-            sequence: 0xffff_ffff_ffff_ffff,
-        };
+        // We failed to decode, so don't know the sequence number.
+        let header = Header::new(0xffff_ffff_ffff_ffff);
         let response = SpToHost::DecodeFailure(reason);
 
         // Serializing can only fail if we pass unexpected types as `response`,
@@ -176,6 +204,18 @@ impl TxBuf {
         self.encode_message(n);
     }
 
+    /// Returns the outcome of the most recent inventory lookup performed by
+    /// `try_encode_inventory()`, if any, and clears it.
+    ///
+    /// Nothing is recorded if the entry was left out of the batch being built
+    /// because it was already full, since the host will request that entry
+    /// again.
+    pub(crate) fn take_inventory_result(
+        &mut self,
+    ) -> Option<InventoryDataResult> {
+        self.inventory_result.take()
+    }
+
     /// Encodes `response` into our outgoing buffer, setting the `SEQ_REPLY` bit
     /// in the header sequence number.
     ///
@@ -194,6 +234,60 @@ impl TxBuf {
         self.try_encode_response(sequence, response, |buf| Ok(fill_data(buf)))
     }
 
+    /// Begins building an `SpToHost::InventoryDataRange` response.
+    ///
+    /// Until `finish_inventory_batch()` is called, `try_encode_inventory()`
+    /// appends an entry to the batch instead of encoding a message.
+    ///
+    /// # Panics
+    ///
+    /// If we still have data from a previously-encoded message that hasn't been
+    /// sent, or are already building a batch.
+    pub(crate) fn start_inventory_batch(&mut self) {
+        assert!(!matches!(self.state, State::ToSend(_)));
+        assert!(self.inventory_batch.is_none());
+
+        // Entries are packed directly into `msg` where the data blob will
+        // land, which is immediately after the header and command; both have
+        // a fixed serialized size.
+        let command = SpToHost::InventoryDataRange { start: 0, count: 0 };
+        let command_len =
+            hubpack::serialize(&mut self.msg[Header::MAX_SIZE..], &command)
+                .unwrap_lite();
+        self.inventory_batch = Some(InventoryBatch {
+            data_start: Header::MAX_SIZE + command_len,
+            capacity: host_sp_messages::sp_to_host_fill_data_len(
+                self.version,
+                &command,
+            ),
+            len: 0,
+            count: 0,
+            full: false,
+        });
+    }
+
+    /// Returns `true` if the batch we're building has run out of space
+    pub(crate) fn inventory_batch_is_full(&self) -> bool {
+        self.inventory_batch.is_some_and(|b| b.full)
+    }
+
+    /// Encodes an `SpToHost::InventoryDataRange` response containing the
+    /// entries packed since `start_inventory_batch()`.
+    ///
+    /// # Panics
+    ///
+    /// If we are not building a batch.
+    pub(crate) fn finish_inventory_batch(&mut self, sequence: u64, start: u32) {
+        let batch = self.inventory_batch.take().unwrap_lite();
+        let response = SpToHost::InventoryDataRange {
+            start,
+            count: batch.count,
+        };
+        // The entries are already in place, so we only need to report their
+        // length.
+        self.encode_response(sequence, &response, |_| batch.len);
+    }
+
     pub(crate) fn try_encode_inventory<'a, F>(
         &mut self,
         sequence: u64,
@@ -202,9 +296,60 @@ impl TxBuf {
     ) where
         F: FnOnce() -> Result<&'a InventoryData, InventoryDataResult>,
     {
-        let mut name_array = [0u8; 32];
+        // Note the outcome of the lookup itself (if we get as far as
+        // performing it) for `take_inventory_result()`.
+        let mut inventory_result = None;
+        let fill_data = || {
+            let r = fill_data();
+            inventory_result =
+                Some(r.map_or_else(|e| e, |_| InventoryDataResult::Ok));
+            r
+        };
+
+        let mut name_array = [0u8; MAX_DEVICE_NAME_LEN];
         let n = name_array.len().min(name.len());
         name_array[..n].copy_from_slice(&name[..n]);
+
+        if let Some(batch) = &mut self.inventory_batch {
+            // Stop as soon as the largest possible entry might not fit,
+            // rather than performing the lookup (and any device I/O it
+            // involves) only to throw the result away; the host will request
+            // this entry again.
+            if batch.capacity - batch.len
+                < InventoryDataRangeEntry::MAX_SIZE + InventoryData::MAX_SIZE
+            {
+                batch.full = true;
+            }
+            if batch.full {
+                return;
+            }
+            let buf = &mut self.msg[batch.data_start..][..batch.capacity]
+                [batch.len..];
+            let entry = |result| InventoryDataRangeEntry {
+                result,
+                name: name_array,
+            };
+            let n = match fill_data() {
+                Ok(d) => {
+                    hubpack::serialize(buf, &entry(InventoryDataResult::Ok))
+                        .and_then(|n| {
+                            Ok(n + hubpack::serialize(&mut buf[n..], d)?)
+                        })
+                }
+                Err(e) => hubpack::serialize(buf, &entry(e)),
+            };
+            match n {
+                Ok(n) => {
+                    batch.len += n;
+                    batch.count += 1;
+                    self.inventory_result = inventory_result;
+                }
+                // Leave this entry (and its result) for the host to request
+                // next time
+                Err(_) => batch.full = true,
+            }
+            return;
+        }
         self.try_encode_response(
             sequence,
             &SpToHost::InventoryData {
@@ -225,7 +370,8 @@ impl TxBuf {
 
                 Ok(n)
             },
-        )
+        );
+        self.inventory_result = inventory_result;
     }
 
     /// Encodes `response` into our outgoing buffer, setting the `SEQ_REPLY` bit
@@ -260,11 +406,8 @@ impl TxBuf {
     where
         F: FnOnce(&mut [u8]) -> Result<usize, SpToHost>,
     {
-        let header = Header {
-            magic: host_sp_messages::MAGIC,
-            version: host_sp_messages::version::V1,
-            sequence: sequence | SEQ_REPLY,
-        };
+        let mut header = Header::new(sequence | SEQ_REPLY);
+        header.version = self.version;
 
         ringbuf_entry!(Trace::Response {
             now: sys_get_timer().now,
@@ -37,8 +37,29 @@ pub enum AssistOp {
     RefreshTaskIdOffByOne = 21,
     RefreshTaskIdOffByMany = 22,
     ReadNotifications = 23,
+    /// Replies, then blocks in a closed RECV from the kernel until one of the
+    /// notification bits in the message is posted (`u32 -> u32`).
+    WaitForNotification = 24,
+    /// Replies with an empty message; unlike every other op, this one is
+    /// `() -> ()`.
+    JustReplyEmpty = 25,
+    /// Reads out, and clears, the most recent notification masks observed,
+    /// oldest first (`() -> [u32; NOTIFICATION_HISTORY_LEN]`).  See
+    /// [`NOTIFICATION_HISTORY_LEN`].
+    ReadNotificationHistory = 26,
+    /// Replies, then sends the task whose `TaskId` is in the low 16 bits of
+    /// the message the operation in the high 16 bits, with a single `bool`
+    /// argument of 2. A server that notices should fault us.
+    SendBadBool = 27,
 }
 
+/// Number of notification masks remembered by the test-assist.
+///
+/// This history is best-effort: once full, older masks are dropped, and the
+/// kernel coalesces bits posted before the assistant gets to run, so several
+/// posts may show up as a single mask.  Unused slots (at the front) are 0.
+pub const NOTIFICATION_HISTORY_LEN: usize = 8;
+
 /// Operations that are performed by the test-suite
 #[derive(FromPrimitive)]
 pub enum SuiteOp {
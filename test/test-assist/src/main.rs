@@ -10,9 +10,10 @@
 
 use core::arch::asm;
 use hubris_num_tasks::NUM_TASKS;
-use test_api::AssistOp;
+use test_api::{AssistOp, NOTIFICATION_HISTORY_LEN};
 use userlib::{
-    hl, kipc, sys_refresh_task_id, sys_send, Generation, Lease, TaskId,
+    hl, kipc, sys_recv_notification, sys_refresh_task_id, sys_send, Generation,
+    Lease, TaskId,
 };
 use zerocopy::AsBytes;
 
@@ -145,13 +146,30 @@ fn eat_some_pi(highregs: bool) {
     }
 }
 
+/// Notifications we've received, which can be read back out.
+#[derive(Default)]
+struct Notifications {
+    /// Bits posted since they were last read
+    posted: u32,
+    /// The masks we've most recently received, oldest first
+    history: [u32; NOTIFICATION_HISTORY_LEN],
+}
+
+impl Notifications {
+    fn observe(&mut self, bits: u32) {
+        self.posted |= bits;
+        self.history.copy_within(1.., 0);
+        self.history[NOTIFICATION_HISTORY_LEN - 1] = bits;
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let mut buffer = [0; 4];
     let mut last_reply = 0u32;
     let mut stored_value = 0;
     let mut borrow_buffer = [0u8; 16];
-    let mut posted_bits = 0;
+    let mut notifications = Notifications::default();
 
     let fatalops = [
         (AssistOp::BadMemory, badread as fn(u32)),
@@ -173,14 +191,31 @@ fn main() -> ! {
         hl::recv(
             &mut buffer,
             ALL_NOTIFICATIONS,
-            &mut posted_bits,
-            |posted_bits, notify_bits| {
+            &mut notifications,
+            |notifications, notify_bits| {
                 // Just record any notifications so they can be read back out.
-                *posted_bits |= notify_bits;
+                notifications.observe(notify_bits);
             },
-            |posted_bits, op, msg| -> Result<(), u32> {
-                // Every incoming message uses the same payload type: it's
-                // always u32 -> u32.
+            |notifications, op, msg| -> Result<(), u32> {
+                // The exceptions to the payload type below: an empty message
+                // gets an empty reply...
+                if op == AssistOp::JustReplyEmpty {
+                    let (_, caller) = msg.fixed::<(), ()>().ok_or(1u32)?;
+                    caller.reply(());
+                    return Ok(());
+                }
+
+                // ...or, for this op, our notification history.
+                if op == AssistOp::ReadNotificationHistory {
+                    let (_, caller) = msg
+                        .fixed::<(), [u32; NOTIFICATION_HISTORY_LEN]>()
+                        .ok_or(1u32)?;
+                    caller.reply(core::mem::take(&mut notifications.history));
+                    return Ok(());
+                }
+
+                // Every other incoming message uses the same payload type:
+                // it's always u32 -> u32.
                 let (msg, caller) = msg.fixed::<u32, u32>().ok_or(1u32)?;
 
                 match op {
@@ -278,7 +313,23 @@ fn main() -> ! {
                         panic!("unexpectedly survived {:?}", op);
                     }
                     AssistOp::ReadNotifications => {
-                        caller.reply(core::mem::replace(posted_bits, 0));
+                        caller
+                            .reply(core::mem::take(&mut notifications.posted));
+                    }
+                    AssistOp::WaitForNotification => {
+                        // Resume the caller, and then wait for only the
+                        // requested bits; anything else that's posted in the
+                        // meantime is left for our next open RECV.
+                        caller.reply(0);
+                        notifications.observe(sys_recv_notification(*msg));
+                    }
+                    AssistOp::SendBadBool => {
+                        caller.reply(0);
+                        let target = TaskId(*msg as u16);
+                        let operation = (*msg >> 16) as u16;
+                        let mut response = [0u8; 4];
+                        sys_send(target, operation, &[2], &mut response, &[]);
+                        panic!("unexpectedly survived {:?}", op);
                     }
                     _ => {
                         // Anything else should be fatal
@@ -25,7 +25,7 @@
 
 use hubris_num_tasks::NUM_TASKS;
 use ringbuf::{ringbuf, ringbuf_entry};
-use test_api::{AssistOp, RunnerOp, SuiteOp};
+use test_api::{AssistOp, RunnerOp, SuiteOp, NOTIFICATION_HISTORY_LEN};
 use userlib::{
     hl, kipc, task_slot, FaultInfo, FaultSource, Generation, IrqStatus,
     LeaseAttributes, ReplyFaultReason, SchedState, TaskId, TaskState,
@@ -76,8 +76,11 @@ task_config::task_config! {
 // Actual list of functions with their names.
 test_cases! {
     test_send,
+    test_send_empty,
     test_recv_reply,
     test_recv_reply_fault,
+    test_reply_fault_to_client,
+    test_reply_fault_unrecognized_op,
     #[cfg(any(armv7m, armv8m))]
     test_floating_point_lowregs,
     #[cfg(any(armv7m, armv8m))]
@@ -106,14 +109,19 @@ test_cases! {
     test_panic,
     test_restart,
     test_restart_taskgen,
+    test_fault_history,
     test_borrow_info,
     test_borrow_read,
     test_borrow_write,
     test_borrow_without_peer_waiting,
+    test_borrow_oob_read,
+    test_borrow_oob_write,
     test_supervisor_fault_notification,
     test_timer_advance,
     test_timer_notify,
     test_timer_notify_past,
+    test_timer_reprogram,
+    test_timer_cancel,
     test_task_config,
     test_task_status,
     test_task_fault_injection,
@@ -121,6 +129,7 @@ test_cases! {
     test_refresh_task_id_off_by_one,
     test_refresh_task_id_off_by_many,
     test_post,
+    test_post_while_blocked,
     test_idol_basic,
     test_idol_bool_arg,
     test_idol_bool_ret,
@@ -129,6 +138,7 @@ test_cases! {
     test_idol_ssmarshal,
     test_idol_ssmarshal_multiarg,
     test_idol_ssmarshal_multiarg_enum,
+    test_idol_bad_bool_arg,
     test_irq_notif,
     test_irq_status,
     #[cfg(feature = "fru-id-eeprom")]
@@ -154,6 +164,21 @@ fn test_send() {
     assert_eq!(response, !0xDEADBEEF);
 }
 
+/// Tests that a zero-length message with no room for a response makes it
+/// through SEND, RECV and REPLY intact.
+fn test_send_empty() {
+    let assist = assist_task_id();
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::JustReplyEmpty as u16,
+        &[],
+        &mut [],
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 0);
+}
+
 /// Tests that we can receive a message from the assistant and reply.
 fn test_recv_reply() {
     let assist = assist_task_id();
@@ -247,6 +272,142 @@ fn test_recv_reply_fault() {
     }
 }
 
+/// Tests the client-visible side of `REPLY_FAULT`: a client blocked in send to
+/// us is faulted in place, further reply-faults are ignored, and once it has
+/// been restarted its old `TaskId` is refused with a dead code carrying the
+/// new generation.
+fn test_reply_fault_to_client() {
+    let assist = assist_task_id();
+    let this_task = userlib::sys_refresh_task_id(TaskId::for_index_and_gen(
+        SUITE.get_task_index().into(),
+        Generation::default(),
+    ));
+
+    // Ask the assistant to send us a message, making it our client.
+    let challenge = 0xFA17_0B0Eu32;
+    let mut response = 0_u32;
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::SendBack as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    // Receive its message. The assistant is now waiting for our reply.
+    let rm = userlib::sys_recv_open(response.as_bytes_mut(), 0);
+    assert_eq!(rm.sender, assist);
+    assert_eq!(rm.operation, 42);
+    assert_eq!(response, challenge);
+    assert_eq!(
+        kipc::read_task_status(ASSIST.get_task_index().into()),
+        TaskState::Healthy(SchedState::InReply(this_task)),
+    );
+
+    // Reject the message. The client never gets a response code; it's
+    // faulted with us named as the server, and its blocked state preserved.
+    userlib::sys_reply_fault(assist, ReplyFaultReason::BadMessageContents);
+    let expected = TaskState::Faulted {
+        fault: FaultInfo::FromServer(
+            this_task,
+            ReplyFaultReason::BadMessageContents,
+        ),
+        original_state: SchedState::InReply(this_task),
+    };
+    assert_eq!(
+        kipc::read_task_status(ASSIST.get_task_index().into()),
+        expected
+    );
+
+    // The client is no longer waiting on us, so a second reply-fault must be
+    // ignored rather than overwriting the recorded fault.
+    userlib::sys_reply_fault(assist, ReplyFaultReason::AccessViolation);
+    assert_eq!(
+        kipc::read_task_status(ASSIST.get_task_index().into()),
+        expected
+    );
+
+    // Restart the assistant. Talking to it through the stale ID fails with a
+    // dead code that tells us the new generation...
+    restart_assistant();
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc & 0xffff_ff00, 0xffff_ff00);
+    assert_eq!(len, 0);
+    let new_gen = Generation::from((rc & 0xff) as u8);
+    assert_ne!(assist.generation(), new_gen);
+    assert_eq!(assist_task_id().generation(), new_gen);
+
+    // ...and the refreshed ID reaches a working assistant again.
+    let (rc, len) = userlib::sys_send(
+        assist_task_id(),
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, !challenge);
+}
+
+/// Tests the recommended handling of a bad operation code: acting as a server,
+/// we decode the assistant's message as one of our own operations, and fault
+/// it when that fails.
+fn test_reply_fault_unrecognized_op() {
+    let assist = assist_task_id();
+
+    // Ask the assistant to send us a message; it always uses operation 42,
+    // which isn't a `SuiteOp`.
+    let challenge = 0x0B0E_0B0Eu32;
+    let mut response = 0_u32;
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::SendBack as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    let rm = userlib::sys_recv_open(response.as_bytes_mut(), 0);
+    let err = match rm.decode_operation::<SuiteOp>() {
+        Ok(_) => panic!("operation {} unexpectedly decoded", rm.operation),
+        Err(e) => e,
+    };
+    assert_eq!(err.sender, assist);
+    assert_eq!(err.operation, 42);
+    err.reply_fault();
+
+    let status = kipc::read_task_status(ASSIST.get_task_index().into());
+    let this_task = userlib::sys_refresh_task_id(TaskId::for_index_and_gen(
+        SUITE.get_task_index().into(),
+        Generation::default(),
+    ));
+    match status {
+        TaskState::Faulted { fault, .. } => {
+            assert_eq!(
+                fault,
+                FaultInfo::FromServer(
+                    this_task,
+                    ReplyFaultReason::UndefinedOperation
+                )
+            );
+        }
+        _ => {
+            panic!("expected fault");
+        }
+    }
+}
+
 /// Helper routine to send a message to the assistant telling it to fault,
 /// and then verifying that the fault caused a state change into the `Faulted`
 /// state, returning the actual fault info.
@@ -485,6 +646,22 @@ fn test_panic() {
     restart_assistant();
 }
 
+/// Tests that the kernel's fault history for a task survives its restarts.
+fn test_fault_history() {
+    let index = ASSIST.get_task_index().into();
+    let before = kipc::read_task_fault_history(index);
+
+    for _ in 0..2 {
+        assert_eq!(test_fault(AssistOp::Panic, 0), FaultInfo::Panic);
+        restart_assistant();
+    }
+
+    let after = kipc::read_task_fault_history(index);
+    assert_eq!(after.restarts.wrapping_sub(before.restarts), 2);
+    assert_eq!(after.faults.wrapping_sub(before.faults), 2);
+    assert_eq!(after.last_fault, Some(FaultInfo::Panic));
+}
+
 fn test_idol_basic() {
     let idol = idol_handle();
     let r = idol.increment(1);
@@ -601,6 +778,37 @@ fn test_idol_ssmarshal_multiarg_enum() {
     assert_eq!(r, 14);
 }
 
+/// Tests that an Idol server faults a client whose message is the right size
+/// but holds an invalid value, here a `bool` argument of 2.
+fn test_idol_bad_bool_arg() {
+    let idol = IDOL.get_task_id();
+    let op = test_idol_api::IdolTestOperation::bool_not as u32;
+    let arg = u32::from(idol.0) | op << 16;
+
+    let mut response = 0_u32;
+    let (rc, len) = userlib::sys_send(
+        assist_task_id(),
+        AssistOp::SendBadBool as u16,
+        &arg.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    assert_eq!(
+        kipc::read_task_status(ASSIST.get_task_index().into()),
+        TaskState::Faulted {
+            fault: FaultInfo::FromServer(
+                idol,
+                ReplyFaultReason::BadMessageContents,
+            ),
+            original_state: SchedState::InReply(idol),
+        },
+    );
+    restart_assistant();
+}
+
 #[cfg(feature = "i2c-devices")]
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
@@ -1125,6 +1333,129 @@ fn test_borrow_without_peer_waiting() {
     assert_eq!(initial_id, new_id, "id should not change");
 }
 
+/// Tests borrow reads that extend past the end of a lease, which the kernel
+/// truncates to the end of the lease rather than failing or faulting.  (An
+/// *offset* past the end of the lease is a different matter, as it faults
+/// the borrower, so we don't test that here.)
+fn test_borrow_oob_read() {
+    let assist = assist_task_id();
+
+    // Ask the assistant to call us back with two particularly shaped loans
+    // (which are hardcoded in the assistant, not encoded here).
+    let mut response = 0_u32;
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::SendBackWithLoans as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    // Don't actually care about the response in this case
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+            let sender = caller.task_id();
+
+            // Borrow #1 is the read-only one, and is 5 bytes long.
+
+            // A read longer than the lease is cut short...
+            let mut dest = [0; 8];
+            let (rc, n) = userlib::sys_borrow_read(sender, 1, 0, &mut dest);
+            assert_eq!(rc, 0);
+            assert_eq!(n, 5);
+            assert_eq!(&dest[..n], b"hello");
+
+            // ...as is one that starts within the lease but runs off the end.
+            let (rc, n) = userlib::sys_borrow_read(sender, 1, 3, &mut dest);
+            assert_eq!(rc, 0);
+            assert_eq!(n, 2);
+            assert_eq!(&dest[..n], b"lo");
+
+            // An offset of exactly the length of the lease reads nothing.
+            let (rc, n) = userlib::sys_borrow_read(sender, 1, 5, &mut dest);
+            assert_eq!(rc, 0);
+            assert_eq!(n, 0);
+
+            // The hl wrappers turn short reads into failures.
+            assert!(caller.borrow(1).read_fully_at(3, &mut dest).is_none());
+
+            // Neither task should have been harmed in the making of this test.
+            assert_eq!(userlib::sys_refresh_task_id(sender), sender);
+
+            caller.reply(0);
+            Ok(())
+        },
+    );
+}
+
+/// Tests borrow writes that extend past the end of a lease, or to a lease
+/// that isn't writable.  The former are truncated to the end of the lease,
+/// and the latter return `DEFECT`; neither faults either task.
+fn test_borrow_oob_write() {
+    let assist = assist_task_id();
+
+    // Ask the assistant to call us back with two particularly shaped loans
+    // (which are hardcoded in the assistant, not encoded here).
+    let mut response = 0_u32;
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::SendBackWithLoans as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    // Don't actually care about the response in this case
+
+    hl::recv_without_notification(
+        response.as_bytes_mut(),
+        |_op: u32, msg| -> Result<(), u32> {
+            let (_msg, caller) = msg.fixed::<u32, u32>().unwrap();
+            let sender = caller.task_id();
+
+            // Borrow #0 is the read-write one, and is 16 bytes long.
+
+            // A write longer than the lease is cut short...
+            let src = [b'x'; 20];
+            let (rc, n) = userlib::sys_borrow_write(sender, 0, 0, &src);
+            assert_eq!(rc, 0);
+            assert_eq!(n, 16);
+
+            // ...as is one that starts within the lease but runs off the end.
+            let (rc, n) = userlib::sys_borrow_write(sender, 0, 12, b"llama");
+            assert_eq!(rc, 0);
+            assert_eq!(n, 4);
+
+            let mut readback = [0; 16];
+            caller.borrow(0).read_fully_at(0, &mut readback).unwrap();
+            assert_eq!(&readback, b"xxxxxxxxxxxxllam");
+
+            // An offset of exactly the length of the lease writes nothing.
+            let (rc, n) = userlib::sys_borrow_write(sender, 0, 16, b"llama");
+            assert_eq!(rc, 0);
+            assert_eq!(n, 0);
+
+            // The hl wrappers turn short writes into failures.
+            assert!(caller.borrow(0).write_fully_at(12, b"llama").is_none());
+
+            // Borrow #1 is read-only, so writing to it is an error.
+            let (rc, _n) = userlib::sys_borrow_write(sender, 1, 0, b"hi");
+            assert_eq!(rc, userlib::DEFECT);
+
+            // Neither task should have been harmed in the making of this test.
+            assert_eq!(userlib::sys_refresh_task_id(sender), sender);
+
+            caller.reply(0);
+            Ok(())
+        },
+    );
+}
+
 /// Tests that faults in tasks are reported to the supervisor.
 ///
 /// NOTE: this test depends on the supervisor fault mask, set in the test's
@@ -1222,6 +1553,70 @@ fn test_timer_notify_past() {
     assert_eq!(rm.lease_count, 0);
 }
 
+/// Tests that setting the timer again before it fires replaces the earlier
+/// deadline and notification, rather than adding a second timer.
+fn test_timer_reprogram() {
+    const FAR_NOTIFICATION: u32 = 1 << 16;
+    const NEAR_NOTIFICATION: u32 = 1 << 17;
+
+    let start_time = userlib::sys_get_timer().now;
+    // The far deadline is arbitrary, but should be long enough that we'd
+    // notice it having fired in place of the near one.
+    let far_deadline = start_time + 1000;
+    let near_deadline = start_time + 2;
+    userlib::sys_set_timer(Some(far_deadline), FAR_NOTIFICATION);
+    userlib::sys_set_timer(Some(near_deadline), NEAR_NOTIFICATION);
+
+    // Listen for both bits, so that we'd see the far one if it were (somehow)
+    // still armed.
+    let rm = userlib::sys_recv_closed(
+        &mut [],
+        FAR_NOTIFICATION | NEAR_NOTIFICATION,
+        TaskId::KERNEL,
+    )
+    .unwrap();
+
+    assert_eq!(rm.sender, TaskId::KERNEL);
+    assert_eq!(rm.operation, NEAR_NOTIFICATION);
+
+    let now = userlib::sys_get_timer().now;
+    assert!(now >= near_deadline);
+    assert!(now < far_deadline);
+}
+
+/// Tests that setting the timer with no deadline disarms a pending timer.
+fn test_timer_cancel() {
+    const TIMER_NOTIFICATION: u32 = 1 << 16;
+    const SELF_NOTIFICATION: u32 = 1 << 17;
+
+    let start_time = userlib::sys_get_timer().now;
+    let deadline = start_time + 2;
+    userlib::sys_set_timer(Some(deadline), TIMER_NOTIFICATION);
+    userlib::sys_set_timer(None, TIMER_NOTIFICATION);
+
+    // Wait until well past the deadline. Like `test_timer_advance`, this is
+    // bounded by time, not iterations.
+    while userlib::sys_get_timer().now < deadline + 2 {
+        // doot doot
+    }
+
+    // We can't poll for notifications without blocking, so post ourselves a
+    // different bit and receive with both: if the timer fired anyway, we'll
+    // see its bit alongside ours.
+    let post_rc = userlib::sys_post(SUITE.get_task_id(), SELF_NOTIFICATION);
+    assert_eq!(post_rc, 0);
+
+    let rm = userlib::sys_recv_closed(
+        &mut [],
+        TIMER_NOTIFICATION | SELF_NOTIFICATION,
+        TaskId::KERNEL,
+    )
+    .unwrap();
+
+    assert_eq!(rm.sender, TaskId::KERNEL);
+    assert_eq!(rm.operation, SELF_NOTIFICATION);
+}
+
 /// Tests that floating point registers are properly saved and restored
 #[cfg(any(armv7m, armv8m))]
 fn test_floating_point(highregs: bool) {
@@ -1409,6 +1804,7 @@ fn test_post() {
     );
     assert_eq!(rc, 0);
     assert_eq!(len, 4);
+    read_assist_notification_history();
 
     // Now, post some bits.
     const ARBITRARY_MASK: u32 = 0xAA00006A;
@@ -1428,6 +1824,88 @@ fn test_post() {
     assert_eq!(len, 4);
 
     assert_eq!(response, ARBITRARY_MASK);
+
+    // They should have arrived all at once.
+    let history = read_assist_notification_history();
+    assert_eq!(history[NOTIFICATION_HISTORY_LEN - 1], ARBITRARY_MASK);
+    assert!(history[..NOTIFICATION_HISTORY_LEN - 1]
+        .iter()
+        .all(|&m| m == 0));
+}
+
+/// Tests that notification bits posted to a task while it's blocked in a
+/// closed RECV that doesn't accept them are latched, and delivered by its next
+/// open RECV.
+///
+/// The interleaving is forced by priorities: the assistant is higher priority
+/// than we are, so when we ask it to `WaitForNotification`, it replies and
+/// then runs until it blocks in a closed RECV from the kernel (accepting only
+/// `WAKE_MASK`) before we resume.  We then post bits outside of `WAKE_MASK`,
+/// which must not wake it, followed by `WAKE_MASK`, which does -- at which
+/// point the assistant returns to its open RECV loop, picks up the latched
+/// bits, and blocks again.  Finally, we read back what it saw.
+fn test_post_while_blocked() {
+    let assist = assist_task_id();
+
+    let mut response = 0_u32;
+
+    // Do an initial call to drain any previously posted bits.
+    let unused = 0u32;
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::ReadNotifications as u16,
+        unused.as_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    read_assist_notification_history();
+
+    const WAKE_MASK: u32 = 1 << 0;
+    const ARBITRARY_MASK: u32 = 0xAA00006A;
+    assert_eq!(WAKE_MASK & ARBITRARY_MASK, 0);
+
+    // Send the assistant off into its closed RECV.
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::WaitForNotification as u16,
+        WAKE_MASK.as_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    // Post bits it isn't waiting for; this must not wake it...
+    let post_rc = userlib::sys_post(assist, ARBITRARY_MASK);
+    assert_eq!(post_rc, 0);
+
+    // ...but this will.
+    let post_rc = userlib::sys_post(assist, WAKE_MASK);
+    assert_eq!(post_rc, 0);
+
+    // And read them back: we should see both the bits that woke it, and the
+    // bits that were latched while it was blocked.
+    let (rc, len) = userlib::sys_send(
+        assist,
+        AssistOp::ReadNotifications as u16,
+        unused.as_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+
+    assert_eq!(response, ARBITRARY_MASK | WAKE_MASK);
+
+    // And in that order: first the bits that woke its closed RECV, and only
+    // then the latched ones.
+    let history = read_assist_notification_history();
+    assert_eq!(
+        history[NOTIFICATION_HISTORY_LEN - 2..],
+        [WAKE_MASK, ARBITRARY_MASK]
+    );
 }
 
 /// Tests that a task is notified on receipt of a hardware interrupt.
@@ -1549,6 +2027,23 @@ fn restart_assistant() {
     kipc::restart_task(ASSIST.get_task_index().into(), true);
 }
 
+/// Asks the assistant for (and clears) the notification masks it has most
+/// recently observed, oldest first.
+fn read_assist_notification_history() -> [u32; NOTIFICATION_HISTORY_LEN] {
+    let mut response = [0u32; NOTIFICATION_HISTORY_LEN];
+    let op = AssistOp::ReadNotificationHistory as u16;
+    let (rc, len) = userlib::sys_send(
+        assist_task_id(),
+        op,
+        &[],
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, core::mem::size_of_val(&response));
+    response
+}
+
 /// Contacts the runner task to read (and clear) its accumulated set of
 /// notifications.
 fn read_runner_notifications() -> u32 {